@@ -0,0 +1,50 @@
+use crate::bsvo::{decode_bsvo, encode_bsvo, BsvoHeader, BSVO_HEADER_SIZE};
+use crate::bvox::{decode_bvox, encode_bvox, BvoxHeader, BVOX_HEADER_SIZE};
+use crate::svo::SVO;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// async counterparts of the file readers and writers. the data is encoded and decoded in memory with the
+// same functions as the sync code, only the transfer awaits. the writers flush but don't shut the writer down.
+// encoding and decoding run inline on the calling task and block its executor thread for as long as the codec
+// takes, large chunks or deflate / lz4 codecs should be encoded or decoded inside tokio::task::spawn_blocking
+// with encode_bvox / decode_bvox / encode_bsvo / decode_bsvo and only the bytes passed through these functions
+
+pub async fn write_bvox_async<W: AsyncWrite + Unpin>(writer: &mut W, chunk_data: &[Vec<u8>], header: BvoxHeader) -> io::Result<()> {
+    let data = encode_bvox(chunk_data, header)?;
+    writer.write_all(&data).await?;
+    writer.flush().await
+}
+
+// reads until the end of the stream
+pub async fn read_bvox_async<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<(BvoxHeader, Vec<Vec<u8>>)> {
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer).await?;
+    decode_bvox(&buffer)
+}
+
+// only consumes the header from the stream
+pub async fn get_bvox_header_async<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<BvoxHeader> {
+    let mut buffer = [0u8; BVOX_HEADER_SIZE];
+    reader.read_exact(&mut buffer).await?;
+    BvoxHeader::from_bytes(&buffer)
+}
+
+pub async fn write_bsvo_async<W: AsyncWrite + Unpin>(writer: &mut W, svo: &SVO, header: BsvoHeader) -> io::Result<()> {
+    let data = encode_bsvo(svo, header);
+    writer.write_all(&data).await?;
+    writer.flush().await
+}
+
+pub async fn read_bsvo_async<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<(BsvoHeader, SVO)> {
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer).await?;
+    let (header, view) = decode_bsvo(&buffer)?;
+    Ok((header, view.to_svo()))
+}
+
+pub async fn get_bsvo_header_async<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<BsvoHeader> {
+    let mut buffer = [0u8; BSVO_HEADER_SIZE];
+    reader.read_exact(&mut buffer).await?;
+    BsvoHeader::from_bytes(&buffer)
+}
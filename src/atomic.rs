@@ -0,0 +1,80 @@
+use std::{fs::{self, File, OpenOptions}, io::{self, Write}, path::Path, process, sync::atomic::{AtomicU64, Ordering}};
+
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// writes data to a sibling temp file, syncs it and renames it over filename, so a crash leaves
+// either the old or the new file. with keep_backup the replaced file stays behind as filename.bak
+pub fn write_atomic(filename: &str, data: &[u8], keep_backup: bool) -> io::Result<()> {
+    replace_atomic(filename, keep_backup, |file| file.write_all(data))
+}
+
+// like write_atomic for contents that are streamed into the temp file instead of held in memory
+pub fn replace_atomic<F: FnOnce(&mut File) -> io::Result<()>>(filename: &str, keep_backup: bool, write: F) -> io::Result<()> {
+    let path = Path::new(filename);
+    let (temp, mut file) = create_temp(filename)?;
+
+    let result = write(&mut file).and_then(|_| file.sync_all());
+    drop(file);
+
+    let result = result.and_then(|_| {
+        if keep_backup && path.exists() {
+            backup_file(filename)?;
+        }
+        fs::rename(&temp, path)
+    });
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    sync_parent(path)
+}
+
+// the temp name is unique per process and call, so concurrent saves of one file don't share a temp file
+// and create_new never clobbers an existing file
+fn create_temp(filename: &str) -> io::Result<(String, File)> {
+    loop {
+        let temp = format!("{}.{}.{}.tmp", filename, process::id(), TEMP_COUNTER.fetch_add(1, Ordering::Relaxed));
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+pub fn backup_path(filename: &str) -> String {
+    format!("{}.bak", filename)
+}
+
+// the original stays in place until the rename, so the backup is a link or a copy rather than a move
+fn backup_file(filename: &str) -> io::Result<()> {
+    let backup = backup_path(filename);
+
+    match fs::remove_file(&backup) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+
+    if fs::hard_link(filename, &backup).is_err() {
+        fs::copy(filename, &backup)?;
+    }
+
+    Ok(())
+}
+
+// renames and removals are only durable once the directory entry is synced
+#[cfg(unix)]
+pub fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+#[cfg(not(unix))]
+pub fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
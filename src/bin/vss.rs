@@ -0,0 +1,471 @@
+use glam::{IVec3, UVec3, Vec3};
+use std::{collections::BTreeMap, env, error::Error, fs, path::Path, process};
+use vss_rs::binvox::{binvox_chunk_to_svo, read_binvox, write_binvox, BinvoxHeader};
+use vss_rs::bsvo::{read_bsvo, write_bsvo, BsvoHeader, NODE_SIZE};
+use vss_rs::bvox::{read_bvox, split_bvox, write_bvox, BvoxHeader};
+use vss_rs::codec::Codec;
+use vss_rs::export::{write_mesh, ExportFormat, Topology};
+use vss_rs::magica::{read_magica_vox, write_magica_vox, MagicaScene};
+use vss_rs::mesh::block_mesh;
+use vss_rs::palette::Palette;
+use vss_rs::pointcloud::{read_point_cloud, voxelize_points};
+use vss_rs::schematic::{read_schematic, BlockMapping};
+use vss_rs::svo::SVO;
+use vss_rs::vdb::{read_vdb, write_vdb, VdbGrid, VdbValueType};
+use vss_rs::verify::verify_file;
+use vss_rs::vox::{morton_decode_3d_grid, morton_encode_3d_grid, pos_to_index, DEFAULT_VOX_MAT};
+
+const USAGE: &str = "usage: vss <command> [options]
+
+commands:
+  info <file>                        print the header, chunk or node counts and compression ratio
+  convert <input> <output>           convert between bvox, bsvo, binvox, vox, vdb, schem, nbt, ply, xyz, obj and glb
+  validate <file> [--repair <out>]   check checksums and structure, --repair writes a repaired bsvo
+  stats <file>                       print fill ratio and material counts
+  extract-chunk <input.bvox> <index> <output>
+
+options:
+  --chunk <index>    chunk to read from bvox, vox and schematic inputs (default 0)
+  --res <res>        chunk resolution for vox, schematic and point cloud inputs (default 256)
+  --codec <name>     bvox codec: none, rle, rle-varint, lz4, deflate, bit-packed, occupancy, auto (default auto)
+  --morton           write bvox chunks in morton order
+  --rle              run length encode bsvo nodes
+  --pointerless      write pointerless bsvo nodes
+  --checksum         write bvox and bsvo checksums";
+
+struct Options {
+    positional: Vec<String>,
+    chunk: usize,
+    res: u32,
+    codec: Codec,
+    morton: bool,
+    rle: bool,
+    pointerless: bool,
+    checksum: bool,
+    repair: Option<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut options = Self {
+            positional: Vec::new(),
+            chunk: 0,
+            res: 256,
+            codec: Codec::Auto,
+            morton: false,
+            rle: false,
+            pointerless: false,
+            checksum: false,
+            repair: None,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("missing value for {arg}."));
+
+            match arg.as_str() {
+                "--chunk" => options.chunk = value()?.parse()?,
+                "--res" => options.res = value()?.parse()?,
+                "--codec" => options.codec = Codec::from_name(value()?)?,
+                "--repair" => options.repair = Some(value()?.clone()),
+                "--morton" => options.morton = true,
+                "--rle" => options.rle = true,
+                "--pointerless" => options.pointerless = true,
+                "--checksum" => options.checksum = true,
+                _ if arg.starts_with("--") => return Err(format!("unknown option {arg}.").into()),
+                _ => options.positional.push(arg.clone()),
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn arg(&self, index: usize, name: &str) -> Result<&str, Box<dyn Error>> {
+        self.positional.get(index).map(|s| s.as_str()).ok_or_else(|| format!("missing {name}.").into())
+    }
+}
+
+fn extension(filename: &str) -> String {
+    Path::new(filename).extension().and_then(|ext| ext.to_str()).unwrap_or("").to_lowercase()
+}
+
+// a dense bvox chunk holds at most 1024^3 voxels since chunk_size is a u32
+const MAX_VOLUME_DEPTH: u8 = 10;
+
+// a single linear chunk, the common ground between all formats
+struct Volume {
+    res: u32,
+    grid: Vec<u8>,
+}
+
+impl Volume {
+    fn from_svo(svo: &SVO) -> Result<Self, Box<dyn Error>> {
+        let len = 1usize.checked_shl(3 * svo.depth as u32).filter(|_| svo.depth <= MAX_VOLUME_DEPTH);
+        let len = len.ok_or_else(|| format!("svo depth {} is too large for a dense volume, the limit is {MAX_VOLUME_DEPTH}.", svo.depth))?;
+
+        let res = 1u32 << svo.depth;
+        let mut grid = vec![0u8; len];
+        svo.for_each_leaf(|pos, mat| grid[pos_to_index(pos.x, pos.y, pos.z, res) as usize] = mat.min(u8::MAX as u32) as u8);
+        Ok(Self { res, grid })
+    }
+
+    fn from_chunks(chunks: Vec<(IVec3, Vec<u8>)>, res: u32, index: usize) -> Result<Self, Box<dyn Error>> {
+        let count = chunks.len();
+        let (_, grid) = chunks.into_iter().nth(index).ok_or_else(|| format!("chunk {index} out of range, the input has {count} chunks."))?;
+        Ok(Self { res, grid })
+    }
+
+    fn size(&self) -> u32 {
+        self.res * self.res * self.res
+    }
+}
+
+fn bvox_volume(header: &BvoxHeader, chunk: Vec<u8>) -> Volume {
+    let grid = if header.morton_encoded {
+        let mut grid = vec![0u8; chunk.len()];
+        morton_decode_3d_grid(&chunk, header.chunk_res, header.chunk_size, &mut grid);
+        grid
+    } else {
+        chunk
+    };
+
+    Volume { res: header.chunk_res, grid }
+}
+
+fn load_bvox_chunk(filename: &str, index: usize) -> Result<Volume, Box<dyn Error>> {
+    let (header, chunks) = read_bvox(filename)?;
+    let count = chunks.len();
+    let chunk = chunks.into_iter().nth(index).ok_or_else(|| format!("chunk {index} out of range, the file has {count} chunks."))?;
+
+    Ok(bvox_volume(&header, chunk))
+}
+
+// the svo queries follow child pointers unchecked, so the tree is validated like the validate command does first
+fn read_valid_bsvo(filename: &str) -> Result<(BsvoHeader, SVO), Box<dyn Error>> {
+    let (header, svo) = read_bsvo(filename)?;
+    let report = svo.validate();
+    if !report.is_valid() {
+        return Err(format!("{} issues found: {:?}", report.issues.len(), report.issues).into());
+    }
+
+    Ok((header, svo))
+}
+
+fn load(filename: &str, options: &Options) -> Result<Volume, Box<dyn Error>> {
+    match extension(filename).as_str() {
+        "bvox" => load_bvox_chunk(filename, options.chunk),
+        "bsvo" => Volume::from_svo(&read_valid_bsvo(filename)?.1),
+        "binvox" => {
+            let (header, grid) = read_binvox(filename)?;
+            Ok(Volume { res: header.chunk_res(), grid })
+        }
+        "vox" => Volume::from_chunks(read_magica_vox(filename)?.to_chunks(options.res)?, options.res, options.chunk),
+        "schem" | "schematic" | "nbt" => {
+            let schematic = read_schematic(filename, &BlockMapping::new(DEFAULT_VOX_MAT))?;
+            Volume::from_chunks(schematic.to_chunks(IVec3::ZERO, options.res, false)?, options.res, options.chunk)
+        }
+        "ply" | "xyz" => {
+            let points = voxelize_points(&read_point_cloud(filename)?, options.res, None)?;
+            Ok(Volume { res: points.grid_res, grid: points.grid })
+        }
+        "vdb" => {
+            let grid = read_vdb(filename)?.into_iter().next().ok_or("vdb file has no grids.")?;
+            let min = grid.voxels.keys().fold(IVec3::MAX, |m, &p| m.min(p));
+            let max = grid.voxels.keys().fold(IVec3::MIN, |m, &p| m.max(p));
+            let span = if grid.voxels.is_empty() { 0 } else { (max.as_i64vec3() - min.as_i64vec3()).max_element() as u64 };
+            let res = (span + 1).next_power_of_two();
+            if res > 1 << MAX_VOLUME_DEPTH {
+                return Err(format!("vdb grid spans {} voxels, the limit is {}.", span + 1, 1u32 << MAX_VOLUME_DEPTH).into());
+            }
+            Ok(Volume { res: res as u32, grid: grid.to_chunk(res as u32, min) })
+        }
+        ext => Err(format!("unsupported input format {ext:?}.").into()),
+    }
+}
+
+fn save(volume: &Volume, filename: &str, options: &Options) -> Result<(), Box<dyn Error>> {
+    match extension(filename).as_str() {
+        "bvox" => {
+            let chunk = if options.morton {
+                let mut morton_grid = vec![0u8; volume.grid.len()];
+                morton_encode_3d_grid(&volume.grid, volume.res, volume.size(), &mut morton_grid);
+                morton_grid
+            } else {
+                volume.grid.clone()
+            };
+
+            let header = BvoxHeader::new(volume.res, volume.size(), false, options.morton)
+                .with_codec(options.codec)
+                .with_checksums(options.checksum, options.checksum);
+            write_bvox(filename, &[chunk], header)?;
+        }
+        "bsvo" => {
+            let svo = binvox_chunk_to_svo(&volume.grid, volume.res);
+            let header = BsvoHeader::new(svo.depth, svo.root_span, options.rle)
+                .with_pointerless(options.pointerless)
+                .with_checksum(options.checksum);
+            write_bsvo(filename, &svo, header)?;
+        }
+        "binvox" => write_binvox(filename, &volume.grid, volume.res, BinvoxHeader::new(UVec3::splat(volume.res), Vec3::ZERO, 1.0))?,
+        "vox" => write_magica_vox(filename, &MagicaScene::from_chunks(&[(IVec3::ZERO, volume.grid.clone())], volume.res, Palette::default())?)?,
+        "vdb" => write_vdb(filename, &[VdbGrid::from_chunk("density", &volume.grid, volume.res, IVec3::ZERO, VdbValueType::Float)])?,
+        ext @ ("obj" | "ply" | "glb") => {
+            let format = match ext {
+                "obj" => ExportFormat::Obj,
+                "ply" => ExportFormat::Ply,
+                _ => ExportFormat::Glb,
+            };
+            write_mesh(filename, &block_mesh(&volume.grid, volume.res, &Palette::default()), Topology::Triangles, format)?;
+        }
+        ext => return Err(format!("unsupported output format {ext:?}.").into()),
+    }
+
+    Ok(())
+}
+
+fn ratio(raw: usize, stored: usize) -> String {
+    if stored == 0 {
+        return "-".to_string();
+    }
+    format!("{:.2}x", raw as f64 / stored as f64)
+}
+
+fn info(filename: &str, options: &Options) -> Result<(), Box<dyn Error>> {
+    let file_size = fs::metadata(filename)?.len() as usize;
+    println!("file: {filename} ({file_size} bytes)");
+
+    match extension(filename).as_str() {
+        "bvox" => {
+            let data = fs::read(filename)?;
+            let (header, records) = split_bvox(&data)?;
+            println!("{header:#?}");
+
+            let mut codecs: BTreeMap<&str, usize> = BTreeMap::new();
+            records.iter().for_each(|(codec, _)| *codecs.entry(codec.name()).or_default() += 1);
+
+            println!("chunks: {}", records.len());
+            for (name, count) in codecs {
+                println!("  {name}: {count}");
+            }
+
+            let raw = records.len() * header.chunk_size as usize;
+            println!("compression ratio: {}", ratio(raw, file_size));
+        }
+        "bsvo" => {
+            let (header, svo) = read_valid_bsvo(filename)?;
+            println!("{header:#?}");
+            println!("nodes: {}", svo.nodes.len());
+            println!("leaves: {}", svo.count_leaf_nodes());
+            println!("depth: {}", svo.depth);
+            println!("root span: {}", svo.root_span);
+            println!("compression ratio: {}", ratio(svo.nodes.len() * NODE_SIZE, file_size));
+        }
+        _ => {
+            let volume = load(filename, options)?;
+            println!("res: {}", volume.res);
+            println!("voxels: {}", volume.grid.iter().filter(|&&v| v > 0).count());
+            println!("compression ratio: {}", ratio(volume.grid.len(), file_size));
+        }
+    }
+
+    Ok(())
+}
+
+fn validate(filename: &str, options: &Options) -> Result<(), Box<dyn Error>> {
+    verify_file(filename)?;
+    println!("checksums and structure: ok");
+
+    match extension(filename).as_str() {
+        "bvox" => {
+            let (_, chunks) = read_bvox(filename)?;
+            println!("decoded {} chunks: ok", chunks.len());
+        }
+        _ => {
+            let (header, mut svo) = read_bsvo(filename)?;
+            let report = svo.validate();
+            println!("reachable nodes: {}", report.reachable_nodes);
+            println!("leaves: {}", report.leaves);
+            for issue in &report.issues {
+                println!("  {issue:?}");
+            }
+
+            if let Some(repair) = &options.repair {
+                svo.repair();
+                write_bsvo(repair, &svo, header)?;
+                println!("repaired svo written to {repair}");
+            }
+
+            if !report.is_valid() {
+                return Err(format!("{} issues found.", report.issues.len()).into());
+            }
+        }
+    }
+
+    println!("valid");
+    Ok(())
+}
+
+fn print_volume_stats(volume: &Volume) {
+    let mut counts = [0u64; 256];
+    volume.grid.iter().for_each(|&v| counts[v as usize] += 1);
+    print_material_stats(volume.res, volume.grid.len() as u128, &counts);
+}
+
+// counts[0] is ignored, empty voxels are whatever the materials don't fill
+fn print_material_stats(res: u32, total: u128, counts: &[u64; 256]) {
+    let filled: u64 = counts[1..].iter().sum();
+
+    println!("res: {res}");
+    println!("filled: {filled} of {total} ({:.2}%)", 100.0 * filled as f64 / total.max(1) as f64);
+
+    let materials: Vec<_> = (1..256).filter(|&m| counts[m] > 0).collect();
+    println!("materials: {}", materials.len());
+    for m in materials {
+        println!("  {m}: {}", counts[m]);
+    }
+}
+
+fn stats(filename: &str, options: &Options) -> Result<(), Box<dyn Error>> {
+    match extension(filename).as_str() {
+        "bvox" => {
+            let (header, chunks) = read_bvox(filename)?;
+            println!("chunks: {} of {}^3", chunks.len(), header.chunk_res);
+
+            for (index, chunk) in chunks.into_iter().enumerate() {
+                println!("chunk {index}:");
+                print_volume_stats(&bvox_volume(&header, chunk));
+            }
+        }
+        "bsvo" => {
+            let (_, svo) = read_valid_bsvo(filename)?;
+            let mut per_depth = vec![0usize; svo.depth as usize + 1];
+            svo.for_each_node(|_, depth, _| per_depth[depth as usize] += 1);
+
+            println!("nodes per depth:");
+            for (depth, count) in per_depth.iter().enumerate() {
+                println!("  {depth}: {count}");
+            }
+
+            // counted from the leaves, a dense grid of a deep octree doesn't fit in memory
+            let mut counts = [0u64; 256];
+            svo.for_each_leaf(|_, mat| counts[mat.min(u8::MAX as u32) as usize] += 1);
+            let res = 1u32.checked_shl(svo.depth as u32).ok_or_else(|| format!("svo depth {} is too large.", svo.depth))?;
+            print_material_stats(res, (res as u128).pow(3), &counts);
+        }
+        _ => print_volume_stats(&load(filename, options)?),
+    }
+
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let command = args.first().ok_or(USAGE)?;
+    let options = Options::parse(&args[1..])?;
+
+    match command.as_str() {
+        "info" => info(options.arg(0, "file")?, &options),
+        "convert" => save(&load(options.arg(0, "input")?, &options)?, options.arg(1, "output")?, &options),
+        "validate" => validate(options.arg(0, "file")?, &options),
+        "stats" => stats(options.arg(0, "file")?, &options),
+        "extract-chunk" => {
+            let index = options.arg(1, "chunk index")?.parse()?;
+            save(&load_bvox_chunk(options.arg(0, "input")?, index)?, options.arg(2, "output")?, &options)
+        }
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => Err(format!("unknown command {command}.\n{USAGE}").into()),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if let Err(err) = run(&args) {
+        eprintln!("error: {err}");
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vss_rs::bsvo::write_empty_bsvo;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn test_grid(res: u32, seed: u32) -> Vec<u8> {
+        (0..res * res * res).map(|i| if (i * 7 + seed) % 5 < 2 { (1 + (i + seed) % 3) as u8 } else { 0 }).collect()
+    }
+
+    #[test]
+    fn convert_round_trip() -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all("output")?;
+        let options = Options::parse(&[])?;
+        let volume = Volume { res: 16, grid: test_grid(16, 0) };
+        save(&volume, "output/vss_convert.bvox", &options)?;
+
+        run(&args(&["convert", "output/vss_convert.bvox", "output/vss_convert.bsvo", "--rle"]))?;
+        run(&args(&["convert", "output/vss_convert.bsvo", "output/vss_convert_back.bvox", "--morton", "--checksum"]))?;
+
+        let back = load("output/vss_convert_back.bvox", &options)?;
+        assert_eq!(back.res, volume.res);
+        assert_eq!(back.grid, volume.grid);
+
+        // dense volumes refuse octrees whose grid doesn't fit a bvox chunk
+        assert!(Volume::from_svo(&SVO::new(MAX_VOLUME_DEPTH + 1)).is_err());
+        assert_eq!(Volume::from_svo(&SVO::new(4))?.grid.len(), 16 * 16 * 16);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_inputs() -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all("output")?;
+        let options = Options::parse(&[])?;
+
+        // an empty bsvo has no root node and a corrupt one points past its nodes
+        write_empty_bsvo("output/vss_empty.bsvo", BsvoHeader::new(3, 8.0, false))?;
+        let corrupt = SVO { nodes: vec![0xFF00_0100], depth: 3, root_span: 8.0 };
+        write_bsvo("output/vss_corrupt.bsvo", &corrupt, BsvoHeader::new(3, 8.0, false))?;
+        for filename in ["output/vss_empty.bsvo", "output/vss_corrupt.bsvo"] {
+            assert!(info(filename, &options).is_err());
+            assert!(stats(filename, &options).is_err());
+            assert!(load(filename, &options).is_err());
+        }
+
+        // vdb grids are bounded like octrees
+        let mut grid = VdbGrid::new("density", VdbValueType::Float, Default::default());
+        grid.set(IVec3::new(-3, 0, 0), 1.0);
+        grid.set(IVec3::new(4, 2, 1), 2.0);
+        write_vdb("output/vss_small.vdb", std::slice::from_ref(&grid))?;
+        assert_eq!(load("output/vss_small.vdb", &options)?.res, 8);
+
+        grid.set(IVec3::MAX, 1.0);
+        grid.set(IVec3::MIN, 1.0);
+        write_vdb("output/vss_far.vdb", &[grid])?;
+        assert!(load("output/vss_far.vdb", &options).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn extract_chunk() -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all("output")?;
+        let chunks: Vec<Vec<u8>> = (0..3).map(|seed| test_grid(8, seed)).collect();
+        write_bvox("output/vss_extract.bvox", &chunks, BvoxHeader::new(8, 512, false, false).with_codec(Codec::Auto))?;
+
+        run(&args(&["extract-chunk", "output/vss_extract.bvox", "1", "output/vss_extract_1.bvox"]))?;
+        let extracted = load("output/vss_extract_1.bvox", &Options::parse(&[])?)?;
+        assert_eq!(extracted.res, 8);
+        assert_eq!(extracted.grid, chunks[1]);
+
+        assert!(load_bvox_chunk("output/vss_extract.bvox", 3).is_err());
+        assert!(run(&args(&["extract-chunk", "output/vss_extract.bvox", "3", "output/vss_extract_3.bvox"])).is_err());
+
+        Ok(())
+    }
+}
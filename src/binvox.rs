@@ -0,0 +1,232 @@
+use crate::atomic::write_atomic;
+use crate::rle::{run_length_decode, run_length_encode};
+use crate::svo::SVO;
+use crate::vox::{morton_encode_3d_grid, pos_to_index, DEFAULT_VOX_MAT};
+use glam::{UVec3, Vec3};
+use std::{fs::File, io, io::{BufReader, Read}, path::Path};
+
+pub const BINVOX_VERSION: u32 = 1;
+// models are read into a cubic chunk of the largest dimension, this bounds it to 1024^3 voxels
+pub const MAX_BINVOX_RES: u32 = 1024;
+
+// dims are the x, y and z extents in file order, the voxels run y fastest, then z, then x
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BinvoxHeader {
+    pub dims: UVec3,
+    pub translate: Vec3,
+    pub scale: f32,
+}
+
+impl BinvoxHeader {
+    pub fn new(dims: UVec3, translate: Vec3, scale: f32) -> Self {
+        Self { dims, translate, scale }
+    }
+
+    // smallest cubic chunk the model fits into
+    pub fn chunk_res(&self) -> u32 {
+        self.dims.max_element()
+    }
+
+    // fails for zero dimensions and ones whose product doesn't fit a usize
+    pub fn voxel_count(&self) -> io::Result<usize> {
+        let [x, y, z] = self.dims.to_array().map(|d| d as usize);
+        x.checked_mul(y)
+            .and_then(|n| n.checked_mul(z))
+            .filter(|&n| n > 0)
+            .ok_or_else(|| invalid_data("binvox dimensions are zero or too large."))
+    }
+
+    // voxels in the cubic chunk of chunk_res
+    pub fn chunk_size(&self) -> io::Result<usize> {
+        cubic_size(self.chunk_res()).ok_or_else(|| invalid_data("binvox dimensions are too large."))
+    }
+
+    pub fn index(&self, pos: UVec3) -> usize {
+        let (y, z) = (self.dims.y as usize, self.dims.z as usize);
+        pos.x as usize * z * y + pos.z as usize * y + pos.y as usize
+    }
+}
+
+impl Default for BinvoxHeader {
+    fn default() -> Self {
+        Self::new(UVec3::splat(32), Vec3::ZERO, 1.0)
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn cubic_size(res: u32) -> Option<usize> {
+    let res = res as usize;
+    res.checked_mul(res).and_then(|n| n.checked_mul(res))
+}
+
+fn parse_values<T: std::str::FromStr>(values: &[&str], count: usize) -> io::Result<Vec<T>> {
+    if values.len() != count {
+        return Err(invalid_data("invalid binvox header line."));
+    }
+
+    values.iter().map(|v| v.parse().map_err(|_| invalid_data("invalid binvox header value."))).collect()
+}
+
+// returns the header and a linear chunk with a resolution of header.chunk_res()
+pub fn parse_binvox(data: &[u8]) -> io::Result<(BinvoxHeader, Vec<u8>)> {
+    let mut header = BinvoxHeader::default();
+    let mut has_dims = false;
+    let mut offset = 0;
+
+    loop {
+        let end = data[offset..].iter().position(|&b| b == b'\n').ok_or_else(|| invalid_data("unexpected end of binvox header."))?;
+        let line = std::str::from_utf8(&data[offset..offset + end]).map_err(|_| invalid_data("invalid binvox header."))?;
+        offset += end + 1;
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.first().copied() {
+            Some("#binvox") => {
+                let version: Vec<u32> = parse_values(&parts[1..], 1)?;
+                if version[0] > BINVOX_VERSION {
+                    return Err(invalid_data("unsupported binvox version."));
+                }
+            }
+            Some("dim") => {
+                let dims: Vec<u32> = parse_values(&parts[1..], 3)?;
+                header.dims = UVec3::new(dims[0], dims[1], dims[2]);
+                has_dims = true;
+            }
+            Some("translate") => {
+                let t: Vec<f32> = parse_values(&parts[1..], 3)?;
+                header.translate = Vec3::new(t[0], t[1], t[2]);
+            }
+            Some("scale") => header.scale = parse_values::<f32>(&parts[1..], 1)?[0],
+            Some("data") => break,
+            _ => return Err(invalid_data("unknown binvox header line.")),
+        }
+    }
+
+    if !has_dims {
+        return Err(invalid_data("binvox file without dimensions."));
+    }
+    if header.chunk_res() > MAX_BINVOX_RES {
+        return Err(invalid_data("binvox dimensions are too large."));
+    }
+    let voxel_count = header.voxel_count()?;
+
+    // the data is the same (value, count) pair encoding as rle.rs, the runs are summed before anything is decoded
+    let runs = &data[offset..];
+    let run_total: usize = runs.chunks(2).map(|run| run.get(1).map_or(0, |&count| count as usize)).sum();
+    if run_total != voxel_count {
+        return Err(invalid_data("binvox data does not match the dimensions."));
+    }
+    let voxels = run_length_decode(runs)?;
+
+    let chunk_res = header.chunk_res();
+    let mut chunk = vec![0u8; header.chunk_size()?];
+
+    for x in 0..header.dims.x {
+        for z in 0..header.dims.z {
+            for y in 0..header.dims.y {
+                if voxels[header.index(UVec3::new(x, y, z))] > 0 {
+                    chunk[pos_to_index(x, y, z, chunk_res) as usize] = DEFAULT_VOX_MAT;
+                }
+            }
+        }
+    }
+
+    Ok((header, chunk))
+}
+
+pub fn read_binvox(filename: &str) -> io::Result<(BinvoxHeader, Vec<u8>)> {
+    let path = Path::new(filename);
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+
+    parse_binvox(&buffer)
+}
+
+// binvox is binary, every non zero voxel of the linear chunk is written as filled
+pub fn encode_binvox(chunk: &[u8], chunk_res: u32, header: BinvoxHeader) -> io::Result<Vec<u8>> {
+    if cubic_size(chunk_res) != Some(chunk.len()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk is not the given size."));
+    }
+
+    if header.dims.max_element() > chunk_res || header.dims.min_element() == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "binvox dimensions don't fit the chunk."));
+    }
+
+    let mut voxels = vec![0u8; header.voxel_count()?];
+    for x in 0..header.dims.x {
+        for z in 0..header.dims.z {
+            for y in 0..header.dims.y {
+                if chunk[pos_to_index(x, y, z, chunk_res) as usize] > 0 {
+                    voxels[header.index(UVec3::new(x, y, z))] = 1;
+                }
+            }
+        }
+    }
+
+    let (d, t) = (header.dims, header.translate);
+    let mut out = format!(
+        "#binvox {}\ndim {} {} {}\ntranslate {} {} {}\nscale {}\ndata\n",
+        BINVOX_VERSION, d.x, d.y, d.z, t.x, t.y, t.z, header.scale
+    ).into_bytes();
+    out.extend(run_length_encode(&voxels));
+
+    Ok(out)
+}
+
+pub fn write_binvox(filename: &str, chunk: &[u8], chunk_res: u32, header: BinvoxHeader) -> io::Result<()> {
+    let data = encode_binvox(chunk, chunk_res, header)?;
+
+    write_atomic(filename, &data, false)
+}
+
+// builds an svo from a linear chunk, padded up to the next power of two
+pub fn binvox_chunk_to_svo(chunk: &[u8], chunk_res: u32) -> SVO {
+    let svo_res = chunk_res.next_power_of_two();
+    let depth = svo_res.trailing_zeros() as u8;
+
+    // morton indices only cover 8 bits per axis
+    if svo_res == chunk_res && chunk_res <= 256 {
+        let size = chunk_res * chunk_res * chunk_res;
+        let mut morton_chunk = vec![0u8; size as usize];
+        morton_encode_3d_grid(chunk, chunk_res, size, &mut morton_chunk);
+        return SVO::from_grid(&morton_chunk, chunk_res, depth);
+    }
+
+    let mut svo = SVO::new(depth);
+    for z in 0..chunk_res {
+        for y in 0..chunk_res {
+            for x in 0..chunk_res {
+                let mat = chunk[pos_to_index(x, y, z, chunk_res) as usize];
+                if mat > 0 {
+                    svo.insert_voxel(UVec3::new(x, y, z), mat as u32).unwrap();
+                }
+            }
+        }
+    }
+
+    svo
+}
+
+pub fn read_binvox_svo(filename: &str) -> io::Result<(BinvoxHeader, SVO)> {
+    let (header, chunk) = read_binvox(filename)?;
+    let svo = binvox_chunk_to_svo(&chunk, header.chunk_res());
+
+    Ok((header, svo))
+}
+
+// writes the svo leaves as a cubic binvox model with one voxel per leaf
+pub fn write_binvox_svo(filename: &str, svo: &SVO, translate: Vec3, scale: f32) -> io::Result<()> {
+    let chunk_res = 1u32.checked_shl(svo.depth as u32).filter(|&res| res <= MAX_BINVOX_RES);
+    let chunk_res = chunk_res.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "svo is too deep for a binvox model."))?;
+    let mut chunk = vec![0u8; (chunk_res as usize).pow(3)];
+
+    svo.for_each_leaf(|pos, _| {
+        chunk[pos_to_index(pos.x, pos.y, pos.z, chunk_res) as usize] = DEFAULT_VOX_MAT;
+    });
+
+    write_binvox(filename, &chunk, chunk_res, BinvoxHeader::new(UVec3::splat(chunk_res), translate, scale))
+}
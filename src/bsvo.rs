@@ -1,20 +1,27 @@
-use std::{fs::File, io, io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write}, path::Path, ptr, slice};
-use crate::svo::{DEFAULT_SVO_MAX_DEPTH, SVO};
+use std::{borrow::Cow, collections::VecDeque, fs::File, io, io::{BufReader, Read}, path::Path};
+use crate::atomic::write_atomic;
+use crate::crc::{crc32, split_file_checksum};
+use crate::rle::{read_varint, run_length_decode_varint, run_length_decode_varint_u32, run_length_encode_varint, run_length_encode_varint_u32, write_varint};
+use crate::svo::{encode_node, Octant, SvoView, DEFAULT_SVO_MAX_DEPTH, SVO};
 
-pub const BSVO_VERSION: u8 = 3;
+pub const BSVO_VERSION: u8 = 5;
 pub const NODE_SIZE: usize = size_of::<u32>();
 
-// Todo: implement run length encoding for bsvo? is it worth it?
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct BsvoHeader {
     version: u8,
     pub depth: u8,
     pub root_span: f32,
-    #[allow(dead_code)]
+    // nodes are stored with encode_bsvo_nodes instead of raw
     pub run_length_encoded: bool,
+    // only child masks and leaf values in breadth first order, see encode_bsvo_pointerless
+    pub pointerless: bool,
+    // crc32 of everything before it in the last 4 bytes of the file
+    pub checksum: bool,
 }
 
-const BSVO_HEADER_SIZE: usize = size_of::<BsvoHeader>();
+// keeps the node data aligned for u32
+pub const BSVO_HEADER_SIZE: usize = 16;
 
 impl BsvoHeader {
     pub fn new(depth: u8, root_span: f32, run_length_encoded: bool) -> BsvoHeader {
@@ -23,8 +30,52 @@ impl BsvoHeader {
             depth,
             root_span,
             run_length_encoded,
+            pointerless: false,
+            checksum: false,
         }
     }
+
+    pub fn with_checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    pub fn with_pointerless(mut self, pointerless: bool) -> Self {
+        self.pointerless = pointerless;
+        self
+    }
+
+    pub fn to_bytes(&self) -> [u8; BSVO_HEADER_SIZE] {
+        let mut bytes = [0u8; BSVO_HEADER_SIZE];
+        bytes[0x00] = BSVO_VERSION;
+        bytes[0x04] = self.depth;
+        bytes[0x08..0x0C].copy_from_slice(&self.root_span.to_le_bytes());
+        bytes[0x0C] = self.run_length_encoded as u8;
+        bytes[0x0D] = self.pointerless as u8;
+        bytes[0x0E] = self.checksum as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; BSVO_HEADER_SIZE]) -> io::Result<Self> {
+        let version = bytes[0x00];
+
+        if version > BSVO_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "newer bsvo reader version required for file."));
+        }
+
+        if version < BSVO_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "file version is outdated, use older bsvo reader."));
+        }
+
+        Ok(Self {
+            version,
+            depth: bytes[0x04],
+            root_span: f32::from_le_bytes(bytes[0x08..0x0C].try_into().unwrap()),
+            run_length_encoded: bytes[0x0C] != 0,
+            pointerless: bytes[0x0D] != 0,
+            checksum: bytes[0x0E] != 0,
+        })
+    }
 }
 
 impl Default for BsvoHeader {
@@ -33,39 +84,208 @@ impl Default for BsvoHeader {
     }
 }
 
-#[warn(dead_code)]
 pub fn write_empty_bsvo(filename: &str, header: BsvoHeader) -> io::Result<()> {
-    let mut header = header;
-    header.version = BSVO_VERSION;
+    let mut data = header.to_bytes().to_vec();
+    if header.checksum {
+        data.extend_from_slice(&crc32(&header.to_bytes()).to_le_bytes());
+    }
 
-    let path = Path::new(filename);
-    let mut writer = BufWriter::new(File::create(path)?);
+    write_atomic(filename, &data, false)
+}
 
-    let header_bytes = unsafe { slice::from_raw_parts(&header as *const _ as *const u8, BSVO_HEADER_SIZE) };
-    writer.write_all(header_bytes)?;
+const NODE_LOW_MASK: u32 = 0x00FF_FFFF;
 
-    writer.flush()?;
+// u32 node count, u32 length of the child mask stream, the varint rle child masks, then the varint rle of the low 24 bits.
+// child pointers are stored as the difference to the previous child pointer, children are allocated in blocks of 8 so most deltas repeat
+pub fn encode_bsvo_nodes(nodes: &[u32]) -> Vec<u8> {
+    let masks: Vec<u8> = nodes.iter().map(|node| node.child_mask()).collect();
 
-    Ok(())
+    let mut prev_pointer = 0u32;
+    let lows: Vec<u32> = nodes
+        .iter()
+        .map(|node| {
+            if !node.has_children() {
+                return node & NODE_LOW_MASK;
+            }
+
+            let pointer = node.first_child_index();
+            let delta = pointer.wrapping_sub(prev_pointer) & NODE_LOW_MASK;
+            prev_pointer = pointer;
+            delta
+        })
+        .collect();
+
+    let encoded_masks = run_length_encode_varint(&masks);
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&(nodes.len() as u32).to_le_bytes());
+    data.extend_from_slice(&(encoded_masks.len() as u32).to_le_bytes());
+    data.extend(encoded_masks);
+    data.extend(run_length_encode_varint_u32(&lows));
+    data
 }
 
-pub fn write_bsvo(filename: &str, svo: &SVO, header: BsvoHeader) -> io::Result<()> {
-    let mut header = header;
-    header.version = BSVO_VERSION;
+pub fn decode_bsvo_nodes(data: &[u8]) -> io::Result<Vec<u32>> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
 
-    let path = Path::new(filename);
-    let mut writer = BufWriter::new(File::create(path)?);
+    let lengths = data.get(..8).ok_or_else(|| invalid("bsvo node stream too short."))?;
+    let node_count = u32::from_le_bytes(lengths[0..4].try_into().unwrap()) as usize;
+    let masks_len = u32::from_le_bytes(lengths[4..8].try_into().unwrap()) as usize;
+
+    let encoded_masks = data.get(8..8 + masks_len).ok_or_else(|| invalid("truncated bsvo child masks."))?;
+    let masks = run_length_decode_varint(encoded_masks, node_count)?;
+    let lows = run_length_decode_varint_u32(&data[8 + masks_len..], node_count)?;
+
+    if masks.len() != node_count || lows.len() != node_count {
+        return Err(invalid("bsvo node streams do not match the node count."));
+    }
+
+    let mut prev_pointer = 0u32;
+    Ok(masks
+        .iter()
+        .zip(&lows)
+        .map(|(&mask, &low)| {
+            if mask == 0 {
+                return low & NODE_LOW_MASK;
+            }
+
+            prev_pointer = prev_pointer.wrapping_add(low) & NODE_LOW_MASK;
+            0u32.set_child_mask(mask).set_first_child_index(prev_pointer)
+        })
+        .collect())
+}
+
+// u32 node count, u32 length of the mask stream, the child masks of all reachable nodes above max depth in breadth first order,
+// then the low 24 bits of every node without children in the same order. both streams are varint rle when run_length_encoded is set.
+// first_child_index is implied by the order, decoding allocates 8 children per node with a mask again
+pub fn encode_bsvo_pointerless(svo: &SVO, run_length_encoded: bool) -> Vec<u8> {
+    let mut masks = Vec::new();
+    let mut leaves = Vec::new();
+    let mut queue = VecDeque::from([(0, 0)]);
+
+    while let Some((idx, depth)) = queue.pop_front() {
+        let node = svo.nodes[idx];
+
+        // nodes at max depth are always leaves and don't store a mask
+        if depth < svo.depth {
+            masks.push(node.child_mask());
+        }
 
-    let header_bytes = unsafe { slice::from_raw_parts(&header as *const _ as *const u8, BSVO_HEADER_SIZE) };
-    writer.write_all(header_bytes)?;
+        if depth == svo.depth || !node.has_children() {
+            leaves.push(node & NODE_LOW_MASK);
+            continue;
+        }
+
+        queue.extend((0..8).filter(|&i| node.check_child(i)).map(|i| ((node.first_child_index() + i) as usize, depth + 1)));
+    }
+
+    let node_count = masks.len();
+    let (masks_data, leaves_data) = if run_length_encoded {
+        (run_length_encode_varint(&masks), run_length_encode_varint_u32(&leaves))
+    } else {
+        let mut leaves_data = Vec::new();
+        leaves.iter().for_each(|&leaf| write_varint(&mut leaves_data, leaf as u64));
+        (masks, leaves_data)
+    };
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&(node_count as u32).to_le_bytes());
+    data.extend_from_slice(&(masks_data.len() as u32).to_le_bytes());
+    data.extend(masks_data);
+    data.extend(leaves_data);
+    data
+}
+
+pub fn decode_bsvo_pointerless(data: &[u8], depth: u8, run_length_encoded: bool) -> io::Result<Vec<u32>> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+    let lengths = data.get(..8).ok_or_else(|| invalid("bsvo node stream too short."))?;
+    let node_count = u32::from_le_bytes(lengths[0..4].try_into().unwrap()) as usize;
+    let masks_len = u32::from_le_bytes(lengths[4..8].try_into().unwrap()) as usize;
+
+    let masks_data = data.get(8..8 + masks_len).ok_or_else(|| invalid("truncated bsvo child masks."))?;
+    let leaves_data = &data[8 + masks_len..];
+
+    let masks = if run_length_encoded { run_length_decode_varint(masks_data, node_count)? } else { masks_data.to_vec() };
+    if masks.len() != node_count {
+        return Err(invalid("bsvo node streams do not match the node count."));
+    }
+
+    // every mask adds at most 8 leaves, the exact count depends on the tree shape and is checked while rebuilding
+    let leaves = if run_length_encoded {
+        run_length_decode_varint_u32(leaves_data, 8 * node_count + 1)?
+    } else {
+        let mut leaves = Vec::new();
+        let mut pos = 0;
+        while pos < leaves_data.len() {
+            leaves.push(read_varint(leaves_data, &mut pos)? as u32);
+        }
+        leaves
+    };
+
+    let mut nodes = vec![0u32];
+    let mut queue = VecDeque::from([(0, 0)]);
+    let (mut masks, mut leaves) = (masks.into_iter(), leaves.into_iter());
+
+    while let Some((idx, cur_depth)) = queue.pop_front() {
+        let mask = if cur_depth < depth { masks.next().ok_or_else(|| invalid("bsvo child masks end before the tree."))? } else { 0 };
+
+        if mask == 0 {
+            nodes[idx] = leaves.next().ok_or_else(|| invalid("bsvo leaves end before the tree."))? & NODE_LOW_MASK;
+            continue;
+        }
+
+        let first_child_index = nodes.len() as u32;
+        if first_child_index > NODE_LOW_MASK {
+            return Err(invalid("bsvo tree exceeds the 24 bit child index."));
+        }
+
+        nodes[idx] = encode_node(mask, first_child_index);
+        nodes.resize(nodes.len() + 8, 0);
+        queue.extend((0..8).filter(|&i| mask & (1 << i) != 0).map(|i| ((first_child_index + i) as usize, cur_depth + 1)));
+    }
+
+    if masks.next().is_some() || leaves.next().is_some() {
+        return Err(invalid("bsvo node streams continue after the tree."));
+    }
+
+    Ok(nodes)
+}
+
+pub fn encode_bsvo(svo: &SVO, header: BsvoHeader) -> Vec<u8> {
+    let mut data = header.to_bytes().to_vec();
+
+    if header.pointerless {
+        data.extend(encode_bsvo_pointerless(svo, header.run_length_encoded));
+    } else if header.run_length_encoded {
+        data.extend(encode_bsvo_nodes(&svo.nodes));
+    } else {
+        data.extend(svo.nodes.iter().flat_map(|node| node.to_le_bytes()));
+    }
 
-    for &node in &svo.nodes {
-        let bytes = node.to_le_bytes();
-        writer.write_all(&bytes)?;
+    if header.checksum {
+        data.extend_from_slice(&crc32(&data).to_le_bytes());
     }
 
-    writer.flush()?;
-    Ok(())
+    data
+}
+
+pub fn write_bsvo(filename: &str, svo: &SVO, header: BsvoHeader) -> io::Result<()> {
+    let data = encode_bsvo(svo, header);
+
+    write_atomic(filename, &data, false)
+}
+
+// keeps the file that is replaced as filename.bak
+pub fn write_bsvo_with_backup(filename: &str, svo: &SVO, header: BsvoHeader) -> io::Result<()> {
+    let data = encode_bsvo(svo, header);
+
+    write_atomic(filename, &data, true)
+}
+
+fn parse_bsvo_header(bytes: &[u8]) -> io::Result<BsvoHeader> {
+    let header_bytes = bytes.get(..BSVO_HEADER_SIZE).ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "bsvo file too short for header."))?;
+    BsvoHeader::from_bytes(header_bytes.try_into().unwrap())
 }
 
 pub fn get_bsvo_header(filename: &str) -> io::Result<BsvoHeader> {
@@ -74,43 +294,146 @@ pub fn get_bsvo_header(filename: &str) -> io::Result<BsvoHeader> {
 
     let mut buffer = [0u8; BSVO_HEADER_SIZE];
     reader.read_exact(&mut buffer)?;
-    let header: BsvoHeader = unsafe { ptr::read(buffer.as_ptr().cast()) };
 
-    if header.version > BSVO_VERSION {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "newer bsvo reader version required for file."));
+    BsvoHeader::from_bytes(&buffer)
+}
+
+// raw little endian nodes, borrowed when the host is little endian and the data is aligned for u32, copied otherwise
+pub fn nodes_from_le_bytes(bytes: &[u8]) -> io::Result<Cow<'_, [u32]>> {
+    if !bytes.len().is_multiple_of(NODE_SIZE) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "bsvo node data is not a multiple of the node size."));
     }
 
-    if header.version < BSVO_VERSION {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "file version is outdated, use older bsvo reader."));
+    if cfg!(target_endian = "little") {
+        // align_to only puts bytes into the middle slice if they are aligned, every bit pattern is a valid u32
+        let (prefix, nodes, suffix) = unsafe { bytes.align_to::<u32>() };
+        if prefix.is_empty() && suffix.is_empty() {
+            return Ok(Cow::Borrowed(nodes));
+        }
     }
 
-    Ok(header)
+    Ok(Cow::Owned(bytes.chunks_exact(NODE_SIZE).map(|b| u32::from_le_bytes(b.try_into().unwrap())).collect()))
 }
 
-pub fn read_bsvo(filename: &str) -> io::Result<(BsvoHeader, SVO)> {
-    let header = get_bsvo_header(filename)?;
+// raw nodes are viewed in place where possible, run length encoded and pointerless nodes are decoded
+pub fn decode_bsvo(data: &[u8]) -> io::Result<(BsvoHeader, SvoView<'_>)> {
+    let header = parse_bsvo_header(data)?;
+    let data = if header.checksum { split_file_checksum(data)? } else { data };
+    let node_data = &data[BSVO_HEADER_SIZE..];
+
+    let nodes = if header.pointerless {
+        Cow::Owned(decode_bsvo_pointerless(node_data, header.depth, header.run_length_encoded)?)
+    } else if header.run_length_encoded {
+        Cow::Owned(decode_bsvo_nodes(node_data)?)
+    } else {
+        nodes_from_le_bytes(node_data)?
+    };
+
+    let view = SvoView {
+        nodes,
+        depth: header.depth,
+        root_span: header.root_span,
+    };
+
+    Ok((header, view))
+}
 
+pub fn read_bsvo(filename: &str) -> io::Result<(BsvoHeader, SVO)> {
     let path = Path::new(filename);
     let mut reader = BufReader::new(File::open(path)?);
 
-    // skip the already read header part
-    reader.seek(SeekFrom::Start(size_of::<BsvoHeader>() as u64))?;
-
     let mut buffer = Vec::new();
     reader.read_to_end(&mut buffer)?;
 
-    assert_eq!(buffer.len() % NODE_SIZE, 0);
-
-    let node_count = buffer.len() / NODE_SIZE;
-
-    let nodes_slice = unsafe { slice::from_raw_parts(buffer.as_ptr().cast(), node_count) };
-    let nodes = nodes_slice.to_vec();
+    let (header, view) = decode_bsvo(&buffer)?;
 
     let svo = SVO {
-        nodes,
+        nodes: view.nodes.into_owned(),
         root_span: header.root_span,
         depth: header.depth,
     };
 
     Ok((header, svo))
-}
\ No newline at end of file
+}
+
+// checks the header and the file checksum without decoding the nodes
+pub fn verify_bsvo(filename: &str) -> io::Result<BsvoHeader> {
+    let path = Path::new(filename);
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+
+    let header = parse_bsvo_header(&buffer)?;
+    let data = if header.checksum { split_file_checksum(&buffer)? } else { &buffer };
+
+    if data.len() < BSVO_HEADER_SIZE {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "bsvo file too short for header."));
+    }
+
+    if !header.pointerless && !header.run_length_encoded && !(data.len() - BSVO_HEADER_SIZE).is_multiple_of(NODE_SIZE) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "bsvo node data is not a multiple of the node size."));
+    }
+
+    Ok(header)
+}
+
+// keeps a bsvo file mapped so raw nodes can be queried without reading or copying them
+#[cfg(feature = "mmap")]
+pub struct MappedBsvo {
+    pub header: BsvoHeader,
+    mmap: memmap2::Mmap,
+    nodes: MappedNodes,
+}
+
+// where the nodes of a mapped file live, decided once when the file is opened
+#[cfg(feature = "mmap")]
+enum MappedNodes {
+    // byte range of raw little endian nodes inside the mapping
+    Mapped(std::ops::Range<usize>),
+    Decoded(Vec<u32>),
+}
+
+#[cfg(feature = "mmap")]
+impl MappedBsvo {
+    // the checksum is verified and encoded nodes are decoded here, views are free afterwards
+    pub fn open(filename: &str) -> io::Result<Self> {
+        let file = File::open(Path::new(filename))?;
+        // the file must not be truncated by another process while it is mapped
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+
+        let (header, view) = decode_bsvo(&mmap)?;
+        if header.depth > 31 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bsvo depth is too large for voxel positions."));
+        }
+
+        let nodes = match view.nodes {
+            Cow::Borrowed(nodes) => {
+                let start = nodes.as_ptr() as usize - mmap.as_ptr() as usize;
+                MappedNodes::Mapped(start..start + nodes.len() * NODE_SIZE)
+            }
+            Cow::Owned(nodes) => MappedNodes::Decoded(nodes),
+        };
+
+        Ok(Self { header, mmap, nodes })
+    }
+
+    // raw files on little endian hosts are read straight from the mapping, other files from the nodes decoded on open
+    pub fn is_zero_copy(&self) -> bool {
+        matches!(self.nodes, MappedNodes::Mapped(_))
+    }
+
+    pub fn view(&self) -> SvoView<'_> {
+        let nodes = match &self.nodes {
+            // the range was borrowed as aligned u32s when the file was opened
+            MappedNodes::Mapped(range) => unsafe { self.mmap[range.clone()].align_to::<u32>().1 },
+            MappedNodes::Decoded(nodes) => nodes.as_slice(),
+        };
+
+        SvoView {
+            nodes: Cow::Borrowed(nodes),
+            depth: self.header.depth,
+            root_span: self.header.root_span,
+        }
+    }
+}
@@ -1,33 +1,91 @@
-use std::{io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write}, io, fs::{File, OpenOptions}, path::Path, slice, ptr};
-use crate::rle::{run_length_decode, run_length_encode};
+use std::{io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write}, io, fs::{self, File, OpenOptions}, path::Path};
+use crate::atomic::{replace_atomic, write_atomic};
+use crate::codec::Codec;
+use crate::crc::{crc32, crc32_update, split_file_checksum, ChecksumError};
 
-pub const BVOX_VERSION: u8 = 2;
-pub const CHUNK_SEPARATOR: u8 = u8::MAX;
+pub const BVOX_VERSION: u8 = 4;
 pub const DEFAULT_CHUNK_RES: u32 = 256;
 pub const DEFAULT_CHUNK_SIZE: u32 = DEFAULT_CHUNK_RES * DEFAULT_CHUNK_RES * DEFAULT_CHUNK_RES;
+pub const BVOX_HEADER_SIZE: usize = 16;
+// codec and payload length in front of every chunk
+pub const BVOX_CHUNK_HEADER_SIZE: usize = 5;
+// crc32 of the payload after the chunk header when chunk checksums are enabled
+pub const BVOX_CHUNK_CHECKSUM_SIZE: usize = 4;
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct BvoxHeader {
     version: u8,
     pub chunk_res: u32,
     pub chunk_size: u32,
-    pub run_length_encoded: bool,
+    // default codec for new chunks, every chunk stores the codec it was written with
+    pub codec: Codec,
     pub morton_encoded: bool,
+    pub chunk_checksums: bool,
+    // crc32 of everything before it in the last 4 bytes of the file
+    pub file_checksum: bool,
 }
 
-const BVOX_HEADER_SIZE: usize = size_of::<BvoxHeader>();
-
 impl BvoxHeader {
     pub fn new(chunk_res: u32, chunk_size: u32, run_length_encoded: bool, morton_encoded: bool) -> Self {
         Self {
             version: BVOX_VERSION,
             chunk_res,
             chunk_size,
-            run_length_encoded,
+            codec: if run_length_encoded { Codec::Rle } else { Codec::None },
             morton_encoded,
+            chunk_checksums: false,
+            file_checksum: false,
         }
     }
+
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    pub fn with_checksums(mut self, chunk_checksums: bool, file_checksum: bool) -> Self {
+        self.chunk_checksums = chunk_checksums;
+        self.file_checksum = file_checksum;
+        self
+    }
+
+    fn chunk_header_size(&self) -> usize {
+        BVOX_CHUNK_HEADER_SIZE + if self.chunk_checksums { BVOX_CHUNK_CHECKSUM_SIZE } else { 0 }
+    }
+
+    pub fn to_bytes(&self) -> [u8; BVOX_HEADER_SIZE] {
+        let mut bytes = [0u8; BVOX_HEADER_SIZE];
+        bytes[0x00] = BVOX_VERSION;
+        bytes[0x01] = self.codec as u8;
+        bytes[0x02] = self.chunk_checksums as u8;
+        bytes[0x03] = self.file_checksum as u8;
+        bytes[0x04..0x08].copy_from_slice(&self.chunk_res.to_le_bytes());
+        bytes[0x08..0x0C].copy_from_slice(&self.chunk_size.to_le_bytes());
+        bytes[0x0D] = self.morton_encoded as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; BVOX_HEADER_SIZE]) -> io::Result<Self> {
+        let version = bytes[0x00];
+
+        if version > BVOX_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "newer bvox reader version required for file."));
+        }
+
+        if version < BVOX_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "file version is outdated, use older bvox reader."));
+        }
+
+        Ok(Self {
+            version,
+            chunk_res: u32::from_le_bytes(bytes[0x04..0x08].try_into().unwrap()),
+            chunk_size: u32::from_le_bytes(bytes[0x08..0x0C].try_into().unwrap()),
+            codec: Codec::from_u8(bytes[0x01])?,
+            morton_encoded: bytes[0x0D] != 0,
+            chunk_checksums: bytes[0x02] != 0,
+            file_checksum: bytes[0x03] != 0,
+        })
+    }
 }
 
 impl Default for BvoxHeader {
@@ -36,51 +94,110 @@ impl Default for BvoxHeader {
     }
 }
 
-pub fn write_empty_bvox(filename: &str, header: BvoxHeader) -> io::Result<()> {
-    let mut header = header;
-    header.version = BVOX_VERSION;
+// one chunk record, auto picks the smallest codec for this chunk
+pub fn encode_bvox_chunk(chunk: &[u8], header: &BvoxHeader, codec: Codec) -> io::Result<Vec<u8>> {
+    if chunk.len() != header.chunk_size as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk is not the given size."));
+    }
 
-    let path = Path::new(filename);
-    let mut writer = BufWriter::new(File::create(path)?);
+    let (codec, payload) = codec.encode(chunk);
 
-    let header_bytes = unsafe { slice::from_raw_parts(&header as *const _ as *const u8, BVOX_HEADER_SIZE) };
-    writer.write_all(header_bytes)?;
-    writer.flush()?;
+    let mut record = Vec::with_capacity(header.chunk_header_size() + payload.len());
+    push_bvox_record(&mut record, header, codec, &payload);
 
-    Ok(())
+    Ok(record)
 }
 
-pub fn write_bvox(
-    filename: &str,
-    chunk_data: &[Vec<u8>],
-    header: BvoxHeader,
-) -> io::Result<()> {
-    let mut header = header;
-    header.version = BVOX_VERSION;
-
-    let path = Path::new(filename);
-    let mut writer = BufWriter::new(File::create(path)?);
+fn push_bvox_record(data: &mut Vec<u8>, header: &BvoxHeader, codec: Codec, payload: &[u8]) {
+    data.push(codec as u8);
+    data.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    if header.chunk_checksums {
+        data.extend_from_slice(&crc32(payload).to_le_bytes());
+    }
+    data.extend_from_slice(payload);
+}
 
-    let header_bytes = unsafe { slice::from_raw_parts(&header as *const _ as *const u8, BVOX_HEADER_SIZE) };
-    writer.write_all(header_bytes)?;
+pub fn encode_bvox(chunk_data: &[Vec<u8>], header: BvoxHeader) -> io::Result<Vec<u8>> {
+    let mut data = header.to_bytes().to_vec();
 
     for chunk in chunk_data {
-        if chunk.len() != header.chunk_size as usize {
-            return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk is not the given size."));
+        data.extend(encode_bvox_chunk(chunk, &header, header.codec)?);
+    }
+
+    if header.file_checksum {
+        data.extend_from_slice(&crc32(&data).to_le_bytes());
+    }
+
+    Ok(data)
+}
+
+// codec and still encoded payload of a chunk
+pub type BvoxRecord<'a> = (Codec, &'a [u8]);
+
+// splits a file into the records of every chunk, checking all checksums without decoding anything
+pub fn split_bvox(data: &[u8]) -> io::Result<(BvoxHeader, Vec<BvoxRecord<'_>>)> {
+    let header_bytes = data.get(..BVOX_HEADER_SIZE).ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "bvox file too short for header."))?;
+    let header = BvoxHeader::from_bytes(header_bytes.try_into().unwrap())?;
+
+    let data = if header.file_checksum { split_file_checksum(data)? } else { data };
+
+    let mut records = Vec::new();
+    let mut offset = BVOX_HEADER_SIZE;
+
+    while offset < data.len() {
+        let record = data.get(offset..offset + header.chunk_header_size()).ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated bvox chunk."))?;
+        let codec = Codec::from_u8(record[0])?;
+        // auto only picks a codec when writing, a record has to name the codec it was written with
+        if codec == Codec::Auto {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "auto is not a chunk codec."));
         }
+        let len = u32::from_le_bytes(record[1..5].try_into().unwrap()) as usize;
+        offset += header.chunk_header_size();
 
-        if header.run_length_encoded {
-            let encoded = run_length_encode(chunk);
-            writer.write_all(&encoded)?;
-        } else {
-            writer.write_all(chunk)?;
+        let payload = data.get(offset..offset + len).ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated bvox chunk."))?;
+
+        if header.chunk_checksums {
+            let expected = u32::from_le_bytes(record[5..9].try_into().unwrap());
+            let actual = crc32(payload);
+            if expected != actual {
+                return Err(ChecksumError { chunk: Some(records.len()), expected, actual }.into());
+            }
         }
 
-        writer.write_all(&[CHUNK_SEPARATOR])?;
+        records.push((codec, payload));
+        offset += len;
     }
 
-    writer.flush()?;
-    Ok(())
+    Ok((header, records))
+}
+
+pub fn decode_bvox(data: &[u8]) -> io::Result<(BvoxHeader, Vec<Vec<u8>>)> {
+    let (header, records) = split_bvox(data)?;
+
+    let chunk_data = records.iter().map(|(codec, payload)| codec.decode(payload, header.chunk_size as usize)).collect::<io::Result<_>>()?;
+
+    Ok((header, chunk_data))
+}
+
+pub fn write_empty_bvox(filename: &str, header: BvoxHeader) -> io::Result<()> {
+    write_bvox(filename, &[], header)
+}
+
+pub fn write_bvox(
+    filename: &str,
+    chunk_data: &[Vec<u8>],
+    header: BvoxHeader,
+) -> io::Result<()> {
+    let data = encode_bvox(chunk_data, header)?;
+
+    write_atomic(filename, &data, false)
+}
+
+// keeps the file that is replaced as filename.bak
+pub fn write_bvox_with_backup(filename: &str, chunk_data: &[Vec<u8>], header: BvoxHeader) -> io::Result<()> {
+    let data = encode_bvox(chunk_data, header)?;
+
+    write_atomic(filename, &data, true)
 }
 
 pub fn get_bvox_header(filename: &str) -> io::Result<BvoxHeader> {
@@ -89,68 +206,115 @@ pub fn get_bvox_header(filename: &str) -> io::Result<BvoxHeader> {
 
     let mut buffer = [0u8; BVOX_HEADER_SIZE];
     reader.read_exact(&mut buffer)?;
-    let header: BvoxHeader = unsafe { ptr::read(buffer.as_ptr().cast()) };
 
-    if header.version > BVOX_VERSION {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "newer bvox reader version required for file."));
-    }
-
-    if header.version < BVOX_VERSION {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "file version is outdated, use older bvox reader."));
-    }
-
-    Ok(header)
+    BvoxHeader::from_bytes(&buffer)
 }
 
+// appends in place when the file has no whole file checksum, a crash can then only leave a truncated last chunk behind.
+// with a file checksum the trailer would be overwritten in place, so those files are copied to a temp file instead
 pub fn append_to_bvox(filename: &str, chunk: &[u8]) -> io::Result<()> {
     let header = get_bvox_header(filename)?;
+    append_to_bvox_with_codec(filename, chunk, header.codec)
+}
+
+// appends a chunk with a codec other than the header default
+pub fn append_to_bvox_with_codec(filename: &str, chunk: &[u8], codec: Codec) -> io::Result<()> {
+    let header = get_bvox_header(filename)?;
+    let record = encode_bvox_chunk(chunk, &header, codec)?;
 
     let path = Path::new(filename);
-    let mut writer = BufWriter::new(OpenOptions::new().write(true).append(true).open(path)?);
+
+    if header.file_checksum {
+        // the record replaces the old checksum, which is continued over the new record
+        let mut original = File::open(path)?;
+        let len = original.metadata()?.len();
+        if len < (BVOX_HEADER_SIZE + 4) as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bvox file is too short for its checksum."));
+        }
+
+        let mut stored = [0u8; 4];
+        original.seek(SeekFrom::Start(len - 4))?;
+        original.read_exact(&mut stored)?;
+        original.rewind()?;
+        let checksum = crc32_update(u32::from_le_bytes(stored), &record);
+
+        return replace_atomic(filename, false, |file| {
+            let mut writer = BufWriter::new(file);
+            io::copy(&mut (&mut original).take(len - 4), &mut writer)?;
+            writer.write_all(&record)?;
+            writer.write_all(&checksum.to_le_bytes())?;
+            writer.flush()
+        });
+    }
+
+    let mut writer = BufWriter::new(OpenOptions::new().append(true).open(path)?);
+    writer.write_all(&record)?;
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+}
+
+// replaces the chunk at index with a chunk encoded with the header codec, the other chunks are copied as they are.
+// like all writers the file is rewritten to a temp file and renamed over the original
+pub fn replace_bvox_chunk(filename: &str, index: usize, chunk: &[u8]) -> io::Result<()> {
+    let data = fs::read(filename)?;
+    let (header, mut records) = split_bvox(&data)?;
+
+    if index >= records.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk index is out of range."));
+    }
 
     if chunk.len() != header.chunk_size as usize {
         return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk is not the given size."));
     }
 
-    if header.run_length_encoded {
-        let encoded = run_length_encode(chunk);
-        writer.write_all(&encoded)?;
-    } else {
-        writer.write_all(chunk)?;
+    let encoded = header.codec.encode(chunk);
+    records[index] = (encoded.0, &encoded.1);
+
+    write_atomic(filename, &encode_bvox_records(&header, &records), false)
+}
+
+pub fn remove_bvox_chunk(filename: &str, index: usize) -> io::Result<()> {
+    let data = fs::read(filename)?;
+    let (header, mut records) = split_bvox(&data)?;
+
+    if index >= records.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk index is out of range."));
     }
+    records.remove(index);
 
-    writer.write_all(&[CHUNK_SEPARATOR])?;
-    writer.flush()?;
-    Ok(())
+    write_atomic(filename, &encode_bvox_records(&header, &records), false)
 }
 
-pub fn read_bvox(filename: &str) -> io::Result<(BvoxHeader, Vec<Vec<u8>>)> {
-    let header = get_bvox_header(filename)?;
+fn encode_bvox_records(header: &BvoxHeader, records: &[BvoxRecord]) -> Vec<u8> {
+    let mut data = header.to_bytes().to_vec();
+
+    for &(codec, payload) in records {
+        push_bvox_record(&mut data, header, codec, payload);
+    }
+
+    if header.file_checksum {
+        data.extend_from_slice(&crc32(&data).to_le_bytes());
+    }
+
+    data
+}
 
+pub fn read_bvox(filename: &str) -> io::Result<(BvoxHeader, Vec<Vec<u8>>)> {
     let path = Path::new(filename);
     let mut reader = BufReader::new(File::open(path)?);
 
-    // skip the already read header part
-    reader.seek(SeekFrom::Start(size_of::<BvoxHeader>() as u64))?;
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
 
-    let mut chunk_data = Vec::new();
-    let mut chunk = Vec::new();
+    decode_bvox(&buffer)
+}
 
-    let mut byte = [0u8; 1];
-    while reader.read(&mut byte)? != 0 {
-        if byte[0] == CHUNK_SEPARATOR {
-            if header.run_length_encoded {
-                let decoded = run_length_decode(&chunk)?;
-                chunk_data.push(decoded);
-            } else {
-                chunk_data.push(chunk.clone());
-            }
+// checks the structure and all checksums of a file without decoding the chunks
+pub fn verify_bvox(filename: &str) -> io::Result<BvoxHeader> {
+    let path = Path::new(filename);
+    let mut reader = BufReader::new(File::open(path)?);
 
-            chunk.clear();
-        } else {
-            chunk.push(byte[0]);
-        }
-    }
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
 
-    Ok((header, chunk_data))
-}
\ No newline at end of file
+    split_bvox(&buffer).map(|(header, _)| header)
+}
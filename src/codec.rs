@@ -0,0 +1,192 @@
+use crate::deflate::{deflate, inflate};
+use crate::lz4::{lz4_compress, lz4_decompress};
+use crate::occupancy::occupancy_mat;
+use crate::rle::{run_length_decode, run_length_decode_varint, run_length_encode, run_length_encode_varint};
+use std::io;
+
+// chunk encodings, the discriminant is what gets stored in files
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Codec {
+    None = 0,
+    // byte pair rle from rle.rs
+    Rle = 1,
+    RleVarint = 2,
+    Lz4 = 3,
+    Deflate = 4,
+    // distinct values followed by the indices packed with as few bits as possible
+    BitPacked = 5,
+    // the single material followed by one bit per voxel, chunks with several materials fall back to bit packing
+    Occupancy = 6,
+    // picks the smallest encoding per chunk, only valid as a default and never stored with a chunk
+    Auto = u8::MAX,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 7] = [Codec::None, Codec::Rle, Codec::RleVarint, Codec::Lz4, Codec::Deflate, Codec::BitPacked, Codec::Occupancy];
+
+    pub fn from_u8(value: u8) -> io::Result<Self> {
+        match value {
+            0 => Ok(Codec::None),
+            1 => Ok(Codec::Rle),
+            2 => Ok(Codec::RleVarint),
+            3 => Ok(Codec::Lz4),
+            4 => Ok(Codec::Deflate),
+            5 => Ok(Codec::BitPacked),
+            6 => Ok(Codec::Occupancy),
+            u8::MAX => Ok(Codec::Auto),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "unknown codec.")),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::None => "none",
+            Codec::Rle => "rle",
+            Codec::RleVarint => "rle-varint",
+            Codec::Lz4 => "lz4",
+            Codec::Deflate => "deflate",
+            Codec::BitPacked => "bit-packed",
+            Codec::Occupancy => "occupancy",
+            Codec::Auto => "auto",
+        }
+    }
+
+    pub fn from_name(name: &str) -> io::Result<Self> {
+        Codec::ALL
+            .into_iter()
+            .chain([Codec::Auto])
+            .find(|codec| codec.name() == name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("unknown codec {name}.")))
+    }
+
+    // encodes the data, auto resolves to the codec that was actually used
+    pub fn encode(&self, data: &[u8]) -> (Codec, Vec<u8>) {
+        let encoded = match self {
+            Codec::None => data.to_vec(),
+            Codec::Rle => run_length_encode(data),
+            Codec::RleVarint => run_length_encode_varint(data),
+            Codec::Lz4 => lz4_compress(data),
+            Codec::Deflate => deflate(data),
+            Codec::BitPacked => bit_pack(data),
+            Codec::Occupancy => match occupancy_mat(data) {
+                Some(mat) => occupancy_pack(data, mat),
+                None => return Codec::BitPacked.encode(data),
+            },
+            Codec::Auto => {
+                return Codec::ALL.iter().map(|codec| codec.encode(data)).min_by_key(|(_, encoded)| encoded.len()).unwrap();
+            }
+        };
+
+        (*self, encoded)
+    }
+
+    // decodes data that has to expand to exactly size bytes
+    pub fn decode(&self, data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+        let decoded = match self {
+            Codec::None => data.to_vec(),
+            Codec::Rle => run_length_decode(data)?,
+            Codec::RleVarint => run_length_decode_varint(data, size)?,
+            Codec::Lz4 => lz4_decompress(data, size)?,
+            Codec::Deflate => inflate(data, size)?,
+            Codec::BitPacked => bit_unpack(data, size)?,
+            Codec::Occupancy => occupancy_unpack(data, size)?,
+            Codec::Auto => return Err(io::Error::new(io::ErrorKind::InvalidData, "auto is not a chunk codec.")),
+        };
+
+        if decoded.len() != size {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "decoded chunk does not match the chunk size."));
+        }
+
+        Ok(decoded)
+    }
+}
+
+// bits needed to index count distinct values, a single value needs none
+fn index_bits(count: usize) -> u32 {
+    usize::BITS - (count - 1).leading_zeros()
+}
+
+// u8 (distinct value count - 1), the distinct values, then the indices lsb first
+pub fn bit_pack(data: &[u8]) -> Vec<u8> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+
+    let mut used = [false; 256];
+    data.iter().for_each(|&v| used[v as usize] = true);
+
+    let values: Vec<u8> = (0..=255).filter(|&v| used[v as usize]).collect();
+    let mut lookup = [0u8; 256];
+    values.iter().enumerate().for_each(|(i, &v)| lookup[v as usize] = i as u8);
+
+    let bits = index_bits(values.len());
+    let mut packed = vec![(values.len() - 1) as u8];
+    packed.extend_from_slice(&values);
+
+    let (mut bit_buf, mut bit_count) = (0u32, 0);
+    for &v in data {
+        bit_buf |= (lookup[v as usize] as u32) << bit_count;
+        bit_count += bits;
+        while bit_count >= 8 {
+            packed.push(bit_buf as u8);
+            bit_buf >>= 8;
+            bit_count -= 8;
+        }
+    }
+    if bit_count > 0 {
+        packed.push(bit_buf as u8);
+    }
+
+    packed
+}
+
+pub fn bit_unpack(data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    if data.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let count = data[0] as usize + 1;
+    let values = data.get(1..1 + count).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unexpected end of bit packed data."))?;
+    let bits = index_bits(count);
+    let packed = &data[1 + count..];
+
+    if packed.len() < (size * bits as usize).div_ceil(8) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected end of bit packed data."));
+    }
+
+    let mask = (1u32 << bits) - 1;
+    let mut unpacked = Vec::with_capacity(size);
+    let (mut bit_buf, mut bit_count, mut pos) = (0u32, 0, 0);
+
+    for _ in 0..size {
+        while bit_count < bits {
+            bit_buf |= (packed[pos] as u32) << bit_count;
+            bit_count += 8;
+            pos += 1;
+        }
+
+        let index = (bit_buf & mask) as usize;
+        bit_buf >>= bits;
+        bit_count -= bits;
+
+        unpacked.push(*values.get(index).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid bit packed index."))?);
+    }
+
+    Ok(unpacked)
+}
+
+fn occupancy_pack(data: &[u8], mat: u8) -> Vec<u8> {
+    let mut packed = vec![mat];
+    packed.extend(data.chunks(8).map(|values| values.iter().enumerate().fold(0u8, |b, (i, &v)| b | ((v != 0) as u8) << i)));
+    packed
+}
+
+fn occupancy_unpack(data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    if data.len() != 1 + size.div_ceil(8) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "occupancy data does not match the chunk size."));
+    }
+
+    let mat = data[0];
+    Ok((0..size).map(|i| if data[1 + i / 8] & (1 << (i % 8)) != 0 { mat } else { 0 }).collect())
+}
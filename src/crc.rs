@@ -0,0 +1,83 @@
+use std::{error::Error, fmt, io};
+
+// crc-32 (ieee 802.3, reflected 0xEDB88320) as used by gzip, zlib and png
+const CRC32_POLY: u32 = 0xEDB88320;
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ CRC32_POLY } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+// continues a finished crc with more data, crc32_update(crc32(a), b) == crc32(a ++ b)
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc = CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+// a stored crc32 that doesn't match the data, readers return it inside an io::Error of kind InvalidData
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChecksumError {
+    // none for the whole file checksum
+    pub chunk: Option<usize>,
+    pub expected: u32,
+    pub actual: u32,
+}
+
+impl ChecksumError {
+    // the checksum error behind an io error returned by a reader, if that was the cause
+    pub fn from_io(err: &io::Error) -> Option<&ChecksumError> {
+        err.get_ref().and_then(|e| e.downcast_ref::<ChecksumError>())
+    }
+}
+
+impl fmt::Display for ChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.chunk {
+            Some(chunk) => write!(f, "checksum mismatch in chunk {chunk}."),
+            None => write!(f, "file checksum mismatch."),
+        }
+    }
+}
+
+impl Error for ChecksumError {}
+
+impl From<ChecksumError> for io::Error {
+    fn from(err: ChecksumError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+// checks the little endian crc32 in the last 4 bytes against the rest and returns the data without it
+pub fn split_file_checksum(data: &[u8]) -> io::Result<&[u8]> {
+    if data.len() < 4 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file too short for checksum."));
+    }
+
+    let (content, stored) = data.split_at(data.len() - 4);
+    let expected = u32::from_le_bytes(stored.try_into().unwrap());
+    let actual = crc32(content);
+
+    if expected != actual {
+        return Err(ChecksumError { chunk: None, expected, actual }.into());
+    }
+
+    Ok(content)
+}
@@ -0,0 +1,448 @@
+use crate::crc::crc32;
+use std::io;
+
+pub const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+const MAX_BITS: usize = 15;
+const MAX_LIT_CODES: usize = 288;
+const MAX_DIST_CODES: usize = 30;
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+// order in which the code length code lengths are stored in dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0, bit_buf: 0, bit_count: 0 }
+    }
+
+    // deflate packs values starting at the least significant bit
+    fn bits(&mut self, count: u32) -> io::Result<u32> {
+        while self.bit_count < count {
+            let byte = *self.data.get(self.pos).ok_or_else(|| invalid_data("unexpected end of deflate stream."))?;
+            self.bit_buf |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+            self.pos += 1;
+        }
+
+        let value = self.bit_buf & ((1u64 << count) - 1) as u32;
+        self.bit_buf >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.bit_buf = 0;
+        self.bit_count = 0;
+    }
+}
+
+// canonical huffman code as symbol counts per code length and the symbols sorted by code
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // reject over subscribed codes, incomplete codes are allowed
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(invalid_data("over subscribed huffman code in deflate stream."));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+
+        for len in 1..=MAX_BITS {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(invalid_data("invalid huffman code in deflate stream."))
+    }
+}
+
+fn fixed_tables() -> io::Result<(Huffman, Huffman)> {
+    let mut lengths = [0u8; MAX_LIT_CODES];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; MAX_DIST_CODES])?))
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let lit_count = reader.bits(5)? as usize + 257;
+    let dist_count = reader.bits(5)? as usize + 1;
+    let code_count = reader.bits(4)? as usize + 4;
+
+    if lit_count > 286 || dist_count > MAX_DIST_CODES {
+        return Err(invalid_data("too many codes in deflate stream."));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_count] {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_huffman = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; lit_count + dist_count];
+    let mut i = 0;
+
+    while i < lengths.len() {
+        let symbol = code_huffman.decode(reader)?;
+
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                if i == 0 {
+                    return Err(invalid_data("repeat without previous length in deflate stream."));
+                }
+                (lengths[i - 1], 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+
+        if i + repeat > lengths.len() {
+            return Err(invalid_data("too many code lengths in deflate stream."));
+        }
+
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+
+    if lengths[256] == 0 {
+        return Err(invalid_data("missing end of block code in deflate stream."));
+    }
+
+    Ok((Huffman::new(&lengths[..lit_count])?, Huffman::new(&lengths[lit_count..])?))
+}
+
+fn too_large() -> io::Error {
+    invalid_data("deflate stream expands beyond the expected size.")
+}
+
+fn inflate_block(reader: &mut BitReader, out: &mut Vec<u8>, lit: &Huffman, dist: &Huffman, max_len: usize) -> io::Result<()> {
+    loop {
+        let symbol = lit.decode(reader)? as usize;
+
+        match symbol {
+            0..=255 => {
+                if out.len() >= max_len {
+                    return Err(too_large());
+                }
+                out.push(symbol as u8);
+            }
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    return Err(invalid_data("invalid length code in deflate stream."));
+                }
+                let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+                let index = dist.decode(reader)? as usize;
+                if index >= DIST_BASE.len() {
+                    return Err(invalid_data("invalid distance code in deflate stream."));
+                }
+                let distance = DIST_BASE[index] as usize + reader.bits(DIST_EXTRA[index] as u32)? as usize;
+
+                if distance > out.len() {
+                    return Err(invalid_data("distance too far back in deflate stream."));
+                }
+                if length > max_len - out.len() {
+                    return Err(too_large());
+                }
+
+                // byte wise copy, the source may overlap the bytes being written
+                let start = out.len() - distance;
+                for i in 0..length {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
+
+// decodes a raw deflate stream (rfc 1951), returns the data and the number of bytes consumed.
+// fails as soon as the output would grow past max_len
+pub fn inflate_with_len(data: &[u8], max_len: usize) -> io::Result<(Vec<u8>, usize)> {
+    let mut reader = BitReader::new(data);
+    let mut out = Vec::new();
+
+    loop {
+        let last = reader.bits(1)? == 1;
+
+        match reader.bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let header = data.get(reader.pos..reader.pos + 4).ok_or_else(|| invalid_data("unexpected end of deflate stream."))?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                let nlen = u16::from_le_bytes([header[2], header[3]]);
+
+                if len != !nlen {
+                    return Err(invalid_data("invalid stored block length in deflate stream."));
+                }
+
+                let start = reader.pos + 4;
+                let block = data.get(start..start + len as usize).ok_or_else(|| invalid_data("unexpected end of deflate stream."))?;
+                if block.len() > max_len - out.len() {
+                    return Err(too_large());
+                }
+                out.extend_from_slice(block);
+                reader.pos = start + len as usize;
+            }
+            1 => {
+                let (lit, dist) = fixed_tables()?;
+                inflate_block(&mut reader, &mut out, &lit, &dist, max_len)?;
+            }
+            2 => {
+                let (lit, dist) = dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &lit, &dist, max_len)?;
+            }
+            _ => return Err(invalid_data("invalid block type in deflate stream.")),
+        }
+
+        if last {
+            return Ok((out, reader.pos));
+        }
+    }
+}
+
+pub fn inflate(data: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+    inflate_with_len(data, max_len).map(|(out, _)| out)
+}
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.len() >= 2 && data[..2] == GZIP_MAGIC
+}
+
+// decodes a single member gzip file (rfc 1952) and checks its crc, streams that expand beyond max_len are rejected
+pub fn gzip_decode(data: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+    const FHCRC: u8 = 2;
+    const FEXTRA: u8 = 4;
+    const FNAME: u8 = 8;
+    const FCOMMENT: u8 = 16;
+
+    if data.len() < 18 || !is_gzip(data) || data[2] != 8 {
+        return Err(invalid_data("data is not a gzip stream."));
+    }
+
+    let flags = data[3];
+    let mut pos = 10;
+
+    if flags & FEXTRA != 0 {
+        let len = data.get(pos..pos + 2).ok_or_else(|| invalid_data("unexpected end of gzip header."))?;
+        pos += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = data.get(pos..).and_then(|d| d.iter().position(|&b| b == 0)).ok_or_else(|| invalid_data("unexpected end of gzip header."))?;
+            pos += end + 1;
+        }
+    }
+
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+
+    // the size in the trailer is only checked afterwards, it is stored modulo 2^32
+    let (out, consumed) = inflate_with_len(data.get(pos..).ok_or_else(|| invalid_data("unexpected end of gzip header."))?, max_len)?;
+
+    let trailer = data.get(pos + consumed..pos + consumed + 8).ok_or_else(|| invalid_data("missing gzip trailer."))?;
+    let crc = u32::from_le_bytes(trailer[0..4].try_into().unwrap());
+    let size = u32::from_le_bytes(trailer[4..8].try_into().unwrap());
+
+    if crc != crc32(&out) || size != out.len() as u32 {
+        return Err(invalid_data("gzip checksum mismatch."));
+    }
+
+    Ok(out)
+}
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+// hash chain steps per position, trades ratio for speed
+const MAX_CHAIN: usize = 16;
+
+struct BitWriter {
+    out: Vec<u8>,
+    bit_buf: u64,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self { out: Vec::new(), bit_buf: 0, bit_count: 0 }
+    }
+
+    fn bits(&mut self, value: u32, count: u32) {
+        self.bit_buf |= (value as u64) << self.bit_count;
+        self.bit_count += count;
+
+        while self.bit_count >= 8 {
+            self.out.push(self.bit_buf as u8);
+            self.bit_buf >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    // huffman codes are packed starting with their most significant bit
+    fn code(&mut self, code: u32, len: u32) {
+        self.bits(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.out.push(self.bit_buf as u8);
+        }
+        self.out
+    }
+}
+
+fn write_fixed_literal(writer: &mut BitWriter, symbol: usize) {
+    match symbol {
+        0..=143 => writer.code(0x30 + symbol as u32, 8),
+        144..=255 => writer.code(0x190 + (symbol - 144) as u32, 9),
+        256..=279 => writer.code((symbol - 256) as u32, 7),
+        _ => writer.code(0xC0 + (symbol - 280) as u32, 8),
+    }
+}
+
+// index of the last base not above value
+fn base_index(bases: &[u16], value: usize) -> usize {
+    bases.iter().rposition(|&base| base as usize <= value).unwrap()
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let index = base_index(&LENGTH_BASE, length);
+    write_fixed_literal(writer, 257 + index);
+    writer.bits((length - LENGTH_BASE[index] as usize) as u32, LENGTH_EXTRA[index] as u32);
+
+    let index = base_index(&DIST_BASE, distance);
+    writer.code(index as u32, 5);
+    writer.bits((distance - DIST_BASE[index] as usize) as u32, DIST_EXTRA[index] as u32);
+}
+
+fn hash3(data: &[u8], pos: usize) -> usize {
+    let v = (data[pos] as u32) << 16 | (data[pos + 1] as u32) << 8 | data[pos + 2] as u32;
+    (v.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+fn insert_hash(data: &[u8], pos: usize, head: &mut [usize], prev: &mut [usize]) {
+    if pos + MIN_MATCH <= data.len() {
+        let h = hash3(data, pos);
+        prev[pos % WINDOW_SIZE] = head[h];
+        head[h] = pos;
+    }
+}
+
+// raw deflate stream (rfc 1951) as a single fixed huffman block with hash chain lz77 matching
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    // final block, fixed huffman codes
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let mut best = (0, 0);
+
+        if pos + MIN_MATCH <= data.len() {
+            let max_len = (data.len() - pos).min(MAX_MATCH);
+            let mut candidate = head[hash3(data, pos)];
+            let mut steps = 0;
+
+            while candidate != usize::MAX && pos - candidate <= WINDOW_SIZE && steps < MAX_CHAIN {
+                let len = (0..max_len).take_while(|&i| data[candidate + i] == data[pos + i]).count();
+                if len > best.0 {
+                    best = (len, pos - candidate);
+                    if len == max_len {
+                        break;
+                    }
+                }
+
+                let next = prev[candidate % WINDOW_SIZE];
+                // older entries of the ring buffer may have been overwritten
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+                steps += 1;
+            }
+        }
+
+        if best.0 >= MIN_MATCH {
+            write_match(&mut writer, best.0, best.1);
+            for p in pos..pos + best.0 {
+                insert_hash(data, p, &mut head, &mut prev);
+            }
+            pos += best.0;
+        } else {
+            write_fixed_literal(&mut writer, data[pos] as usize);
+            insert_hash(data, pos, &mut head, &mut prev);
+            pos += 1;
+        }
+    }
+
+    write_fixed_literal(&mut writer, 256);
+    writer.finish()
+}
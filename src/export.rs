@@ -0,0 +1,308 @@
+use crate::atomic::write_atomic;
+use crate::mesh::{corner_offset, Mesh, CELL_EDGES};
+use crate::palette::Palette;
+use crate::svo::SVO;
+use crate::vox::index_to_pos;
+use glam::Vec3;
+use std::{fmt::Write as FmtWrite, io};
+
+pub const GLB_MAGIC: u32 = 0x46546C67;
+pub const GLB_VERSION: u32 = 2;
+const GLB_CHUNK_JSON: u32 = 0x4E4F534A;
+const GLB_CHUNK_BIN: u32 = 0x004E4942;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Obj,
+    Ply,
+    // binary gltf
+    Glb,
+}
+
+// how the mesh indices are interpreted, points ignore the indices
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Topology {
+    Triangles,
+    Lines,
+    Points,
+}
+
+impl Topology {
+    fn index_count(&self) -> usize {
+        match self {
+            Topology::Triangles => 3,
+            Topology::Lines => 2,
+            Topology::Points => 1,
+        }
+    }
+}
+
+// leaf centers of a linear grid with their palette colors
+pub fn point_cloud(vox_grid: &[u8], grid_res: u32, palette: &Palette) -> Mesh {
+    let mut mesh = Mesh::new();
+
+    for (i, &mat) in vox_grid.iter().enumerate() {
+        if mat > 0 {
+            mesh.positions.push(index_to_pos(i as u32, grid_res).as_vec3() + Vec3::splat(0.5));
+            mesh.colors.push(palette.get(mat));
+        }
+    }
+
+    mesh
+}
+
+pub fn point_cloud_svo(svo: &SVO, palette: &Palette) -> Mesh {
+    let mut mesh = Mesh::new();
+    let voxel_size = svo.voxel_size();
+
+    svo.for_each_leaf(|pos, mat| {
+        mesh.positions.push((pos.as_vec3() + Vec3::splat(0.5)) * voxel_size);
+        mesh.colors.push(palette.get(mat as u8));
+    });
+
+    mesh
+}
+
+// bounding box of every octree node as line segments, for debugging the tree structure
+pub fn octree_wireframe(svo: &SVO) -> Mesh {
+    let mut mesh = Mesh::new();
+    let voxel_size = svo.voxel_size();
+
+    svo.for_each_node(|pos, cur_depth, _| {
+        let node_size = (1u32 << (svo.depth - cur_depth)) as f32 * voxel_size;
+        let first = mesh.positions.len() as u32;
+
+        for corner in 0..8 {
+            mesh.positions.push((pos + corner_offset(corner)).as_vec3() * node_size);
+        }
+
+        for (a, b) in CELL_EDGES {
+            mesh.indices.extend([first + a as u32, first + b as u32]);
+        }
+    });
+
+    mesh
+}
+
+fn check_mesh(mesh: &Mesh, topology: Topology) -> io::Result<()> {
+    let count = mesh.positions.len();
+
+    if (!mesh.normals.is_empty() && mesh.normals.len() != count) || (!mesh.colors.is_empty() && mesh.colors.len() != count) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "mesh attributes don't match the vertex count."));
+    }
+
+    if topology != Topology::Points && (!mesh.indices.len().is_multiple_of(topology.index_count()) || mesh.indices.iter().any(|&i| i as usize >= count)) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid mesh indices."));
+    }
+
+    Ok(())
+}
+
+pub fn encode_obj(mesh: &Mesh, topology: Topology) -> io::Result<Vec<u8>> {
+    check_mesh(mesh, topology)?;
+
+    let mut out = String::new();
+    let has_normals = !mesh.normals.is_empty() && topology == Topology::Triangles;
+
+    for (i, p) in mesh.positions.iter().enumerate() {
+        match mesh.colors.get(i) {
+            // vertex colors are a common obj extension understood by blender and meshlab
+            Some(c) => writeln!(out, "v {} {} {} {} {} {}", p.x, p.y, p.z, c[0] as f32 / 255.0, c[1] as f32 / 255.0, c[2] as f32 / 255.0),
+            None => writeln!(out, "v {} {} {}", p.x, p.y, p.z),
+        }.unwrap();
+    }
+
+    if has_normals {
+        for n in &mesh.normals {
+            writeln!(out, "vn {} {} {}", n.x, n.y, n.z).unwrap();
+        }
+    }
+
+    // obj indices are one based
+    match topology {
+        Topology::Triangles => {
+            for t in mesh.indices.chunks_exact(3) {
+                let (a, b, c) = (t[0] + 1, t[1] + 1, t[2] + 1);
+                if has_normals {
+                    writeln!(out, "f {a}//{a} {b}//{b} {c}//{c}").unwrap();
+                } else {
+                    writeln!(out, "f {a} {b} {c}").unwrap();
+                }
+            }
+        }
+        Topology::Lines => {
+            for l in mesh.indices.chunks_exact(2) {
+                writeln!(out, "l {} {}", l[0] + 1, l[1] + 1).unwrap();
+            }
+        }
+        Topology::Points => {
+            for i in 0..mesh.positions.len() {
+                writeln!(out, "p {}", i + 1).unwrap();
+            }
+        }
+    }
+
+    Ok(out.into_bytes())
+}
+
+// binary little endian ply, lines are written as edge elements
+pub fn encode_ply(mesh: &Mesh, topology: Topology) -> io::Result<Vec<u8>> {
+    check_mesh(mesh, topology)?;
+
+    let has_normals = !mesh.normals.is_empty();
+    let has_colors = !mesh.colors.is_empty();
+
+    let mut header = String::from("ply\nformat binary_little_endian 1.0\ncomment vss-rs export\n");
+    writeln!(header, "element vertex {}", mesh.positions.len()).unwrap();
+    header.push_str("property float x\nproperty float y\nproperty float z\n");
+    if has_normals {
+        header.push_str("property float nx\nproperty float ny\nproperty float nz\n");
+    }
+    if has_colors {
+        header.push_str("property uchar red\nproperty uchar green\nproperty uchar blue\nproperty uchar alpha\n");
+    }
+    match topology {
+        Topology::Triangles => {
+            writeln!(header, "element face {}", mesh.indices.len() / 3).unwrap();
+            header.push_str("property list uchar uint vertex_indices\n");
+        }
+        Topology::Lines => {
+            writeln!(header, "element edge {}", mesh.indices.len() / 2).unwrap();
+            header.push_str("property uint vertex1\nproperty uint vertex2\n");
+        }
+        Topology::Points => {}
+    }
+    header.push_str("end_header\n");
+
+    let mut out = header.into_bytes();
+
+    for i in 0..mesh.positions.len() {
+        for v in mesh.positions[i].to_array() {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        if has_normals {
+            for v in mesh.normals[i].to_array() {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        if has_colors {
+            out.extend_from_slice(&mesh.colors[i]);
+        }
+    }
+
+    match topology {
+        Topology::Triangles => {
+            for t in mesh.indices.chunks_exact(3) {
+                out.push(3);
+                for &i in t {
+                    out.extend_from_slice(&i.to_le_bytes());
+                }
+            }
+        }
+        Topology::Lines => {
+            for &i in &mesh.indices {
+                out.extend_from_slice(&i.to_le_bytes());
+            }
+        }
+        Topology::Points => {}
+    }
+
+    Ok(out)
+}
+
+fn pad_to_4(data: &mut Vec<u8>, byte: u8) {
+    while !data.len().is_multiple_of(4) {
+        data.push(byte);
+    }
+}
+
+pub fn encode_glb(mesh: &Mesh, topology: Topology) -> io::Result<Vec<u8>> {
+    check_mesh(mesh, topology)?;
+
+    let count = mesh.positions.len();
+    let mut bin = Vec::new();
+    let mut views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut attributes = Vec::new();
+
+    let (min, max) = mesh.positions.iter().fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), &p| (min.min(p), max.max(p)));
+    let (min, max) = if count == 0 { (Vec3::ZERO, Vec3::ZERO) } else { (min, max) };
+
+    // every attribute gets its own tightly packed buffer view
+    let mut push_view = |bin: &mut Vec<u8>, data: &[u8], target: u32| {
+        let offset = bin.len();
+        bin.extend_from_slice(data);
+        pad_to_4(bin, 0);
+        views.push(format!(r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}}}"#, offset, data.len(), target));
+        views.len() - 1
+    };
+
+    let positions: Vec<u8> = mesh.positions.iter().flat_map(|p| p.to_array()).flat_map(f32::to_le_bytes).collect();
+    let view = push_view(&mut bin, &positions, 34962);
+    accessors.push(format!(
+        r#"{{"bufferView":{},"componentType":5126,"count":{},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}}"#,
+        view, count, min.x, min.y, min.z, max.x, max.y, max.z
+    ));
+    attributes.push(format!(r#""POSITION":{}"#, accessors.len() - 1));
+
+    if !mesh.normals.is_empty() {
+        let normals: Vec<u8> = mesh.normals.iter().flat_map(|n| n.to_array()).flat_map(f32::to_le_bytes).collect();
+        let view = push_view(&mut bin, &normals, 34962);
+        accessors.push(format!(r#"{{"bufferView":{},"componentType":5126,"count":{},"type":"VEC3"}}"#, view, count));
+        attributes.push(format!(r#""NORMAL":{}"#, accessors.len() - 1));
+    }
+
+    if !mesh.colors.is_empty() {
+        let colors: Vec<u8> = mesh.colors.iter().flatten().copied().collect();
+        let view = push_view(&mut bin, &colors, 34962);
+        accessors.push(format!(r#"{{"bufferView":{},"componentType":5121,"normalized":true,"count":{},"type":"VEC4"}}"#, view, count));
+        attributes.push(format!(r#""COLOR_0":{}"#, accessors.len() - 1));
+    }
+
+    let mut primitive = format!(r#""attributes":{{{}}}"#, attributes.join(","));
+
+    if topology != Topology::Points {
+        let indices: Vec<u8> = mesh.indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let view = push_view(&mut bin, &indices, 34963);
+        accessors.push(format!(r#"{{"bufferView":{},"componentType":5125,"count":{},"type":"SCALAR"}}"#, view, mesh.indices.len()));
+        write!(primitive, r#","indices":{}"#, accessors.len() - 1).unwrap();
+    }
+
+    let mode = match topology {
+        Topology::Points => 0,
+        Topology::Lines => 1,
+        Topology::Triangles => 4,
+    };
+
+    let mut json = format!(
+        concat!(
+            r#"{{"asset":{{"version":"2.0","generator":"vss-rs"}},"scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],"#,
+            r#""meshes":[{{"primitives":[{{{},"mode":{}}}]}}],"accessors":[{}],"bufferViews":[{}],"buffers":[{{"byteLength":{}}}]}}"#
+        ),
+        primitive, mode, accessors.join(","), views.join(","), bin.len()
+    ).into_bytes();
+    pad_to_4(&mut json, b' ');
+
+    let total = 12 + 8 + json.len() + 8 + bin.len();
+    let mut out = Vec::with_capacity(total);
+
+    for v in [GLB_MAGIC, GLB_VERSION, total as u32, json.len() as u32, GLB_CHUNK_JSON] {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    out.extend_from_slice(&json);
+    out.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    out.extend_from_slice(&GLB_CHUNK_BIN.to_le_bytes());
+    out.extend_from_slice(&bin);
+
+    Ok(out)
+}
+
+pub fn write_mesh(filename: &str, mesh: &Mesh, topology: Topology, format: ExportFormat) -> io::Result<()> {
+    let data = match format {
+        ExportFormat::Obj => encode_obj(mesh, topology)?,
+        ExportFormat::Ply => encode_ply(mesh, topology)?,
+        ExportFormat::Glb => encode_glb(mesh, topology)?,
+    };
+
+    write_atomic(filename, &data, false)
+}
@@ -1,17 +1,63 @@
-use crate::bsvo::{read_bsvo, write_bsvo, write_empty_bsvo, BsvoHeader};
-use crate::bvox::{append_to_bvox, read_bvox, write_bvox, write_empty_bvox, BvoxHeader, DEFAULT_CHUNK_RES, DEFAULT_CHUNK_SIZE};
-use crate::svo::{DEFAULT_SVO_MAX_DEPTH, SVO};
-use crate::vox::{morton_decode_3d_grid, morton_encode_3d_grid, pos_to_index, DEFAULT_VOX_MAT};
-use glam::Vec3;
+use crate::atomic::{backup_path, write_atomic};
+use crate::binvox::{parse_binvox, read_binvox, read_binvox_svo, write_binvox, write_binvox_svo, BinvoxHeader};
+use crate::crc::{crc32, ChecksumError};
+use crate::verify::verify_file;
+use crate::deflate::{deflate, gzip_decode, inflate};
+use crate::nbt::{encode_nbt, parse_nbt, NbtTag, TAG_COMPOUND, TAG_INT};
+use crate::schematic::{parse_schematic, read_schematic, BlockMapping, Schematic};
+use crate::bsvo::{decode_bsvo, encode_bsvo, read_bsvo, write_bsvo, write_bsvo_with_backup, write_empty_bsvo, BsvoHeader, BSVO_HEADER_SIZE, BSVO_VERSION, NODE_SIZE};
+#[cfg(feature = "mmap")]
+use crate::bsvo::MappedBsvo;
+#[cfg(all(test, feature = "async"))]
+use crate::async_io::{get_bsvo_header_async, get_bvox_header_async, read_bsvo_async, read_bvox_async, write_bsvo_async, write_bvox_async};
+use crate::export::{encode_ply, octree_wireframe, point_cloud, point_cloud_svo, write_mesh, ExportFormat, Topology, GLB_MAGIC};
+use crate::magica::{encode_magica_vox, magica_default_palette, parse_magica_vox, read_magica_vox, write_magica_vox, MagicaInstance, MagicaModel, MagicaScene, IDENTITY_ROTATION};
+use crate::mesh::{block_mesh, block_mesh_svo, dual_contouring, marching_cubes, Mesh, VoxelField, DEFAULT_ISO_LEVEL};
+use crate::codec::{bit_pack, Codec};
+use crate::rle::{run_length_decode, run_length_decode_varint, run_length_decode_varint_u32, run_length_encode, run_length_encode_varint, run_length_encode_varint_u32};
+use crate::occupancy::OccupancyChunk;
+use crate::bvox::{append_to_bvox, append_to_bvox_with_codec, decode_bvox, encode_bvox, encode_bvox_chunk, read_bvox, remove_bvox_chunk, replace_bvox_chunk, write_bvox, write_bvox_with_backup, write_empty_bvox, BvoxHeader, BVOX_CHUNK_CHECKSUM_SIZE, BVOX_CHUNK_HEADER_SIZE, BVOX_HEADER_SIZE, BVOX_VERSION, DEFAULT_CHUNK_RES, DEFAULT_CHUNK_SIZE};
+use crate::palette::Palette;
+use crate::pointcloud::{parse_ply, parse_xyz, read_point_cloud, voxelize_points, voxelize_points_svo_with, voxelize_points_with};
+use crate::svo::{Octant, SvoIssue, SvoView, DEFAULT_SVO_MAT, DEFAULT_SVO_MAX_DEPTH, SVO};
+use crate::vdb::{encode_vdb, parse_vdb, read_vdb, write_vdb, VdbGrid, VdbValueType, VDB_MAGIC};
+use crate::region::{RegionFile, RegionStore, REGION_ENTRY_SIZE, REGION_HEADER_SIZE, REGION_SECTOR_SIZE};
+use crate::world::{ChunkStore, DirectoryStore, VoxelWorld, WorldChunk};
+use crate::voxelize::{rasterize_triangle, voxelize_solid_svo_with, voxelize_solid_with, voxelize_triangles_svo_with, voxelize_triangles_with, FillMode, GridTransform, VoxelizeMode};
+use crate::vox::{index_to_pos, morton_decode_3d_grid, morton_encode_3d_grid, pos_to_index, DEFAULT_VOX_MAT};
+use glam::{IVec3, UVec3, Vec3};
 use rand::distributions::{Bernoulli, Distribution};
 use rand::thread_rng;
+use std::collections::HashMap;
 use std::error::Error;
+use std::io;
 
+pub mod atomic;
+#[cfg(feature = "async")]
+pub mod async_io;
 pub mod bsvo;
 pub mod svo;
 pub mod vox;
 pub mod bvox;
 pub mod rle;
+pub mod mesh;
+pub mod voxelize;
+pub mod palette;
+pub mod magica;
+pub mod export;
+pub mod binvox;
+pub mod crc;
+pub mod deflate;
+pub mod nbt;
+pub mod schematic;
+pub mod pointcloud;
+pub mod vdb;
+pub mod codec;
+pub mod lz4;
+pub mod occupancy;
+pub mod verify;
+pub mod world;
+pub mod region;
 
 //
 // testing modules
@@ -102,6 +148,154 @@ pub fn test_bvox_compression() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+pub fn test_bvox_codecs() -> Result<(), Box<dyn Error>> {
+    let chunk_res = 64;
+    let chunk_size = chunk_res * chunk_res * chunk_res;
+
+    let random = gen_rand_vox_grid(chunk_size as usize, 0.1);
+    let mut sphere = gen_sphere_grid(chunk_res, 24.0);
+    for (i, v) in sphere.iter_mut().enumerate() {
+        if *v > 0 { *v = (i % 5) as u8 + 1; }
+    }
+    let chunk_data = vec![random.clone(), sphere, vec![0; chunk_size as usize]];
+
+    for codec in Codec::ALL.into_iter().chain([Codec::Auto]) {
+        assert_eq!(Codec::from_name(codec.name())?, codec);
+
+        let filename = format!("output/test_bvox_codec_{:?}.bvox", codec);
+        let header = BvoxHeader::new(chunk_res, chunk_size, false, false).with_codec(codec);
+        write_bvox(&filename, &chunk_data, header)?;
+
+        let (read_header, read_chunk_data) = read_bvox(&filename)?;
+        assert_eq!(read_header, header);
+        assert_eq!(read_chunk_data, chunk_data);
+    }
+
+    // 0/1 chunks pack into one bit per voxel, far below the byte pair rle
+    let (_, packed) = Codec::BitPacked.encode(&random);
+    let (_, rle) = Codec::Rle.encode(&random);
+    assert_eq!(packed.len(), 3 + chunk_size as usize / 8);
+    assert!(packed.len() * 2 < rle.len());
+    assert_eq!(Codec::Auto.encode(&random).0, Codec::Occupancy);
+    assert!(Codec::RleVarint.encode(&chunk_data[2]).1.len() < 8);
+    assert_eq!(bit_pack(&[7; 100]), vec![0, 7]);
+
+    // a deflate chunk that expands past the chunk size is rejected while inflating
+    let bomb = deflate(&vec![0; 1 << 24]);
+    assert_eq!(inflate(&bomb, 1 << 24)?.len(), 1 << 24);
+    assert!(inflate(&bomb, (1 << 24) - 1).is_err());
+    assert!(Codec::Deflate.decode(&bomb, chunk_size as usize).is_err());
+
+    // auto only exists for writing, a record claiming it is rejected by reads and verification alike
+    let mut data = std::fs::read("output/test_bvox_codec_Rle.bvox")?;
+    data[BVOX_HEADER_SIZE] = Codec::Auto as u8;
+    std::fs::write("output/test_bvox_auto_record.bvox", &data)?;
+    assert!(decode_bvox(&data).is_err());
+    assert!(read_bvox("output/test_bvox_auto_record.bvox").is_err());
+    assert!(verify_file("output/test_bvox_auto_record.bvox").is_err());
+
+    // chunks in one file can use different codecs
+    let header = BvoxHeader::new(chunk_res, chunk_size, false, true).with_codec(Codec::Lz4);
+    write_empty_bvox("output/test_bvox_mixed.bvox", header)?;
+    append_to_bvox("output/test_bvox_mixed.bvox", &chunk_data[1])?;
+    append_to_bvox_with_codec("output/test_bvox_mixed.bvox", &chunk_data[0], Codec::Deflate)?;
+    append_to_bvox_with_codec("output/test_bvox_mixed.bvox", &chunk_data[2], Codec::RleVarint)?;
+
+    let (_, read_chunk_data) = read_bvox("output/test_bvox_mixed.bvox")?;
+    assert_eq!(read_chunk_data, vec![chunk_data[1].clone(), chunk_data[0].clone(), chunk_data[2].clone()]);
+
+    Ok(())
+}
+
+pub fn test_occupancy() -> Result<(), Box<dyn Error>> {
+    let chunk_res = 64;
+    let chunk_size = chunk_res * chunk_res * chunk_res;
+    let depth = 6;
+
+    let grid = gen_rand_vox_grid(chunk_size as usize, 0.1);
+    let mut morton_grid = vec![0; chunk_size as usize];
+    morton_encode_3d_grid(&grid, chunk_res, chunk_size, &mut morton_grid);
+
+    let occupancy = OccupancyChunk::from_grid(&morton_grid, chunk_res, true)?;
+    let filled = grid.iter().filter(|&&v| v > 0).count();
+    assert_eq!(occupancy.count(), filled);
+    assert_eq!(occupancy.to_grid(DEFAULT_VOX_MAT), morton_grid);
+    assert_eq!(occupancy.to_linear(), OccupancyChunk::from_grid(&grid, chunk_res, false)?);
+    assert_eq!(occupancy.to_linear().to_morton(), occupancy);
+
+    // popcount statistics against a plain count of the bytes
+    assert_eq!(occupancy.count_range(13, 1000), morton_grid[13..1000].iter().filter(|&&v| v > 0).count());
+    assert_eq!(occupancy.count_range(64, 128), morton_grid[64..128].iter().filter(|&&v| v > 0).count());
+    let counts = occupancy.node_counts(1)?;
+    assert_eq!(counts.len(), 8);
+    assert_eq!(counts.iter().sum::<usize>(), filled);
+    assert_eq!(counts[7], morton_grid[7 * chunk_size as usize / 8..].iter().filter(|&&v| v > 0).count());
+    assert!(occupancy.to_linear().node_counts(1).is_err());
+    assert_eq!(occupancy.node_counts(depth as u32)?.len(), chunk_size as usize);
+    assert!(occupancy.node_counts(depth as u32 + 1).is_err());
+    assert!(occupancy.node_counts(32).is_err());
+    assert!(occupancy.node_counts(u32::MAX).is_err());
+
+    let pos = UVec3::new(3, 40, 17);
+    assert_eq!(occupancy.get_voxel(pos), grid[pos_to_index(pos.x, pos.y, pos.z, chunk_res) as usize] > 0);
+
+    // the octree matches the one built from the byte grid
+    let svo = SVO::from_occupancy(&occupancy, depth, DEFAULT_VOX_MAT as u32);
+    assert_eq!(svo.nodes, SVO::from_grid(&morton_grid, chunk_res, depth).nodes);
+    assert_eq!(SVO::from_occupancy(&occupancy.to_linear(), depth, 1).count_leaf_nodes() as usize, filled);
+
+    let bytes = occupancy.to_bytes();
+    assert_eq!(bytes.len(), chunk_size as usize / 8);
+    assert_eq!(OccupancyChunk::from_bytes(&bytes, chunk_res, true)?, occupancy);
+
+    // short grids and resolutions whose voxel count overflows are errors instead of panics
+    assert!(OccupancyChunk::from_grid(&morton_grid[1..], chunk_res, true).is_err());
+    assert!(OccupancyChunk::from_grid(&morton_grid, chunk_res / 2, true).is_err());
+    assert!(OccupancyChunk::new(0, false).is_err());
+    assert!(OccupancyChunk::new(1 << 11, false).is_err());
+    assert!(OccupancyChunk::from_bytes(&[], u32::MAX, false).is_err());
+
+    // one material chunks are stored with a bit per voxel, mixed ones fall back to bit packing
+    let mut mixed = morton_grid.clone();
+    mixed[0] = 9;
+    let header = BvoxHeader::new(chunk_res, chunk_size, false, true).with_codec(Codec::Occupancy);
+    write_bvox("output/test_occupancy.bvox", &[morton_grid.clone(), mixed.clone()], header)?;
+
+    let data = std::fs::read("output/test_occupancy.bvox")?;
+    assert_eq!(data[BVOX_HEADER_SIZE], Codec::Occupancy as u8);
+    assert_eq!(data[BVOX_HEADER_SIZE + BVOX_CHUNK_HEADER_SIZE + 1 + bytes.len()], Codec::BitPacked as u8);
+    assert_eq!(read_bvox("output/test_occupancy.bvox")?.1, vec![morton_grid, mixed]);
+
+    Ok(())
+}
+
+pub fn test_rle() -> Result<(), Box<dyn Error>> {
+    assert!(run_length_encode(&[]).is_empty());
+    assert!(run_length_decode(&[])?.is_empty());
+    assert!(run_length_encode_varint(&[]).is_empty());
+    assert!(run_length_decode_varint(&[], 0)?.is_empty());
+
+    // an empty chunk is a single pair instead of one pair per 254 voxels
+    let empty = vec![0u8; CHUNK_SIZE as usize];
+    assert_eq!(run_length_encode(&empty).len(), CHUNK_SIZE.div_ceil(254) as usize * 2);
+    assert_eq!(run_length_encode_varint(&empty), vec![0, 0x80, 0x80, 0x80, 0x08]);
+    assert_eq!(run_length_decode_varint(&run_length_encode_varint(&empty), CHUNK_SIZE as usize)?, empty);
+    assert!(run_length_decode_varint(&[0, 0x80, 0x80, 0x80, 0x08], 1000).is_err());
+
+    let data = [5u8, 5, 5, 255, 0, 0, 1];
+    assert_eq!(run_length_encode_varint(&data), vec![5, 3, 255, 1, 0, 2, 1, 1]);
+    assert_eq!(run_length_decode_varint(&run_length_encode_varint(&data), data.len())?, data);
+
+    // node values don't fit into a byte
+    let nodes = [0u32, 0, 0, 0x0100_0009, 0x0100_0009, 1];
+    let encoded = run_length_encode_varint_u32(&nodes);
+    assert_eq!(encoded.len(), 9);
+    assert_eq!(run_length_decode_varint_u32(&encoded, nodes.len())?, nodes);
+    assert!(run_length_encode_varint_u32(&[]).is_empty());
+
+    Ok(())
+}
+
 pub fn test_bsvo_read_write() -> Result<(), Box<dyn Error>> {
     let chunk = gen_rand_vox_grid(CHUNK_SIZE as usize, 0.1);
 
@@ -120,11 +314,14 @@ pub fn test_bsvo_read_write() -> Result<(), Box<dyn Error>> {
     let bsvo_header = BsvoHeader::new(svo.depth, svo.root_span, true);
     write_bsvo("output/test_bsvo_rw.bsvo", &svo, bsvo_header)?;
 
-    let (_, read_svo) = read_bsvo("output/test_bsvo_rw.bsvo")?;
+    let (read_header, read_svo) = read_bsvo("output/test_bsvo_rw.bsvo")?;
+    assert!(read_header.run_length_encoded);
+    assert_eq!(svo.nodes, read_svo.nodes);
+    assert_eq!(svo.depth, read_svo.depth);
 
-    for i in 0..read_svo.nodes.len() {
-        assert_eq!(svo.nodes[i], read_svo.nodes[i]);
-    }
+    // the rle node stream is smaller than the raw nodes
+    let raw_size = svo.nodes.len() * NODE_SIZE;
+    assert!((std::fs::metadata("output/test_bsvo_rw.bsvo")?.len() as usize) < raw_size);
 
     let svo_node_count = svo.count_leaf_nodes();
     let chunk_node_count = chunk.iter().filter(|&&v| v > 0).count() as u32;
@@ -133,6 +330,696 @@ pub fn test_bsvo_read_write() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+pub fn test_bsvo_pointerless() -> Result<(), Box<dyn Error>> {
+    let chunk_res = 64;
+    let chunk = gen_sphere_grid(chunk_res, 28.0);
+    let mut morton_chunk = vec![0u8; chunk.len()];
+    morton_encode_3d_grid(&chunk, chunk_res, chunk.len() as u32, &mut morton_chunk);
+    let sphere = SVO::from_grid(&morton_chunk, chunk_res, 6);
+
+    let mut random = SVO::new(6);
+    random.gen_random_svo(3);
+
+    for (name, svo) in [("sphere", &sphere), ("random", &random)] {
+        let mut expected = Vec::new();
+        svo.for_each_node(|pos, depth, node| expected.push((pos, depth, node.child_mask(), if node.has_children() { 0 } else { node })));
+
+        for rle in [false, true] {
+            let filename = format!("output/pointerless_{name}_{rle}.bsvo");
+            let bsvo_header = BsvoHeader::new(svo.depth, svo.root_span, rle).with_pointerless(true);
+            write_bsvo(&filename, svo, bsvo_header)?;
+
+            let (read_header, read_svo) = read_bsvo(&filename)?;
+            assert!(read_header.pointerless);
+            assert_eq!(read_svo.depth, svo.depth);
+
+            // pointers are rebuilt in breadth first order, so compare the tree instead of the node array
+            let mut read = Vec::new();
+            read_svo.for_each_node(|pos, depth, node| read.push((pos, depth, node.child_mask(), if node.has_children() { 0 } else { node })));
+            assert_eq!(read, expected);
+
+            // writing the rebuilt tree again gives the same stream
+            let rewritten = format!("output/pointerless_{name}_{rle}_rewritten.bsvo");
+            write_bsvo(&rewritten, &read_svo, bsvo_header)?;
+            assert_eq!(std::fs::read(&filename)?, std::fs::read(&rewritten)?);
+        }
+    }
+
+    // at least 4x smaller than the raw nodes
+    let raw_size = sphere.nodes.len() * NODE_SIZE;
+    assert!(std::fs::metadata("output/pointerless_sphere_false.bsvo")?.len() as usize * 4 <= raw_size);
+
+    Ok(())
+}
+
+pub fn test_bsvo_view() -> Result<(), Box<dyn Error>> {
+    let chunk_res = 32;
+    let chunk = gen_sphere_grid(chunk_res, 12.0);
+    let mut morton_chunk = vec![0u8; chunk.len()];
+    morton_encode_3d_grid(&chunk, chunk_res, chunk.len() as u32, &mut morton_chunk);
+    let svo = SVO::from_grid(&morton_chunk, chunk_res, 5);
+
+    write_bsvo("output/view_sphere.bsvo", &svo, BsvoHeader::new(svo.depth, svo.root_span, false))?;
+    write_bsvo("output/view_sphere_rle.bsvo", &svo, BsvoHeader::new(svo.depth, svo.root_span, true))?;
+
+    let check_view = |view: &SvoView| {
+        assert_eq!(view.nodes.as_ref(), svo.nodes.as_slice());
+        assert_eq!(view.count_leaf_nodes(), svo.count_leaf_nodes());
+        for (i, &v) in chunk.iter().enumerate() {
+            assert_eq!(view.get_voxel(index_to_pos(i as u32, chunk_res)), v as u32);
+        }
+    };
+
+    // shifted by a byte so the nodes are usually not aligned for u32 and get copied
+    let mut shifted = vec![0u8];
+    shifted.extend(std::fs::read("output/view_sphere.bsvo")?);
+    let (_, view) = decode_bsvo(&shifted[1..])?;
+    check_view(&view);
+
+    let rle = std::fs::read("output/view_sphere_rle.bsvo")?;
+    let (_, view) = decode_bsvo(&rle)?;
+    assert!(!view.is_borrowed());
+    check_view(&view);
+
+    #[cfg(feature = "mmap")]
+    {
+        let mapped = MappedBsvo::open("output/view_sphere.bsvo")?;
+        let view = mapped.view();
+        assert_eq!(mapped.is_zero_copy(), cfg!(target_endian = "little"));
+        assert!(view.is_borrowed());
+        check_view(&view);
+
+        let mapped = MappedBsvo::open("output/view_sphere_rle.bsvo")?;
+        assert!(mapped.header.run_length_encoded);
+        assert!(!mapped.is_zero_copy());
+        check_view(&mapped.view());
+
+        // a child pointer past the end of the mapping reads as empty instead of panicking
+        let corrupt = SVO { nodes: vec![0xFF00_0100, 0, 0, 0, 0, 0, 0, 0, 0], depth: 2, root_span: 4.0 };
+        write_bsvo("output/view_corrupt.bsvo", &corrupt, BsvoHeader::new(2, 4.0, false))?;
+        let mapped = MappedBsvo::open("output/view_corrupt.bsvo")?;
+        let view = mapped.view();
+        assert_eq!(view.get_voxel(UVec3::new(3, 3, 3)), 0);
+        let mut nodes = 0;
+        view.for_each_node(|_, _, _| nodes += 1);
+        assert_eq!(nodes, 1);
+        assert!(!view.validate().is_valid());
+    }
+
+    Ok(())
+}
+
+// fixtures are written out byte by byte so they don't depend on the host, swapping every multi byte field
+// gives what a native dump on a big endian host would look like
+pub fn test_endianness() -> Result<(), Box<dyn Error>> {
+    let swap_words = |bytes: &[u8]| -> Vec<u8> { bytes.chunks(4).flat_map(|w| w.iter().rev().copied()).collect() };
+
+    // depth 1 tree with a single leaf in child 0
+    let mut svo = SVO::new(1);
+    svo.insert_voxel(UVec3::ZERO, 1)?;
+    assert_eq!(svo.nodes[..2], [0x0100_0001, 1]);
+
+    let bsvo_header = BsvoHeader::new(svo.depth, svo.root_span, false);
+    let header_fixture = [BSVO_VERSION, 0, 0, 0, 1, 0, 0, 0, 0x00, 0x00, 0x00, 0x40, 0, 0, 0, 0];
+    let mut nodes_fixture = vec![0x01, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00];
+    nodes_fixture.resize(9 * NODE_SIZE, 0);
+
+    let fixture = [header_fixture.as_slice(), &nodes_fixture].concat();
+    assert_eq!(encode_bsvo(&svo, bsvo_header), fixture);
+
+    let (read_header, view) = decode_bsvo(&fixture)?;
+    assert_eq!(read_header, bsvo_header);
+    assert_eq!(view.nodes.as_ref(), svo.nodes.as_slice());
+
+    // a big endian dump decodes to the swapped values on every host instead of being read natively
+    let mut swapped_header = header_fixture;
+    swapped_header[0x08..0x0C].reverse();
+    let swapped = [swapped_header.as_slice(), &swap_words(&nodes_fixture)].concat();
+    let (read_header, view) = decode_bsvo(&swapped)?;
+    assert_eq!(read_header.root_span.to_bits(), svo.root_span.to_bits().swap_bytes());
+    assert_eq!(view.nodes.iter().map(|n| n.swap_bytes()).collect::<Vec<_>>(), svo.nodes);
+
+    // run length encoded and pointerless streams only contain bytes and explicit little endian lengths
+    for header in [BsvoHeader::new(svo.depth, svo.root_span, true), bsvo_header.with_pointerless(true)] {
+        let data = encode_bsvo(&svo, header);
+        assert_eq!(data[BSVO_HEADER_SIZE..BSVO_HEADER_SIZE + 4], (if header.pointerless { 1u32 } else { 9 }).to_le_bytes());
+        assert_eq!(decode_bsvo(&data)?.1.nodes.as_ref(), svo.nodes.as_slice());
+    }
+
+    // 2^3 chunk without a codec
+    let chunk: Vec<u8> = (1..=8).collect();
+    let bvox_header = BvoxHeader::new(2, 8, false, true);
+    let mut fixture = vec![BVOX_VERSION, 0, 0, 0, 2, 0, 0, 0, 8, 0, 0, 0, 0, 1, 0, 0];
+    fixture.extend([0, 8, 0, 0, 0]);
+    fixture.extend(&chunk);
+
+    assert_eq!(encode_bvox(std::slice::from_ref(&chunk), bvox_header)?, fixture);
+    let (read_header, chunks) = decode_bvox(&fixture)?;
+    assert_eq!(read_header, bvox_header);
+    assert_eq!(chunks, vec![chunk]);
+
+    let mut swapped = fixture.clone();
+    swapped[0x04..0x0C].copy_from_slice(&swap_words(&fixture[0x04..0x0C]));
+    let swapped_header = BvoxHeader::from_bytes(swapped[..BVOX_HEADER_SIZE].try_into()?)?;
+    assert_eq!((swapped_header.chunk_res, swapped_header.chunk_size), (2u32.swap_bytes(), 8u32.swap_bytes()));
+
+    // the swapped chunk length no longer matches the data
+    swapped[0x11..0x15].reverse();
+    assert!(decode_bvox(&swapped).is_err());
+
+    Ok(())
+}
+
+pub fn test_svo_validate() -> Result<(), Box<dyn Error>> {
+    let chunk_res = 16;
+    let chunk = gen_sphere_grid(chunk_res, 6.0);
+    let mut morton_chunk = vec![0u8; chunk.len()];
+    morton_encode_3d_grid(&chunk, chunk_res, chunk.len() as u32, &mut morton_chunk);
+    let svo = SVO::from_grid(&morton_chunk, chunk_res, 4);
+
+    let report = svo.validate();
+    assert!(report.is_valid());
+    assert!(report.issues.is_empty());
+    assert_eq!(report.leaves, svo.count_leaf_nodes() as usize);
+    assert!(SVO::new(4).validate().issues.is_empty());
+
+    // index of the first existing child of a node
+    let first_child = |svo: &SVO, idx: usize| (svo.nodes[idx].first_child_index() + svo.nodes[idx].child_mask().trailing_zeros()) as usize;
+    let depth_1 = first_child(&svo, 0);
+    let depth_2 = first_child(&svo, depth_1);
+    let mut leaf = depth_2;
+    while svo.nodes[leaf].has_children() {
+        leaf = first_child(&svo, leaf);
+    }
+
+    let len = svo.nodes.len();
+    type IssueCheck = fn(&SvoIssue) -> bool;
+    let corruptions: [(usize, u32, IssueCheck); 5] = [
+        (depth_1, svo.nodes[depth_1].set_first_child_index(len as u32 - 4), |i| matches!(i, SvoIssue::PointerOutOfRange { .. })),
+        (depth_1, svo.nodes[depth_1].set_first_child_index(0), |i| matches!(i, SvoIssue::SharedChild { .. })),
+        (depth_2, DEFAULT_SVO_MAT, |i| matches!(i, SvoIssue::LeafAboveDepth { depth: 2, .. })),
+        (leaf, 0, |i| matches!(i, SvoIssue::EmptyChild { .. })),
+        (leaf, 0u32.set_child(0), |i| matches!(i, SvoIssue::ChildrenAtMaxDepth { .. })),
+    ];
+
+    for (idx, node, expected) in corruptions {
+        let mut corrupt = SVO { nodes: svo.nodes.clone(), depth: svo.depth, root_span: svo.root_span };
+        corrupt.nodes[idx] = node;
+
+        let report = corrupt.validate();
+        assert!(!report.is_valid());
+        assert!(report.issues.iter().any(expected), "{:?}", report.issues);
+
+        // the dropped subtrees leave unreachable nodes that are removed as well
+        assert_eq!(corrupt.repair(), report);
+        let repaired = corrupt.validate();
+        assert!(repaired.issues.is_empty(), "{:?}", repaired.issues);
+        assert!(repaired.leaves < svo.count_leaf_nodes() as usize);
+
+        // every voxel left is one of the original voxels
+        corrupt.for_each_leaf(|pos, mat| assert_eq!(svo.get_voxel(pos), mat));
+    }
+
+    Ok(())
+}
+
+pub fn test_checksums() -> Result<(), Box<dyn Error>> {
+    let chunk_res = 16;
+    let chunk_size = chunk_res * chunk_res * chunk_res;
+    let chunks: Vec<Vec<u8>> = (0..3).map(|i| gen_sphere_grid(chunk_res, 3.0 + i as f32)).collect();
+
+    let checksum_error = |result: io::Result<()>| result.err().and_then(|e| ChecksumError::from_io(&e).copied());
+
+    let header = BvoxHeader::new(chunk_res, chunk_size, false, false).with_codec(Codec::Auto).with_checksums(true, true);
+    write_empty_bvox("output/checksums.bvox", header)?;
+    for chunk in &chunks {
+        append_to_bvox("output/checksums.bvox", chunk)?;
+    }
+    verify_file("output/checksums.bvox")?;
+    assert_eq!(std::fs::read("output/checksums.bvox")?, encode_bvox(&chunks, header)?);
+    assert_eq!(temp_files("output/checksums.bvox")?, 0);
+
+    // the trailer isn't rewritten in place, a failed append leaves the whole file as it was
+    assert!(append_to_bvox("output/checksums.bvox", &chunks[0][1..]).is_err());
+    assert_eq!(std::fs::read("output/checksums.bvox")?, encode_bvox(&chunks, header)?);
+
+    let (read_header, read_chunks) = read_bvox("output/checksums.bvox")?;
+    assert_eq!(read_header, header);
+    assert_eq!(read_chunks, chunks);
+
+    // a truncated download misses the file checksum even when it ends on a chunk boundary
+    let data = std::fs::read("output/checksums.bvox")?;
+    let last_record = encode_bvox_chunk(&chunks[2], &header, header.codec)?.len();
+    let mut truncated = data[..data.len() - 4 - last_record].to_vec();
+    truncated.extend_from_slice(&data[data.len() - 4..]);
+    std::fs::write("output/checksums_truncated.bvox", &truncated)?;
+    assert_eq!(checksum_error(verify_file("output/checksums_truncated.bvox")).map(|e| e.chunk), Some(None));
+
+    // bit rot in a chunk payload
+    let header = header.with_checksums(true, false);
+    let mut data = encode_bvox(&chunks, header)?;
+    let second_payload = BVOX_HEADER_SIZE + encode_bvox_chunk(&chunks[0], &header, header.codec)?.len() + BVOX_CHUNK_HEADER_SIZE + BVOX_CHUNK_CHECKSUM_SIZE;
+    data[second_payload] ^= 0x10;
+    std::fs::write("output/checksums_rot.bvox", &data)?;
+    let error = checksum_error(verify_file("output/checksums_rot.bvox")).unwrap();
+    assert_eq!(error.chunk, Some(1));
+    assert!(read_bvox("output/checksums_rot.bvox").is_err());
+
+    let mut svo = SVO::new(4);
+    svo.gen_random_svo(1);
+    for bsvo_header in [BsvoHeader::new(svo.depth, svo.root_span, false), BsvoHeader::new(svo.depth, svo.root_span, true).with_pointerless(true)] {
+        let bsvo_header = bsvo_header.with_checksum(true);
+        write_bsvo("output/checksums.bsvo", &svo, bsvo_header)?;
+        verify_file("output/checksums.bsvo")?;
+        assert_eq!(read_bsvo("output/checksums.bsvo")?.0, bsvo_header);
+
+        let mut data = std::fs::read("output/checksums.bsvo")?;
+        data[BSVO_HEADER_SIZE + 2] ^= 0x01;
+        std::fs::write("output/checksums_rot.bsvo", &data)?;
+        assert_eq!(checksum_error(verify_file("output/checksums_rot.bsvo")).map(|e| e.chunk), Some(None));
+        assert!(read_bsvo("output/checksums_rot.bsvo").is_err());
+    }
+
+    assert!(verify_file("output/checksums.vox").is_err());
+
+    Ok(())
+}
+
+pub fn test_svo_chunks() -> Result<(), Box<dyn Error>> {
+    let chunk_res = 16;
+    let world_res = 48;
+    let sphere = gen_sphere_grid(world_res, 20.0);
+
+    // 3^3 chunks starting at a negative chunk coordinate
+    let min_coord = IVec3::new(-2, -1, 0);
+    let mut chunks = Vec::new();
+    for coord in (0..27).map(|i| IVec3::new(i % 3, (i / 3) % 3, i / 9)) {
+        let mut chunk = vec![0u8; (chunk_res * chunk_res * chunk_res) as usize];
+        for (i, v) in chunk.iter_mut().enumerate() {
+            let pos = index_to_pos(i as u32, chunk_res) + coord.as_uvec3() * chunk_res;
+            *v = sphere[pos_to_index(pos.x, pos.y, pos.z, world_res) as usize] * (1 + (i % 3) as u8);
+        }
+        if chunk.iter().any(|&v| v > 0) {
+            chunks.push((coord + min_coord, chunk));
+        }
+    }
+
+    // chunk coordinates aren't part of bvox, they travel next to the file
+    let header = BvoxHeader::new(chunk_res, chunk_res * chunk_res * chunk_res, false, false).with_codec(Codec::Auto);
+    write_bvox("output/svo_chunks.bvox", &chunks.iter().map(|(_, c)| c.clone()).collect::<Vec<_>>(), header)?;
+    let (_, read_chunks) = read_bvox("output/svo_chunks.bvox")?;
+    let world: Vec<(IVec3, Vec<u8>)> = chunks.iter().map(|(coord, _)| *coord).zip(read_chunks).collect();
+
+    let (svo, origin) = SVO::from_chunks(&world, chunk_res, false)?;
+    assert_eq!(origin, min_coord * chunk_res as i32);
+    // 3 chunks per axis round up to 4
+    assert_eq!(svo.depth, 6);
+    assert!(svo.validate().issues.is_empty());
+
+    for (coord, chunk) in &world {
+        for (i, &v) in chunk.iter().enumerate() {
+            let pos = (*coord * chunk_res as i32 - origin).as_uvec3() + index_to_pos(i as u32, chunk_res);
+            assert_eq!(svo.get_voxel(pos), v as u32);
+        }
+    }
+
+    assert_eq!(svo.to_chunks(chunk_res, origin, false)?, world);
+
+    // morton chunks of a different resolution cover the same voxels
+    let morton_chunks = svo.to_chunks(8, origin, true)?;
+    let (morton_svo, morton_origin) = SVO::from_chunks(&morton_chunks, 8, true)?;
+    assert_eq!(morton_svo.to_chunks(chunk_res, morton_origin, false)?, world);
+
+    assert!(SVO::from_chunks(&world, 12, false).is_err());
+
+    // chunks too far apart for one octree, and resolutions whose volume overflows
+    let far = vec![(IVec3::MIN, vec![1u8; 8]), (IVec3::MAX, vec![1u8; 8])];
+    assert!(SVO::from_chunks(&far, 2, false).is_err());
+    assert!(SVO::from_chunks(&world, 1 << 30, false).is_err());
+    assert!(svo.to_chunks(1 << 30, origin, false).is_err());
+
+    Ok(())
+}
+
+pub fn test_voxel_world() -> Result<(), Box<dyn Error>> {
+    let chunk_res = 16;
+    let dir = "output/world";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir)?;
+    }
+
+    let mut world = VoxelWorld::with_store(chunk_res, Box::new(DirectoryStore::new(dir, chunk_res)?))?;
+
+    // a wall crossing the chunk boundaries at x = 0 and z = 0
+    for y in -16..16 {
+        for z in -16..16 {
+            world.set(IVec3::new(-1, y, z), 3)?;
+        }
+    }
+    world.set(IVec3::new(40, 5, -40), 7)?;
+    assert_eq!(world.get(IVec3::new(-1, -16, 15))?, 3);
+    assert_eq!(world.get(IVec3::new(0, 0, 0))?, 0);
+    assert_eq!(world.dirty_chunks().count(), 2 * 2 + 1);
+
+    // rays cross empty chunks and hit the wall from both sides
+    let hit = world.raycast(Vec3::new(30.5, 2.5, 3.5), Vec3::new(-1.0, 0.0, 0.0), 100.0)?.unwrap();
+    assert_eq!((hit.pos, hit.mat, hit.normal), (IVec3::new(-1, 2, 3), 3, IVec3::X));
+    assert!((hit.distance - 30.5).abs() < 1e-4);
+
+    let hit = world.raycast(Vec3::new(-30.5, -14.5, -14.5), Vec3::new(1.0, 1.0, 1.0), 100.0)?.unwrap();
+    assert_eq!((hit.pos.x, hit.mat, hit.normal), (-1, 3, IVec3::NEG_X));
+    assert!(world.raycast(Vec3::new(30.5, 2.5, 3.5), Vec3::new(1.0, 0.0, 0.0), 100.0)?.is_none());
+    assert!(world.raycast(Vec3::new(30.5, 2.5, 3.5), Vec3::new(-1.0, 0.0, 0.0), 20.0)?.is_none());
+
+    world.compact()?;
+    assert!(matches!(world.chunk(IVec3::new(2, 0, -3))?, Some(WorldChunk::Svo(_))));
+
+    assert_eq!(world.save()?, 5);
+    assert_eq!(world.dirty_chunks().count(), 0);
+    assert_eq!(std::fs::read_dir(dir)?.count(), 5);
+
+    // clearing a voxel of an svo chunk turns it dense, an emptied chunk is removed from the store
+    world.set(IVec3::new(40, 5, -40), 0)?;
+    assert!(matches!(world.chunk(IVec3::new(2, 0, -3))?, Some(WorldChunk::Dense(_))));
+    assert!(world.is_dirty(IVec3::new(2, 0, -3)));
+    world.unload_outside(IVec3::ZERO, 1)?;
+    assert!(!world.is_loaded(IVec3::new(2, 0, -3)));
+    assert_eq!(world.loaded_chunks().count(), 4);
+    assert_eq!(std::fs::read_dir(dir)?.count(), 4);
+
+    // a fresh world pages the chunks back in lazily
+    let mut world = VoxelWorld::with_store(chunk_res, Box::new(DirectoryStore::new(dir, chunk_res)?))?;
+    assert_eq!(world.loaded_chunks().count(), 0);
+
+    // inserted chunks and the world resolution are checked instead of panicking later
+    assert!(world.insert_chunk(IVec3::new(9, 9, 9), WorldChunk::Dense(vec![1; 15])).is_err());
+    assert!(world.insert_chunk(IVec3::new(9, 9, 9), WorldChunk::Svo(SVO::new(3))).is_err());
+    assert!(!world.is_loaded(IVec3::new(9, 9, 9)));
+    assert!(VoxelWorld::new(0).is_err());
+    assert!(VoxelWorld::new(12).is_err());
+    assert!(VoxelWorld::new(2048).is_err());
+    let mut memory = VoxelWorld::new(4)?;
+    memory.insert_chunk(IVec3::new(-1, 0, 0), WorldChunk::Dense(vec![2; 64]))?;
+    assert_eq!(memory.get(IVec3::new(-4, 3, 3))?, 2);
+    assert_eq!(world.get(IVec3::new(-1, 7, -7))?, 3);
+    assert_eq!(world.loaded_chunks().count(), 1);
+    assert_eq!(world.get(IVec3::new(40, 5, -40))?, 0);
+    assert!(world.chunk(IVec3::new(2, 0, -3))?.is_none());
+
+    let hit = world.raycast(Vec3::new(-0.5, 50.5, 10.5), Vec3::new(0.0, -1.0, 0.0), 100.0)?.unwrap();
+    assert_eq!((hit.pos, hit.normal), (IVec3::new(-1, 15, 10), IVec3::Y));
+
+    // unbounded rays are refused instead of stepping through empty chunks forever
+    for max_distance in [f32::INFINITY, f32::NAN, 1e30] {
+        let err = world.raycast(Vec3::new(30.5, 2.5, 3.5), Vec3::new(1.0, 0.0, 0.0), max_distance).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    // an svo of another depth than the chunk resolution is not a valid chunk
+    write_bsvo(&format!("{}/9_9_9.bsvo", dir), &SVO::new(5), BsvoHeader::new(5, 32.0, false))?;
+    assert_eq!(world.chunk(IVec3::new(9, 9, 9)).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    Ok(())
+}
+
+pub fn test_region() -> Result<(), Box<dyn Error>> {
+    std::fs::create_dir_all("output")?;
+    let filename = "output/test.region";
+    let mut region = RegionFile::create(filename, 4)?;
+    let table = region.sector_count();
+
+    let small = vec![1u8; 100];
+    let large = vec![2u8; REGION_SECTOR_SIZE * 3 + 1];
+    region.write_chunk(UVec3::new(0, 0, 0), &small)?;
+    region.write_chunk(UVec3::new(3, 2, 1), &large)?;
+    region.write_chunk(UVec3::new(1, 1, 1), &small)?;
+    assert_eq!(region.sector_count(), table + 1 + 4 + 1);
+    assert!(region.write_chunk(UVec3::new(4, 0, 0), &small).is_err());
+
+    // rewritten chunks go to free sectors first and leave their old sectors as holes for later chunks
+    region.write_chunk(UVec3::new(3, 2, 1), &small)?;
+    assert_eq!(region.free_sectors(), 4);
+    assert_eq!(region.sector_count(), table + 7);
+    region.write_chunk(UVec3::new(0, 0, 0), &vec![3u8; REGION_SECTOR_SIZE * 2])?;
+    assert_eq!(region.free_sectors(), 3);
+    assert_eq!(region.sector_count(), table + 7);
+
+    assert!(region.remove_chunk(UVec3::new(1, 1, 1))?);
+    assert!(!region.remove_chunk(UVec3::new(1, 1, 1))?);
+    assert_eq!(region.len(), 2);
+    assert_eq!(region.free_sectors(), 4);
+
+    let mut region = RegionFile::open(filename)?;
+    assert_eq!(region.chunks(), vec![UVec3::new(0, 0, 0), UVec3::new(3, 2, 1)]);
+    assert_eq!(region.read_chunk(UVec3::new(3, 2, 1))?, Some(small.clone()));
+    assert_eq!(region.read_chunk(UVec3::new(1, 1, 1))?, None);
+
+    let free = region.free_sectors();
+    assert_eq!(region.compact()?, (free * REGION_SECTOR_SIZE) as u64);
+    assert_eq!(region.free_sectors(), 0);
+    assert_eq!(std::fs::metadata(filename)?.len(), ((table + 3) * REGION_SECTOR_SIZE) as u64);
+    assert_eq!(temp_files("output/test.region")?, 0);
+
+    // the compacted region stays usable through the same handle
+    region.write_chunk(UVec3::new(1, 1, 1), &small)?;
+    assert_eq!(region.sector_count(), table + 4);
+
+    let mut region = RegionFile::open(filename)?;
+    assert_eq!(region.read_chunk(UVec3::new(0, 0, 0))?, Some(vec![3u8; REGION_SECTOR_SIZE * 2]));
+    assert_eq!(region.read_chunk(UVec3::new(3, 2, 1))?, Some(small));
+
+    // region sizes outside 1..=256 and table entries past the end of the file are rejected
+    assert_eq!(RegionFile::create("output/test_invalid.region", 0).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(RegionFile::create("output/test_invalid.region", 257).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+
+    let mut corrupt = std::fs::read(filename)?;
+    corrupt[REGION_HEADER_SIZE + 5 * REGION_ENTRY_SIZE..][..REGION_ENTRY_SIZE].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 100, 0, 0, 0]);
+    std::fs::write("output/test_invalid.region", corrupt)?;
+    assert_eq!(RegionFile::open("output/test_invalid.region").err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+    // a world paged through regions of 4^3 chunks
+    let chunk_res = 8;
+    let dir = "output/regions";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir)?;
+    }
+
+    let mut world = VoxelWorld::with_store(chunk_res, Box::new(RegionStore::new(dir, chunk_res, 4)?))?;
+    for x in -20..20 {
+        world.set(IVec3::new(x, 3, -1), (x.rem_euclid(5) + 1) as u8)?;
+    }
+    world.set(IVec3::new(100, 0, 0), 9)?;
+    world.compact()?;
+    assert_eq!(world.save()?, 7);
+    assert_eq!(std::fs::read_dir(dir)?.count(), 3);
+
+    world.set(IVec3::new(100, 0, 0), 0)?;
+    world.save()?;
+    assert_eq!(std::fs::read_dir(dir)?.count(), 2);
+
+    let mut store = RegionStore::new(dir, chunk_res, 4)?;
+    store.compact()?;
+
+    let mut world = VoxelWorld::with_store(chunk_res, Box::new(store))?;
+    for x in -20..20 {
+        assert_eq!(world.get(IVec3::new(x, 3, -1))?, (x.rem_euclid(5) + 1) as u8);
+    }
+    assert!(world.chunk(IVec3::new(12, 0, 0))?.is_none());
+
+    let mut store = RegionStore::new(dir, chunk_res, 4)?;
+    store.save(IVec3::new(1, 1, 1), &WorldChunk::Svo(SVO::new(4)))?;
+    assert_eq!(store.load(IVec3::new(1, 1, 1)).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    Ok(())
+}
+
+pub fn test_bvox_edit() -> Result<(), Box<dyn Error>> {
+    std::fs::create_dir_all("output")?;
+    let filename = "output/test_edit.bvox";
+    let chunk_res = 8;
+    let chunk_size = chunk_res * chunk_res * chunk_res;
+    let chunks: Vec<Vec<u8>> = (0..4).map(|i| (0..chunk_size).map(|j| ((i * 7 + j) % 5) as u8).collect()).collect();
+
+    let header = BvoxHeader::new(chunk_res, chunk_size, true, false).with_checksums(true, true);
+    write_bvox(filename, &chunks, header)?;
+
+    let replacement = vec![9u8; chunk_size as usize];
+    replace_bvox_chunk(filename, 2, &replacement)?;
+    remove_bvox_chunk(filename, 0)?;
+    append_to_bvox(filename, &chunks[0])?;
+
+    let (read_header, read_chunks) = read_bvox(filename)?;
+    assert_eq!(read_header, header);
+    assert_eq!(read_chunks, vec![chunks[1].clone(), replacement, chunks[3].clone(), chunks[0].clone()]);
+    verify_file(filename)?;
+
+    // failed edits leave the file and no temp file behind
+    let before = std::fs::read(filename)?;
+    assert!(replace_bvox_chunk(filename, 4, &chunks[0]).is_err());
+    assert!(replace_bvox_chunk(filename, 0, &[1, 2, 3]).is_err());
+    assert!(remove_bvox_chunk(filename, 4).is_err());
+    assert_eq!(std::fs::read(filename)?, before);
+    assert_eq!(temp_files("output/test_edit.bvox")?, 0);
+
+    for _ in 0..4 {
+        remove_bvox_chunk(filename, 0)?;
+    }
+    assert!(read_bvox(filename)?.1.is_empty());
+
+    Ok(())
+}
+
+// temp files of write_atomic left next to filename
+pub fn temp_files(filename: &str) -> io::Result<usize> {
+    let path = std::path::Path::new(filename);
+    let prefix = format!("{}.", path.file_name().and_then(|name| name.to_str()).unwrap_or(""));
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(std::path::Path::new("."));
+
+    let mut count = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if name.starts_with(&prefix) && name.ends_with(".tmp") && name != format!("{}tmp", prefix) {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+pub fn test_atomic_write() -> Result<(), Box<dyn Error>> {
+    std::fs::create_dir_all("output")?;
+    let filename = "output/test_atomic.bsvo";
+    let _ = std::fs::remove_file(backup_path(filename));
+
+    let mut svo = SVO::new(3);
+    svo.insert_voxel(UVec3::new(1, 2, 3), 5)?;
+    let header = BsvoHeader::new(svo.depth, svo.root_span, false);
+    write_bsvo(filename, &svo, header)?;
+    let old = std::fs::read(filename)?;
+
+    // an unrelated file that happens to carry the .tmp suffix is left alone
+    std::fs::write(format!("{}.tmp", filename), b"user data")?;
+    svo.insert_voxel(UVec3::new(7, 7, 7), 6)?;
+    write_bsvo_with_backup(filename, &svo, header)?;
+
+    assert_eq!(read_bsvo(filename)?.1.get_voxel(UVec3::new(7, 7, 7)), 6);
+    assert_eq!(std::fs::read(backup_path(filename))?, old);
+    assert_eq!(std::fs::read(format!("{}.tmp", filename))?, b"user data");
+    assert_eq!(temp_files(filename)?, 0);
+
+    // concurrent saves of the same file each write their own temp file
+    let concurrent = "output/test_atomic_concurrent.bin";
+    std::thread::scope(|scope| {
+        for i in 0..8u8 {
+            scope.spawn(move || write_atomic(concurrent, &[i; 4096], false).unwrap());
+        }
+    });
+    let data = std::fs::read(concurrent)?;
+    assert!(data.len() == 4096 && data.iter().all(|&b| b == data[0]));
+    assert_eq!(temp_files(concurrent)?, 0);
+
+    let bvox = "output/test_atomic.bvox";
+    let _ = std::fs::remove_file(bvox);
+    let _ = std::fs::remove_file(backup_path(bvox));
+    let bvox_header = BvoxHeader::new(4, 64, false, false);
+    write_bvox_with_backup(bvox, &[vec![1; 64]], bvox_header)?;
+    assert!(!std::path::Path::new(&backup_path(bvox)).exists());
+    write_bvox_with_backup(bvox, &[vec![2; 64]], bvox_header)?;
+    assert_eq!(read_bvox(&backup_path(bvox))?.1, vec![vec![1; 64]]);
+    assert_eq!(read_bvox(bvox)?.1, vec![vec![2; 64]]);
+
+    // a failed rename keeps the target as it was and cleans up the temp file
+    let dir = "output/test_atomic_dir";
+    std::fs::create_dir_all(format!("{}/keep", dir))?;
+    assert!(write_atomic(dir, b"data", false).is_err());
+    assert!(std::path::Path::new(&format!("{}/keep", dir)).is_dir());
+    assert_eq!(temp_files(dir)?, 0);
+
+    Ok(())
+}
+
+// needs the tokio runtime from the dev dependencies
+#[cfg(all(test, feature = "async"))]
+pub fn test_async_io() -> Result<(), Box<dyn Error>> {
+    let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+
+    runtime.block_on(async {
+        let chunk_res = 16;
+        let chunk_size = chunk_res * chunk_res * chunk_res;
+        let chunks: Vec<Vec<u8>> = (0..3).map(|i| (0..chunk_size).map(|j| ((i + j) % 3) as u8).collect()).collect();
+        let header = BvoxHeader::new(chunk_res, chunk_size, false, false).with_checksums(true, true);
+
+        // the duplex buffer is smaller than the file, so writer and reader have to take turns
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let write = async {
+            write_bvox_async(&mut client, &chunks, header).await?;
+            drop(client);
+            Ok::<_, io::Error>(())
+        };
+        let (written, read) = tokio::join!(write, read_bvox_async(&mut server));
+        written?;
+        assert_eq!(read?, (header, chunks.clone()));
+
+        // the async output is byte for byte the sync encoding
+        let (mut client, mut server) = tokio::io::duplex(1 << 16);
+        write_bvox_async(&mut client, &chunks, header).await?;
+        drop(client);
+        let mut data = Vec::new();
+        tokio::io::AsyncReadExt::read_to_end(&mut server, &mut data).await?;
+        assert_eq!(data, encode_bvox(&chunks, header)?);
+        assert_eq!(get_bvox_header_async(&mut data.as_slice()).await?, header);
+
+        let mut svo = SVO::new(5);
+        for i in 0..32 {
+            svo.insert_voxel(UVec3::new(i, 31 - i, i / 2), i + 1)?;
+        }
+        let header = BsvoHeader::new(svo.depth, svo.root_span, true).with_checksum(true);
+
+        let (mut client, mut server) = tokio::io::duplex(256);
+        let write = async {
+            write_bsvo_async(&mut client, &svo, header).await?;
+            drop(client);
+            Ok::<_, io::Error>(())
+        };
+        let (written, read) = tokio::join!(write, read_bsvo_async(&mut server));
+        written?;
+        let (read_header, read_svo) = read?;
+        assert_eq!(read_header, header);
+        assert_eq!(read_svo.nodes, svo.nodes);
+        assert_eq!(get_bsvo_header_async(&mut encode_bsvo(&svo, header).as_slice()).await?, header);
+
+        // a corrupted stream fails like a corrupted file
+        let mut data = encode_bsvo(&svo, header);
+        data[BSVO_HEADER_SIZE] ^= 1;
+        assert!(read_bsvo_async(&mut data.as_slice()).await.is_err());
+
+        Ok(())
+    })
+}
+
+pub fn test_insert_node() -> Result<(), Box<dyn Error>> {
+    let mut svo = SVO::new(3);
+
+    // positions are in root span units, every level picks the octant relative to the node it is in
+    svo.insert_node(Vec3::new(5.5, 2.5, 7.5));
+    svo.insert_node(Vec3::new(4.0, 0.0, 3.99));
+    assert_eq!(svo.get_voxel(UVec3::new(5, 2, 7)), DEFAULT_SVO_MAT);
+    assert_eq!(svo.get_voxel(UVec3::new(4, 0, 3)), DEFAULT_SVO_MAT);
+    assert_eq!(svo.count_leaf_nodes(), 2);
+
+    let mut leaves = Vec::new();
+    svo.for_each_leaf(|pos, _| leaves.push(pos));
+    leaves.sort_by_key(|pos| (pos.z, pos.y, pos.x));
+    assert_eq!(leaves, vec![UVec3::new(4, 0, 3), UVec3::new(5, 2, 7)]);
+
+    // above max depth the node covering the position becomes a leaf
+    let mut svo = SVO::new(3);
+    let node = svo.insert_node_at_depth(Vec3::new(6.0, 1.0, 1.0), 1);
+    assert_eq!(node, 1 + 1);
+    assert!(svo.nodes[0].check_child(1));
+
+    Ok(())
+}
+
 pub fn test_gen_random_svo() -> Result<(), Box<dyn Error>> {
     let mut svo = SVO::new(SVO_MAX_DEPTH);
     svo.gen_random_svo(0);
@@ -146,6 +1033,12 @@ pub fn test_gen_random_svo() -> Result<(), Box<dyn Error>> {
         assert_eq!(svo.nodes[i], read_svo.nodes[i]);
     }
 
+    let bsvo_header = BsvoHeader::new(svo.depth, svo.root_span, true);
+    write_bsvo("output/random_svo_rle.bsvo", &svo, bsvo_header)?;
+
+    let (_, read_svo) = read_bsvo("output/random_svo_rle.bsvo")?;
+    assert_eq!(svo.nodes, read_svo.nodes);
+
     Ok(())
 }
 
@@ -223,6 +1116,613 @@ pub fn test_svo_simplest() {
     write_bsvo("output/simplest.bsvo", &svo, bsvo_header).unwrap();
 }
 
+pub fn gen_sphere_grid(res: u32, radius: f32) -> Vec<u8> {
+    let center = Vec3::splat(res as f32 * 0.5);
+
+    (0..res * res * res)
+        .map(|i| {
+            let pos = index_to_pos(i, res).as_vec3() + Vec3::splat(0.5);
+            if pos.distance(center) < radius { DEFAULT_VOX_MAT } else { 0 }
+        })
+        .collect()
+}
+
+fn mesh_signed_volume(mesh: &Mesh) -> f32 {
+    mesh.indices
+        .chunks_exact(3)
+        .map(|t| {
+            let (a, b, c) = (mesh.positions[t[0] as usize], mesh.positions[t[1] as usize], mesh.positions[t[2] as usize]);
+            a.dot(b.cross(c)) / 6.0
+        })
+        .sum()
+}
+
+fn mesh_is_closed(mesh: &Mesh) -> bool {
+    // every directed edge needs exactly one opposite edge on a closed, consistently wound surface
+    let mut edges = HashMap::new();
+    for t in mesh.indices.chunks_exact(3) {
+        for (a, b) in [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])] {
+            *edges.entry((a, b)).or_insert(0) += 1;
+        }
+    }
+
+    edges.iter().all(|(&(a, b), &count)| count == 1 && edges.get(&(b, a)) == Some(&1))
+}
+
+pub fn test_smooth_meshing() -> Result<(), Box<dyn Error>> {
+    let chunk_res = 16;
+    let chunk_size = chunk_res * chunk_res * chunk_res;
+    let depth = 4;
+
+    let chunk = gen_sphere_grid(chunk_res, 5.0);
+
+    let mc_mesh = marching_cubes(&chunk, chunk_res, VoxelField::Occupancy, DEFAULT_ISO_LEVEL);
+    assert!(mc_mesh.triangle_count() > 0);
+    assert!(mesh_is_closed(&mc_mesh));
+    assert!(mesh_signed_volume(&mc_mesh) > 0.0);
+
+    let mut morton_chunk = vec![0; chunk_size as usize];
+    morton_encode_3d_grid(&chunk, chunk_res, chunk_size, &mut morton_chunk);
+
+    let svo = SVO::from_grid(&morton_chunk, chunk_res, depth);
+
+    let dc_mesh = dual_contouring(&svo);
+    assert!(dc_mesh.triangle_count() > 0);
+    assert!(mesh_is_closed(&dc_mesh));
+    assert!(mesh_signed_volume(&dc_mesh) > 0.0);
+
+    Ok(())
+}
+
+// closed box as 12 outward facing triangles
+pub fn gen_box_triangles(min: Vec3, max: Vec3) -> Vec<[Vec3; 3]> {
+    let c = |i: u32| Vec3::new(
+        if i & 1 != 0 { max.x } else { min.x },
+        if i & 2 != 0 { max.y } else { min.y },
+        if i & 4 != 0 { max.z } else { min.z },
+    );
+
+    [
+        [0, 2, 3], [0, 3, 1], // -z
+        [4, 5, 7], [4, 7, 6], // +z
+        [0, 1, 5], [0, 5, 4], // -y
+        [2, 6, 7], [2, 7, 3], // +y
+        [0, 4, 6], [0, 6, 2], // -x
+        [1, 3, 7], [1, 7, 5], // +x
+    ]
+    .iter()
+    .map(|t| [c(t[0]), c(t[1]), c(t[2])])
+    .collect()
+}
+
+pub fn test_voxelize_box() -> Result<(), Box<dyn Error>> {
+    let grid_res = 16;
+    let triangles = gen_box_triangles(Vec3::splat(2.3), Vec3::splat(13.7));
+    let materials = vec![3; triangles.len()];
+
+    // shell of the voxels 2..=13
+    let expected_count = 12 * 12 * 12 - 10 * 10 * 10;
+
+    for mode in [VoxelizeMode::Conservative, VoxelizeMode::Center] {
+        let grid = voxelize_triangles_with(&triangles, Some(&materials), grid_res, GridTransform::default(), mode)?;
+        assert_eq!(grid.iter().filter(|&&v| v == 3).count(), expected_count);
+        assert_eq!(grid.iter().filter(|&&v| v != 0 && v != 3).count(), 0);
+        assert_eq!(grid[pos_to_index(8, 8, 8, grid_res) as usize], 0);
+
+        let svo = voxelize_triangles_svo_with(&triangles, Some(&materials), grid_res, GridTransform::default(), mode)?;
+        assert_eq!(svo.count_leaf_nodes() as usize, expected_count);
+        assert_eq!(svo.get_voxel(UVec3::new(2, 8, 8)), 3);
+    }
+
+    // one material per triangle, a short slice is an error instead of an out of bounds index
+    let short = &materials[1..];
+    assert!(voxelize_triangles_with(&triangles, Some(short), grid_res, GridTransform::default(), VoxelizeMode::Center).is_err());
+    assert!(voxelize_triangles_svo_with(&triangles, Some(short), grid_res, GridTransform::default(), VoxelizeMode::Center).is_err());
+    assert!(voxelize_solid_with(&triangles, Some(short), grid_res, GridTransform::default(), FillMode::Parity, 2).is_err());
+    assert!(voxelize_solid_svo_with(&triangles, Some(short), grid_res, GridTransform::default(), 2).is_err());
+
+    // resolutions without voxels or whose grid overflows are errors, not panics
+    for res in [0, u32::MAX] {
+        assert!(voxelize_triangles_with(&triangles, None, res, GridTransform::default(), VoxelizeMode::Center).is_err());
+        assert!(voxelize_triangles_svo_with(&triangles, None, res, GridTransform::default(), VoxelizeMode::Center).is_err());
+        assert!(voxelize_solid_with(&triangles, None, res, GridTransform::default(), FillMode::Parity, 2).is_err());
+    }
+    let mut voxels = 0;
+    rasterize_triangle(&triangles[0], 0, VoxelizeMode::Conservative, |_| voxels += 1);
+    assert_eq!(voxels, 0);
+
+    Ok(())
+}
+
+pub fn test_voxelize_solid_box() -> Result<(), Box<dyn Error>> {
+    let grid_res = 16;
+    let triangles = gen_box_triangles(Vec3::splat(2.3), Vec3::splat(13.7));
+    let expected_count = 12 * 12 * 12;
+
+    for fill_mode in [FillMode::Parity, FillMode::FloodFill] {
+        let grid = voxelize_solid_with(&triangles, None, grid_res, GridTransform::default(), fill_mode, 2)?;
+        assert_eq!(grid.iter().filter(|&&v| v != 0).count(), expected_count);
+        assert_eq!(grid[pos_to_index(8, 8, 8, grid_res) as usize], 2);
+        assert_eq!(grid[pos_to_index(2, 8, 8, grid_res) as usize], DEFAULT_VOX_MAT);
+    }
+
+    let svo = voxelize_solid_svo_with(&triangles, None, grid_res, GridTransform::default(), 2)?;
+    assert_eq!(svo.count_leaf_nodes() as usize, expected_count);
+    assert_eq!(svo.get_voxel(UVec3::splat(8)), 2);
+
+    // a missing triangle has to be reported instead of filling garbage
+    let open = &triangles[1..];
+    assert!(voxelize_solid_with(open, None, grid_res, GridTransform::default(), FillMode::Parity, 2).is_err());
+    assert!(voxelize_solid_svo_with(open, None, grid_res, GridTransform::default(), 2).is_err());
+
+    Ok(())
+}
+
+pub fn test_magica_vox() -> Result<(), Box<dyn Error>> {
+    let chunk_res = 8;
+    let chunk_size = chunk_res * chunk_res * chunk_res;
+
+    let mut palette = Palette::default();
+    palette.set(1, [255, 0, 0, 255]);
+    palette.set(7, [0, 255, 0, 128]);
+
+    let mut chunk_a = vec![0; chunk_size as usize];
+    chunk_a[pos_to_index(1, 2, 3, chunk_res) as usize] = 1;
+    chunk_a[pos_to_index(7, 7, 7, chunk_res) as usize] = 7;
+
+    let mut chunk_b = vec![0; chunk_size as usize];
+    chunk_b[pos_to_index(0, 0, 0, chunk_res) as usize] = 7;
+
+    // sorted the same way to_chunks returns them
+    let chunks = vec![(IVec3::new(1, 0, -1), chunk_b), (IVec3::ZERO, chunk_a)];
+
+    let scene = MagicaScene::from_chunks(&chunks, chunk_res, palette.clone())?;
+    write_magica_vox("output/test_magica.vox", &scene)?;
+
+    let read_scene = read_magica_vox("output/test_magica.vox")?;
+    assert_eq!(read_scene.palette, palette);
+    assert_eq!(read_scene.to_chunks(chunk_res)?, chunks);
+    assert!(read_scene.to_chunks(0).is_err());
+
+    // 2x1x1 model rotated by 90 degrees around z
+    let mut model = MagicaModel::new(UVec3::new(2, 1, 1));
+    model.voxels = vec![1, 2];
+
+    let mut rotated = MagicaScene::new(palette);
+    rotated.models.push(model);
+    rotated.instances.push(MagicaInstance { model_id: 0, rotation: 0b0010001, translation: IVec3::ZERO });
+    write_magica_vox("output/test_magica_rotated.vox", &rotated)?;
+
+    let mut voxels = Vec::new();
+    read_magica_vox("output/test_magica_rotated.vox")?.for_each_voxel(|pos, value| voxels.push((pos, value)));
+    assert_eq!(voxels, vec![(IVec3::new(0, -1, 0), 1), (IVec3::ZERO, 2)]);
+
+    // files without an RGBA chunk use the magicavoxel default palette
+    let data = std::fs::read("output/test_magica_rotated.vox")?;
+    let rgba = data.windows(4).position(|w| w == b"RGBA").ok_or("no palette")?;
+    let mut no_palette = data[..rgba].to_vec();
+    let children = (no_palette.len() - 20) as i32;
+    no_palette[16..20].copy_from_slice(&children.to_le_bytes());
+    let default = parse_magica_vox(&no_palette)?.palette;
+    assert_eq!(default, magica_default_palette());
+    assert_eq!(default.get(0), [0, 0, 0, 0]);
+    assert_eq!(default.get(1), [0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(default.get(2), [0xff, 0xff, 0xcc, 0xff]);
+    assert_eq!(default.get(37), [0xcc, 0xff, 0xff, 0xff]);
+    assert_eq!(default.get(215), [0x00, 0x00, 0x33, 0xff]);
+    assert_eq!(default.get(216), [0xee, 0x00, 0x00, 0xff]);
+    assert_eq!(default.get(255), [0x11, 0x11, 0x11, 0xff]);
+
+    // translations that overflow once they are combined are rejected
+    let mut far = rotated.clone();
+    far.instances.push(MagicaInstance { model_id: 0, rotation: IDENTITY_ROTATION, translation: IVec3::new(i32::MAX, 0, 0) });
+    assert!(parse_magica_vox(&encode_magica_vox(&far)?).is_err());
+
+    Ok(())
+}
+
+pub fn test_export_formats() -> Result<(), Box<dyn Error>> {
+    let chunk_res = 8;
+    let chunk_size = chunk_res * chunk_res * chunk_res;
+    let depth = 3;
+
+    let mut chunk = vec![0; chunk_size as usize];
+    for x in 2..4 {
+        for y in 2..4 {
+            for z in 2..4 {
+                chunk[pos_to_index(x, y, z, chunk_res) as usize] = DEFAULT_VOX_MAT;
+            }
+        }
+    }
+
+    let mut morton_chunk = vec![0; chunk_size as usize];
+    morton_encode_3d_grid(&chunk, chunk_res, chunk_size, &mut morton_chunk);
+    let svo = SVO::from_grid(&morton_chunk, chunk_res, depth);
+
+    let palette = Palette::default();
+
+    // 4 faces on each side of the 2x2x2 cube
+    let mesh = block_mesh(&chunk, chunk_res, &palette);
+    assert_eq!(mesh.triangle_count(), 6 * 4 * 2);
+    assert_eq!(block_mesh_svo(&svo, &palette).positions, mesh.positions);
+
+    let points = point_cloud_svo(&svo, &palette);
+    assert_eq!(points.vertex_count(), 8);
+
+    // root, one node on each inner level and the 8 leaves
+    let wireframe = octree_wireframe(&svo);
+    assert_eq!(wireframe.indices.len(), 11 * 12 * 2);
+
+    for (format, ext) in [(ExportFormat::Obj, "obj"), (ExportFormat::Ply, "ply"), (ExportFormat::Glb, "glb")] {
+        write_mesh(&format!("output/test_export_cube.{}", ext), &mesh, Topology::Triangles, format)?;
+        write_mesh(&format!("output/test_export_points.{}", ext), &points, Topology::Points, format)?;
+        write_mesh(&format!("output/test_export_octree.{}", ext), &wireframe, Topology::Lines, format)?;
+    }
+
+    let glb = std::fs::read("output/test_export_cube.glb")?;
+    assert_eq!(u32::from_le_bytes(glb[0..4].try_into()?), GLB_MAGIC);
+    assert_eq!(u32::from_le_bytes(glb[8..12].try_into()?) as usize, glb.len());
+
+    Ok(())
+}
+
+pub fn test_binvox() -> Result<(), Box<dyn Error>> {
+    let chunk_res = 16;
+    let chunk = gen_sphere_grid(chunk_res, 6.0);
+
+    let header = BinvoxHeader::new(UVec3::splat(chunk_res), Vec3::new(-0.5, 0.25, 1.0), 2.0);
+    write_binvox("output/test_sphere.binvox", &chunk, chunk_res, header)?;
+
+    let (read_header, read_chunk) = read_binvox("output/test_sphere.binvox")?;
+    assert_eq!(read_header, header);
+    assert_eq!(read_chunk, chunk);
+
+    let (_, svo) = read_binvox_svo("output/test_sphere.binvox")?;
+    assert_eq!(svo.count_leaf_nodes() as usize, chunk.iter().filter(|&&v| v > 0).count());
+
+    write_binvox_svo("output/test_sphere_svo.binvox", &svo, header.translate, header.scale)?;
+    assert_eq!(read_binvox("output/test_sphere_svo.binvox")?.1, chunk);
+
+    // the second voxel in binvox order is x = 0, z = 0, y = 1
+    let mut data = b"#binvox 1\ndim 2 2 2\ntranslate 0 0 0\nscale 1\ndata\n".to_vec();
+    data.extend([0, 1, 1, 1, 0, 6]);
+    std::fs::write("output/test_order.binvox", data)?;
+
+    let (_, order_chunk) = read_binvox("output/test_order.binvox")?;
+    assert_eq!(order_chunk.iter().filter(|&&v| v > 0).count(), 1);
+    assert_eq!(order_chunk[pos_to_index(0, 1, 0, 2) as usize], DEFAULT_VOX_MAT);
+
+    // dimensions from the header are checked before anything is allocated
+    let binvox = |dims: &str, runs: &[u8]| {
+        let mut data = format!("#binvox 1\ndim {dims}\ndata\n").into_bytes();
+        data.extend(runs);
+        parse_binvox(&data)
+    };
+    assert!(binvox("2 2 2", &[0, 1, 1, 1, 0, 6]).is_ok());
+    assert!(binvox("2 2 2", &[0, 1, 1, 1, 0, 7]).is_err());
+    assert!(binvox("0 2 2", &[]).is_err());
+    assert!(binvox("65536 65536 1", &[0, 255]).is_err());
+    assert!(binvox("1 1 4096", &[0, 255]).is_err());
+    assert!(BinvoxHeader::new(UVec3::new(u32::MAX, u32::MAX, u32::MAX), Vec3::ZERO, 1.0).chunk_size().is_err());
+    assert!(write_binvox("output/test_short.binvox", &chunk[1..], chunk_res, header).is_err());
+    assert!(write_binvox_svo("output/test_deep.binvox", &SVO::new(11), Vec3::ZERO, 1.0).is_err());
+
+    Ok(())
+}
+
+pub fn test_schematic() -> Result<(), Box<dyn Error>> {
+    // python gzip.compress(b"".join(f"minecraft:stone {i}\n" for i in range(40)), mtime=0), a dynamic huffman block
+    let fixture = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x6d, 0xd2, 0xb1, 0x0d, 0x83, 0x40,
+        0x00, 0x43, 0xd1, 0x9e, 0x29, 0x18, 0x21, 0xb6, 0x43, 0x80, 0x6c, 0x83, 0x10, 0x48, 0x14, 0x77,
+        0x91, 0x80, 0xfd, 0x45, 0x9f, 0x7f, 0xed, 0xaf, 0x9e, 0x2c, 0x97, 0xa3, 0x6e, 0xeb, 0xb9, 0xec,
+        0xf7, 0xf7, 0xba, 0x7f, 0x75, 0xeb, 0x5f, 0x5d, 0xf9, 0x2b, 0x42, 0x31, 0x4a, 0x50, 0xde, 0x28,
+        0x03, 0xca, 0x07, 0x65, 0x44, 0x99, 0x50, 0x66, 0x0a, 0x1b, 0x68, 0xaa, 0x45, 0xb6, 0xe8, 0x16,
+        0xe1, 0xa2, 0x5c, 0xa4, 0x8b, 0x76, 0x11, 0x2f, 0xea, 0x4d, 0xbd, 0x1b, 0x9b, 0x53, 0x6f, 0xea,
+        0x4d, 0xbd, 0xa9, 0x37, 0xf5, 0xa6, 0xde, 0xd4, 0x9b, 0xfa, 0x50, 0x1f, 0xea, 0xd3, 0xb8, 0x0c,
+        0xf5, 0xa1, 0x3e, 0xd4, 0x87, 0xfa, 0x50, 0x1f, 0xea, 0x33, 0x77, 0x0f, 0xda, 0x9d, 0x1c, 0x5a,
+        0xee, 0x02, 0x00, 0x00,
+    ];
+    let expected: String = (0..40).map(|i| format!("minecraft:stone {}\n", i)).collect();
+    assert_eq!(gzip_decode(&fixture, expected.len())?, expected.as_bytes());
+    assert!(gzip_decode(&fixture, expected.len() - 1).is_err());
+
+    // sponge v2 schematic, 3 x 2 x 2 with air, stone and two oak log states
+    let size = UVec3::new(3, 2, 2);
+    let palette = HashMap::from([
+        ("minecraft:air".to_string(), NbtTag::Int(0)),
+        ("minecraft:stone".to_string(), NbtTag::Int(1)),
+        ("minecraft:oak_log[axis=y]".to_string(), NbtTag::Int(2)),
+        ("minecraft:oak_log[axis=x]".to_string(), NbtTag::Int(200)),
+    ]);
+
+    // block data is y slowest, then z, then x, index 200 is a two byte varint
+    let mut block_data = Vec::new();
+    let mut expected_voxels = HashMap::new();
+    for y in 0..size.y {
+        for z in 0..size.z {
+            for x in 0..size.x {
+                let id = (x + y + z) % 3;
+                let id = if id == 2 && x == 0 { 200 } else { id };
+                if id == 200 {
+                    block_data.extend([0xC8u8 as i8, 0x01]);
+                } else {
+                    block_data.push(id as i8);
+                }
+                let value = match id {
+                    0 => 0,
+                    1 => 7,
+                    _ => 9,
+                };
+                expected_voxels.insert(UVec3::new(x, y, z), value);
+            }
+        }
+    }
+
+    let root = NbtTag::Compound(HashMap::from([
+        ("Version".to_string(), NbtTag::Int(2)),
+        ("Width".to_string(), NbtTag::Short(size.x as i16)),
+        ("Height".to_string(), NbtTag::Short(size.y as i16)),
+        ("Length".to_string(), NbtTag::Short(size.z as i16)),
+        ("Offset".to_string(), NbtTag::IntArray(vec![1, -2, 3])),
+        ("PaletteMax".to_string(), NbtTag::Int(palette.len() as i32)),
+        ("Palette".to_string(), NbtTag::Compound(palette)),
+        ("BlockData".to_string(), NbtTag::ByteArray(block_data)),
+        ("Entities".to_string(), NbtTag::List(TAG_COMPOUND, Vec::new())),
+    ]));
+
+    let nbt = encode_nbt("Schematic", &root);
+    assert_eq!(parse_nbt(&nbt)?, ("Schematic".to_string(), root.clone()));
+
+    // wrap the nbt in a gzip member with a single stored deflate block
+    let mut gzip = vec![0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 0xFF, 1];
+    gzip.extend((nbt.len() as u16).to_le_bytes());
+    gzip.extend((!(nbt.len() as u16)).to_le_bytes());
+    gzip.extend(&nbt);
+    gzip.extend(crc32(&nbt).to_le_bytes());
+    gzip.extend((nbt.len() as u32).to_le_bytes());
+    std::fs::write("output/test_sponge.schem", &gzip)?;
+
+    // oak logs map to 9 regardless of their axis, unknown blocks are skipped
+    let mut mapping = BlockMapping::new(0);
+    mapping.insert("minecraft:stone", 7);
+    mapping.insert("minecraft:oak_log", 9);
+
+    let schematic = read_schematic("output/test_sponge.schem", &mapping)?;
+    assert_eq!(schematic.size, size);
+    assert_eq!(schematic.offset, IVec3::new(1, -2, 3));
+    for (pos, value) in &expected_voxels {
+        assert_eq!(schematic.get(*pos), *value);
+    }
+
+    // placed across a chunk border the voxels end up in two chunks
+    let chunk_res = 4;
+    let world_pos = IVec3::new(2, 0, -1);
+    let chunks = schematic.to_chunks(world_pos, chunk_res, true)?;
+    let filled = chunks.iter().map(|(_, c)| c.iter().filter(|&&v| v > 0).count()).sum::<usize>();
+    assert_eq!(filled, expected_voxels.values().filter(|&&v| v > 0).count());
+
+    for (coord, chunk) in &chunks {
+        let svo = SVO::from_grid(chunk, chunk_res, chunk_res.trailing_zeros() as u8);
+        svo.for_each_leaf(|pos, mat| {
+            let local = (coord * chunk_res as i32 + pos.as_ivec3() - world_pos).as_uvec3();
+            assert_eq!(mat as u8, expected_voxels[&local]);
+        });
+    }
+
+    // mcedit schematic, 4 x 1 x 1 with ids above 255 split over Blocks and the AddBlocks nibbles
+    let legacy = NbtTag::Compound(HashMap::from([
+        ("Width".to_string(), NbtTag::Short(4)),
+        ("Height".to_string(), NbtTag::Short(1)),
+        ("Length".to_string(), NbtTag::Short(1)),
+        ("Materials".to_string(), NbtTag::String("Alpha".to_string())),
+        ("Blocks".to_string(), NbtTag::ByteArray(vec![0x01, 0x10, 0x20, 0x01])),
+        ("Data".to_string(), NbtTag::ByteArray(vec![0, 5, 0, 0])),
+        // 0x21 gives 0x1 to block 0 and 0x2 to block 1, 0x03 gives 0x3 to block 2 and nothing to block 3
+        ("AddBlocks".to_string(), NbtTag::ByteArray(vec![0x21, 0x03])),
+        ("WEOffsetX".to_string(), NbtTag::Int(-1)),
+    ]));
+    std::fs::write("output/test_legacy.schematic", encode_nbt("Schematic", &legacy))?;
+
+    let mut legacy_mapping = BlockMapping::new(0);
+    legacy_mapping.insert("1", 2);
+    legacy_mapping.insert("257", 3);
+    legacy_mapping.insert("528:5", 4);
+    legacy_mapping.insert("800", 5);
+
+    let schematic = read_schematic("output/test_legacy.schematic", &legacy_mapping)?;
+    assert_eq!(schematic.offset, IVec3::new(-1, 0, 0));
+    assert_eq!(schematic.voxels, vec![3, 4, 5, 2]);
+
+    // structure nbt with a single block state
+    let structure = NbtTag::Compound(HashMap::from([
+        ("DataVersion".to_string(), NbtTag::Int(3465)),
+        ("size".to_string(), NbtTag::List(TAG_INT, vec![NbtTag::Int(2), NbtTag::Int(2), NbtTag::Int(2)])),
+        ("palette".to_string(), NbtTag::List(TAG_COMPOUND, vec![NbtTag::Compound(HashMap::from([
+            ("Name".to_string(), NbtTag::String("minecraft:oak_log".to_string())),
+            ("Properties".to_string(), NbtTag::Compound(HashMap::from([("axis".to_string(), NbtTag::String("z".to_string()))]))),
+        ]))])),
+        ("blocks".to_string(), NbtTag::List(TAG_COMPOUND, vec![NbtTag::Compound(HashMap::from([
+            ("pos".to_string(), NbtTag::List(TAG_INT, vec![NbtTag::Int(1), NbtTag::Int(0), NbtTag::Int(1)])),
+            ("state".to_string(), NbtTag::Int(0)),
+        ]))])),
+    ]));
+    std::fs::write("output/test_structure.nbt", encode_nbt("", &structure))?;
+
+    mapping.insert("minecraft:oak_log[axis=z]", 11);
+    let schematic = read_schematic("output/test_structure.nbt", &mapping)?;
+    assert_eq!(schematic.voxels.iter().filter(|&&v| v > 0).count(), 1);
+    assert_eq!(schematic.get(UVec3::new(1, 0, 1)), 11);
+
+    // sizes that overflow or can't be allocated are rejected instead of panicking
+    assert!(Schematic::new(UVec3::splat(u32::MAX), IVec3::ZERO).is_err());
+    assert!(Schematic::new(UVec3::splat(1 << 20), IVec3::ZERO).is_err());
+
+    let mut huge = structure.clone();
+    if let NbtTag::Compound(map) = &mut huge {
+        map.insert("size".to_string(), NbtTag::List(TAG_INT, vec![NbtTag::Int(i32::MAX); 3]));
+    }
+    assert_eq!(parse_schematic(&encode_nbt("", &huge), &mapping).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    // 65535 per axis in a sponge and an mcedit schematic without the block data to match
+    for schematic in [&root, &legacy] {
+        let mut huge = schematic.clone();
+        if let NbtTag::Compound(map) = &mut huge {
+            for dim in ["Width", "Height", "Length"] {
+                map.insert(dim.to_string(), NbtTag::Short(-1));
+            }
+        }
+        assert_eq!(parse_schematic(&encode_nbt("Schematic", &huge), &mapping).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    Ok(())
+}
+
+pub fn test_point_cloud() -> Result<(), Box<dyn Error>> {
+    let grid_res = 16;
+    let palette = Palette::default();
+
+    // sphere with a material per height so the palette lookup can be checked
+    let mut grid = gen_sphere_grid(grid_res, 6.0);
+    for (i, v) in grid.iter_mut().enumerate() {
+        if *v > 0 { *v = 10 + index_to_pos(i as u32, grid_res).y as u8 * 4; }
+    }
+
+    // binary ply with voxel centers and palette colors, read back and binned at the same scale
+    let cloud_mesh = point_cloud(&grid, grid_res, &palette);
+    std::fs::write("output/test_points.ply", encode_ply(&cloud_mesh, Topology::Points)?)?;
+
+    let cloud = read_point_cloud("output/test_points.ply")?;
+    assert_eq!(cloud.len(), grid.iter().filter(|&&v| v > 0).count());
+    assert!(cloud.has_colors());
+
+    let points = voxelize_points_with(&cloud, grid_res, GridTransform::default(), Some(&palette))?;
+    assert_eq!(points.grid, grid);
+    let index = grid.iter().position(|&v| v > 0).unwrap();
+    assert_eq!(points.colors[index], palette.get(grid[index]));
+
+    let (svo, colors) = voxelize_points_svo_with(&cloud, grid_res, GridTransform::default(), Some(&palette))?;
+    assert_eq!(svo.count_leaf_nodes() as usize, cloud.len());
+    assert_eq!(colors.len(), cloud.len());
+    svo.for_each_leaf(|pos, mat| {
+        assert_eq!(mat as u8, grid[pos_to_index(pos.x, pos.y, pos.z, grid_res) as usize]);
+    });
+
+    // ascii ply with float colors and a face element after the vertices
+    let ascii = b"ply\nformat ascii 1.0\nelement vertex 3\nproperty double x\nproperty double y\nproperty double z\n\
+        property float red\nproperty float green\nproperty float blue\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n\
+        0 0 0 1 0 0\n0.5 0.5 0.5 0 0 1\n4 4 4 0 1 0\n3 0 1 2\n";
+    let cloud = parse_ply(ascii)?;
+    assert_eq!(cloud.positions[2], Vec3::splat(4.0));
+    assert_eq!(cloud.colors, vec![[255, 0, 0], [0, 0, 255], [0, 255, 0]]);
+
+    // the first two points share a voxel and their colors are averaged
+    let points = voxelize_points(&cloud, 4, None)?;
+    assert_eq!(points.grid.iter().filter(|&&v| v == DEFAULT_VOX_MAT).count(), 2);
+
+    // resolutions without voxels or whose grid overflows are rejected up front
+    assert!(voxelize_points(&cloud, 0, None).is_err());
+    assert!(voxelize_points_svo_with(&cloud, 0, GridTransform::default(), None).is_err());
+    assert!(voxelize_points_with(&cloud, u32::MAX, GridTransform::default(), None).is_err());
+    assert_eq!(points.colors[0], [128, 0, 128, 255]);
+    assert_eq!(points.colors[pos_to_index(3, 3, 3, 4) as usize], [0, 255, 0, 255]);
+
+    // big endian positions without colors
+    let mut big_endian = b"ply\nformat binary_big_endian 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty float z\nend_header\n".to_vec();
+    for v in [1.5f32, -2.0, 3.25] {
+        big_endian.extend(v.to_be_bytes());
+    }
+    let cloud = parse_ply(&big_endian)?;
+    assert_eq!(cloud.positions, vec![Vec3::new(1.5, -2.0, 3.25)]);
+    assert!(!cloud.has_colors());
+
+    // xyz with an intensity column, comments and comma separated values
+    let cloud = parse_xyz(b"# x y z i r g b\n0.5 0.5 0.5 0.3 200 10 10\n1.5,0.5,0.5,0.9,10,10,200\n")?;
+    assert_eq!(cloud.colors, vec![[200, 10, 10], [10, 10, 200]]);
+
+    let (svo, colors) = voxelize_points_svo_with(&cloud, 2, GridTransform::default(), None)?;
+    assert_eq!(svo.get_voxel(UVec3::new(1, 0, 0)), DEFAULT_VOX_MAT as u32);
+    assert_eq!(colors[&UVec3::ZERO], [200, 10, 10, 255]);
+
+    Ok(())
+}
+
+pub fn test_vdb() -> Result<(), Box<dyn Error>> {
+    let chunk_res = 32;
+    let mut chunk = gen_sphere_grid(chunk_res, 12.0);
+    for (i, v) in chunk.iter_mut().enumerate() {
+        if *v > 0 { *v = 1 + (i % 200) as u8; }
+    }
+
+    // the offset makes the chunk straddle leaf, internal and root node borders including negative ones
+    let offset = IVec3::new(-16, 120, 4090);
+    let mut float_grid = VdbGrid::from_chunk("density", &chunk, chunk_res, offset, VdbValueType::Float);
+    float_grid.transform = GridTransform::new(Vec3::new(1.0, -2.0, 0.5), 0.25);
+    let bool_grid = VdbGrid::from_chunk("mask", &chunk, chunk_res, offset, VdbValueType::Bool);
+
+    write_vdb("output/test_sphere.vdb", &[float_grid.clone(), bool_grid.clone()])?;
+
+    let data = std::fs::read("output/test_sphere.vdb")?;
+    assert_eq!(i64::from_le_bytes(data[0..8].try_into()?), VDB_MAGIC);
+
+    let grids = read_vdb("output/test_sphere.vdb")?;
+    assert_eq!(grids.len(), 2);
+    assert_eq!(grids[0], float_grid);
+    assert_eq!(grids[1], bool_grid);
+    assert_eq!(grids[0].to_chunk(chunk_res, offset), chunk);
+    assert_eq!(grids[1].to_chunk(chunk_res, offset).iter().filter(|&&v| v == DEFAULT_VOX_MAT).count(), bool_grid.voxels.len());
+
+    // svo leaves keep their materials and the voxel size ends up in the transform
+    let depth = 4;
+    let mut svo = SVO::new(depth);
+    for (i, pos) in [UVec3::new(0, 0, 0), UVec3::new(7, 8, 9), UVec3::new(15, 15, 15)].iter().enumerate() {
+        svo.insert_voxel(*pos, i as u32 + 3)?;
+    }
+    svo.root_span = 4.0;
+
+    write_vdb("output/test_svo.vdb", &[VdbGrid::from_svo("svo", &svo, VdbValueType::Float)])?;
+    let grid = &read_vdb("output/test_svo.vdb")?[0];
+    assert_eq!(grid.name, "svo");
+    assert_eq!(grid.transform.voxel_size, 0.25);
+    assert_eq!(grid.voxels.len(), 3);
+    assert_eq!(grid.voxels[&IVec3::new(7, 8, 9)], 4.0);
+
+    // every value of the upper node turned into an active tile of 128^3 voxels
+    let mut single = VdbGrid::new("bomb", VdbValueType::Float, GridTransform::default());
+    single.set(IVec3::ZERO, 1.0);
+    let mut data = encode_vdb(&[single])?;
+    let map = data.windows(24).position(|w| w == b"UniformScaleTranslateMap").ok_or("no transform")? + 24 + 18 * 8;
+    let value_mask = map + 4 + 4 + 4 + 4 + 12 + 4096;
+    data[value_mask..value_mask + 4096].fill(0xFF);
+    data[value_mask] = 0xFE;
+    data[value_mask + 4096] = 6;
+    data.splice(value_mask + 4097..value_mask + 4097, 1.0f32.to_le_bytes().repeat(32768));
+    assert!(parse_vdb(&data).unwrap_err().to_string().contains("tiles"));
+
+    // grid end offsets and node origins come from the file
+    let mut single = VdbGrid::new("offsets", VdbValueType::Float, GridTransform::default());
+    single.set(IVec3::ZERO, 1.0);
+    let data = encode_vdb(&[single])?;
+    let end_offset = data.windows(7).position(|w| w == b"offsets").ok_or("no grid name")? + 7 + 4 + 16 + 4 + 16;
+    assert_eq!(i64::from_le_bytes(data[end_offset..end_offset + 8].try_into()?), data.len() as i64);
+    assert!(parse_vdb(&data).is_ok());
+    for end in [-1i64, i64::MAX, data.len() as i64 + 1, end_offset as i64] {
+        let mut corrupt = data.clone();
+        corrupt[end_offset..end_offset + 8].copy_from_slice(&end.to_le_bytes());
+        assert!(parse_vdb(&corrupt).is_err());
+    }
+
+    let map = data.windows(24).position(|w| w == b"UniformScaleTranslateMap").ok_or("no transform")? + 24 + 18 * 8;
+    let upper_origin = map + 4 + 4 + 4 + 4;
+    let mut corrupt = data.clone();
+    corrupt[upper_origin..upper_origin + 4].copy_from_slice(&(i32::MAX - 4095).to_le_bytes());
+    assert!(parse_vdb(&corrupt).is_ok());
+    corrupt[upper_origin..upper_origin + 4].copy_from_slice(&(i32::MAX - 4094).to_le_bytes());
+    assert!(parse_vdb(&corrupt).is_err());
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -252,6 +1752,82 @@ mod tests {
         test_bvox_compression().unwrap();
     }
 
+    #[test]
+    fn bvox_codecs() {
+        test_bvox_codecs().unwrap();
+    }
+
+    #[test]
+    fn occupancy() {
+        test_occupancy().unwrap();
+    }
+
+    #[test]
+    fn rle() {
+        test_rle().unwrap();
+    }
+
+    #[test]
+    fn bsvo_pointerless() {
+        test_bsvo_pointerless().unwrap();
+    }
+
+    #[test]
+    fn bsvo_view() {
+        test_bsvo_view().unwrap();
+    }
+
+    #[test]
+    fn endianness() {
+        test_endianness().unwrap();
+    }
+
+    #[test]
+    fn svo_validate() {
+        test_svo_validate().unwrap();
+    }
+
+    #[test]
+    fn checksums() {
+        test_checksums().unwrap();
+    }
+
+    #[test]
+    fn svo_chunks() {
+        test_svo_chunks().unwrap();
+    }
+
+    #[test]
+    fn voxel_world() {
+        test_voxel_world().unwrap();
+    }
+
+    #[test]
+    fn region() {
+        test_region().unwrap();
+    }
+
+    #[test]
+    fn bvox_edit() {
+        test_bvox_edit().unwrap();
+    }
+
+    #[test]
+    fn atomic_write() {
+        test_atomic_write().unwrap();
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_io() {
+        test_async_io().unwrap();
+    }
+
+    #[test]
+    fn insert_node() {
+        test_insert_node().unwrap();
+    }
+
     #[test]
     fn bsvo_rw() {
         test_bsvo_read_write().unwrap();
@@ -271,4 +1847,49 @@ mod tests {
     fn simplest() {
         test_svo_simplest();
     }
+
+    #[test]
+    fn smooth_meshing() {
+        test_smooth_meshing().unwrap();
+    }
+
+    #[test]
+    fn voxelize_box() {
+        test_voxelize_box().unwrap();
+    }
+
+    #[test]
+    fn voxelize_solid_box() {
+        test_voxelize_solid_box().unwrap();
+    }
+
+    #[test]
+    fn magica_vox() {
+        test_magica_vox().unwrap();
+    }
+
+    #[test]
+    fn export_formats() {
+        test_export_formats().unwrap();
+    }
+
+    #[test]
+    fn binvox() {
+        test_binvox().unwrap();
+    }
+
+    #[test]
+    fn schematic() {
+        test_schematic().unwrap();
+    }
+
+    #[test]
+    fn point_cloud() {
+        test_point_cloud().unwrap();
+    }
+
+    #[test]
+    fn vdb() {
+        test_vdb().unwrap();
+    }
 }
@@ -0,0 +1,149 @@
+use std::io;
+
+// lz4 block format, the decompressed size has to be known by the caller
+const MIN_MATCH: usize = 4;
+// the last match has to start at least 12 bytes before the end and the last 5 bytes are literals
+const MF_LIMIT: usize = 12;
+const LAST_LITERALS: usize = 5;
+const MAX_OFFSET: usize = u16::MAX as usize;
+const HASH_LOG: u32 = 16;
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn hash(sequence: u32) -> usize {
+    (sequence.wrapping_mul(2654435761) >> (32 - HASH_LOG)) as usize
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+// lengths of 15 and above continue in extra bytes of 255
+fn write_length(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], match_info: Option<(usize, usize)>) {
+    let lit_len = literals.len();
+    let match_len = match_info.map_or(0, |(_, len)| len - MIN_MATCH);
+
+    let token = ((lit_len.min(15) as u8) << 4) | match_len.min(15) as u8;
+    out.push(token);
+    if lit_len >= 15 {
+        write_length(out, lit_len - 15);
+    }
+    out.extend_from_slice(literals);
+
+    if let Some((offset, _)) = match_info {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_len >= 15 {
+            write_length(out, match_len - 15);
+        }
+    }
+}
+
+// greedy single probe compressor
+pub fn lz4_compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut table = vec![u32::MAX; 1 << HASH_LOG];
+    let mut anchor = 0;
+    let mut pos = 0;
+
+    if data.len() > MF_LIMIT {
+        let match_limit = data.len() - MF_LIMIT;
+        let end_limit = data.len() - LAST_LITERALS;
+
+        while pos < match_limit {
+            let sequence = read_u32(data, pos);
+            let h = hash(sequence);
+            let candidate = table[h] as usize;
+            table[h] = pos as u32;
+
+            if candidate == u32::MAX as usize || pos - candidate > MAX_OFFSET || read_u32(data, candidate) != sequence {
+                pos += 1;
+                continue;
+            }
+
+            let mut len = MIN_MATCH;
+            while pos + len < end_limit && data[candidate + len] == data[pos + len] {
+                len += 1;
+            }
+
+            write_sequence(&mut out, &data[anchor..pos], Some((pos - candidate, len)));
+            pos += len;
+            anchor = pos;
+        }
+    }
+
+    write_sequence(&mut out, &data[anchor..], None);
+    out
+}
+
+fn read_length(data: &[u8], pos: &mut usize, mut len: usize) -> io::Result<usize> {
+    loop {
+        let byte = *data.get(*pos).ok_or_else(|| invalid_data("unexpected end of lz4 data."))?;
+        *pos += 1;
+        len += byte as usize;
+        if byte != 255 {
+            return Ok(len);
+        }
+    }
+}
+
+pub fn lz4_decompress(data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(size);
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let token = data[pos];
+        pos += 1;
+
+        let mut lit_len = (token >> 4) as usize;
+        if lit_len == 15 {
+            lit_len = read_length(data, &mut pos, lit_len)?;
+        }
+
+        let literals = data.get(pos..pos + lit_len).ok_or_else(|| invalid_data("unexpected end of lz4 data."))?;
+        out.extend_from_slice(literals);
+        pos += lit_len;
+
+        // the last sequence has no match
+        if pos == data.len() {
+            break;
+        }
+
+        let offset = u16::from_le_bytes(data.get(pos..pos + 2).ok_or_else(|| invalid_data("unexpected end of lz4 data."))?.try_into().unwrap()) as usize;
+        pos += 2;
+
+        let mut match_len = (token & 0x0F) as usize;
+        if match_len == 15 {
+            match_len = read_length(data, &mut pos, match_len)?;
+        }
+        match_len += MIN_MATCH;
+
+        if offset == 0 || offset > out.len() {
+            return Err(invalid_data("invalid lz4 match offset."));
+        }
+        if out.len() + match_len > size {
+            return Err(invalid_data("lz4 data exceeds the expected size."));
+        }
+
+        // byte wise copy, the match may overlap the bytes being written
+        let start = out.len() - offset;
+        for i in 0..match_len {
+            out.push(out[start + i]);
+        }
+    }
+
+    if out.len() != size {
+        return Err(invalid_data("lz4 data does not match the expected size."));
+    }
+
+    Ok(out)
+}
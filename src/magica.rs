@@ -0,0 +1,549 @@
+use crate::atomic::write_atomic;
+use crate::palette::{Palette, PALETTE_SIZE};
+use crate::vox::pos_to_index;
+use glam::{IVec3, UVec3};
+use std::collections::HashMap;
+use std::{fs::File, io, io::{BufReader, Cursor, Read}, path::Path};
+
+pub const MAGICA_VOX_MAGIC: &[u8; 4] = b"VOX ";
+pub const MAGICA_VOX_VERSION: i32 = 150;
+pub const MAGICA_MAX_MODEL_RES: u32 = 256;
+// first row picks x, second row picks y, all signs positive
+pub const IDENTITY_ROTATION: u8 = 0b0000100;
+
+// the palette magicavoxel uses for files without an RGBA chunk: a 6x6x6 color cube from white to just above black
+// (blue fastest, red slowest), then ramps of 10 shades of red, green, blue and gray from 0xee down to 0x11
+pub fn magica_default_palette() -> Palette {
+    const LEVELS: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut palette = Palette::new();
+    for i in 0..215 {
+        palette.colors[1 + i] = [LEVELS[i / 36], LEVELS[(i / 6) % 6], LEVELS[i % 6], u8::MAX];
+    }
+    for (j, &v) in RAMP.iter().enumerate() {
+        palette.colors[216 + j] = [v, 0, 0, u8::MAX];
+        palette.colors[226 + j] = [0, v, 0, u8::MAX];
+        palette.colors[236 + j] = [0, 0, v, u8::MAX];
+        palette.colors[246 + j] = [v, v, v, u8::MAX];
+    }
+    palette
+}
+
+// a single model, voxels are color indices in a dense x + y * size.x + z * size.x * size.y grid
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MagicaModel {
+    pub size: UVec3,
+    pub voxels: Vec<u8>,
+}
+
+impl MagicaModel {
+    pub fn new(size: UVec3) -> Self {
+        Self {
+            size,
+            voxels: vec![0; (size.x * size.y * size.z) as usize],
+        }
+    }
+
+    pub fn index(&self, pos: UVec3) -> usize {
+        (pos.x + pos.y * self.size.x + pos.z * self.size.x * self.size.y) as usize
+    }
+}
+
+// placement of a model in the world, world = rotation * (voxel - size / 2) + translation
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MagicaInstance {
+    pub model_id: usize,
+    pub rotation: u8,
+    pub translation: IVec3,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MagicaScene {
+    pub models: Vec<MagicaModel>,
+    pub instances: Vec<MagicaInstance>,
+    pub palette: Palette,
+}
+
+enum SceneNode {
+    Transform { child: i32, rotation: u8, translation: IVec3 },
+    Group { children: Vec<i32> },
+    Shape { models: Vec<i32> },
+}
+
+// rows of the rotation matrix from the packed magicavoxel rotation byte
+pub fn decode_rotation(rotation: u8) -> [IVec3; 3] {
+    let i0 = (rotation & 3) as usize;
+    let i1 = ((rotation >> 2) & 3) as usize;
+    let i2 = 3usize.saturating_sub(i0 + i1);
+
+    let row = |index: usize, sign_bit: u8| {
+        let mut row = IVec3::ZERO;
+        row[index.min(2)] = if rotation & (1 << sign_bit) != 0 { -1 } else { 1 };
+        row
+    };
+
+    [row(i0, 4), row(i1, 5), row(i2, 6)]
+}
+
+pub fn encode_rotation(rows: [IVec3; 3]) -> u8 {
+    let index = |row: IVec3| (0..3).find(|&i| row[i] != 0).unwrap_or(0) as u8;
+    let negative = |row: IVec3| (row.element_sum() < 0) as u8;
+
+    index(rows[0]) | (index(rows[1]) << 2) | (negative(rows[0]) << 4) | (negative(rows[1]) << 5) | (negative(rows[2]) << 6)
+}
+
+fn rotate(rows: &[IVec3; 3], v: IVec3) -> IVec3 {
+    IVec3::new(rows[0].dot(v), rows[1].dot(v), rows[2].dot(v))
+}
+
+// translations are read from the file, so transforms are applied without overflowing
+fn checked_transform(rows: &[IVec3; 3], v: IVec3, translation: IVec3) -> Option<IVec3> {
+    let dot = |row: IVec3, t: i32| (0..3).try_fold(t, |sum, i| sum.checked_add(row[i].checked_mul(v[i])?));
+    Some(IVec3::new(dot(rows[0], translation.x)?, dot(rows[1], translation.y)?, dot(rows[2], translation.z)?))
+}
+
+fn multiply(a: &[IVec3; 3], b: &[IVec3; 3]) -> [IVec3; 3] {
+    let col = |i: usize| IVec3::new(b[0][i], b[1][i], b[2][i]);
+    a.map(|row| IVec3::new(row.dot(col(0)), row.dot(col(1)), row.dot(col(2))))
+}
+
+impl MagicaScene {
+    pub fn new(palette: Palette) -> Self {
+        Self {
+            models: Vec::new(),
+            instances: Vec::new(),
+            palette,
+        }
+    }
+
+    // calls f with the world position and color index of every voxel of every instance
+    pub fn for_each_voxel<F: FnMut(IVec3, u8)>(&self, mut f: F) {
+        for instance in &self.instances {
+            let model = &self.models[instance.model_id];
+            let rows = decode_rotation(instance.rotation);
+            let pivot = (model.size / 2).as_ivec3();
+
+            for z in 0..model.size.z {
+                for y in 0..model.size.y {
+                    for x in 0..model.size.x {
+                        let pos = UVec3::new(x, y, z);
+                        let value = model.voxels[model.index(pos)];
+
+                        if value > 0 {
+                            f(rotate(&rows, pos.as_ivec3() - pivot) + instance.translation, value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // bakes all instances into linear bvox chunks keyed by their chunk coordinate
+    pub fn to_chunks(&self, chunk_res: u32) -> io::Result<Vec<(IVec3, Vec<u8>)>> {
+        let size = (chunk_res as usize).checked_pow(3).filter(|_| chunk_res > 0 && chunk_res <= i32::MAX as u32);
+        let size = size.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid chunk resolution."))?;
+
+        let mut chunks: HashMap<IVec3, Vec<u8>> = HashMap::new();
+        let res = chunk_res as i32;
+
+        self.for_each_voxel(|pos, value| {
+            let coord = pos.div_euclid(IVec3::splat(res));
+            let local = pos.rem_euclid(IVec3::splat(res)).as_uvec3();
+
+            let chunk = chunks.entry(coord).or_insert_with(|| vec![0; size]);
+            chunk[pos_to_index(local.x, local.y, local.z, chunk_res) as usize] = value;
+        });
+
+        let mut chunks: Vec<(IVec3, Vec<u8>)> = chunks.into_iter().collect();
+        chunks.sort_by_key(|(coord, _)| (coord.z, coord.y, coord.x));
+        Ok(chunks)
+    }
+
+    // one model per non empty linear chunk, placed at its chunk coordinate
+    pub fn from_chunks(chunks: &[(IVec3, Vec<u8>)], chunk_res: u32, palette: Palette) -> io::Result<Self> {
+        if chunk_res > MAGICA_MAX_MODEL_RES {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk resolution exceeds the vox model size limit."));
+        }
+
+        let mut scene = Self::new(palette);
+        let size = UVec3::splat(chunk_res);
+
+        for (coord, chunk) in chunks {
+            if chunk.len() != (chunk_res * chunk_res * chunk_res) as usize {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk is not the given size."));
+            }
+
+            if chunk.iter().all(|&v| v == 0) {
+                continue;
+            }
+
+            // linear chunks use the same x + y * res + z * res * res layout as the models
+            scene.instances.push(MagicaInstance {
+                model_id: scene.models.len(),
+                rotation: IDENTITY_ROTATION,
+                translation: *coord * chunk_res as i32 + (size / 2).as_ivec3(),
+            });
+            scene.models.push(MagicaModel { size, voxels: chunk.clone() });
+        }
+
+        Ok(scene)
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn read_i32<R: Read>(reader: &mut R) -> io::Result<i32> {
+    let mut buffer = [0u8; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(i32::from_le_bytes(buffer))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let len = read_i32(reader)?;
+    if len < 0 {
+        return Err(invalid_data("negative string length in vox file."));
+    }
+
+    let mut buffer = vec![0u8; len as usize];
+    reader.read_exact(&mut buffer)?;
+    String::from_utf8(buffer).map_err(|_| invalid_data("invalid string in vox file."))
+}
+
+fn read_dict<R: Read>(reader: &mut R) -> io::Result<HashMap<String, String>> {
+    let count = read_i32(reader)?;
+    let mut dict = HashMap::new();
+
+    for _ in 0..count {
+        let key = read_string(reader)?;
+        let value = read_string(reader)?;
+        dict.insert(key, value);
+    }
+
+    Ok(dict)
+}
+
+fn read_transform<R: Read>(reader: &mut R) -> io::Result<SceneNode> {
+    read_dict(reader)?;
+    let child = read_i32(reader)?;
+    let _reserved = read_i32(reader)?;
+    let _layer = read_i32(reader)?;
+    let frame_count = read_i32(reader)?;
+
+    let mut rotation = IDENTITY_ROTATION;
+    let mut translation = IVec3::ZERO;
+
+    // only the first frame is used, animations are not supported
+    for frame in 0..frame_count {
+        let attributes = read_dict(reader)?;
+        if frame != 0 {
+            continue;
+        }
+
+        if let Some(r) = attributes.get("_r") {
+            rotation = r.trim().parse().map_err(|_| invalid_data("invalid rotation in vox file."))?;
+        }
+
+        if let Some(t) = attributes.get("_t") {
+            let values: Vec<i32> = t.split_whitespace().map(|v| v.parse()).collect::<Result<_, _>>()
+                .map_err(|_| invalid_data("invalid translation in vox file."))?;
+            if values.len() != 3 {
+                return Err(invalid_data("invalid translation in vox file."));
+            }
+            translation = IVec3::new(values[0], values[1], values[2]);
+        }
+    }
+
+    Ok(SceneNode::Transform { child, rotation, translation })
+}
+
+pub fn parse_magica_vox(data: &[u8]) -> io::Result<MagicaScene> {
+    let mut reader = Cursor::new(data);
+
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGICA_VOX_MAGIC {
+        return Err(invalid_data("file is not a magicavoxel vox file."));
+    }
+    let _version = read_i32(&mut reader)?;
+
+    let mut scene = MagicaScene::new(magica_default_palette());
+    let mut nodes: HashMap<i32, SceneNode> = HashMap::new();
+    let mut size = None;
+
+    while (reader.position() as usize) < data.len() {
+        let mut id = [0u8; 4];
+        reader.read_exact(&mut id)?;
+        let content_size = read_i32(&mut reader)?;
+        let _children_size = read_i32(&mut reader)?;
+
+        if content_size < 0 || reader.position() as usize + content_size as usize > data.len() {
+            return Err(invalid_data("unexpected end of vox file."));
+        }
+
+        let start = reader.position() as usize;
+        let mut content = Cursor::new(&data[start..start + content_size as usize]);
+
+        match &id {
+            // children of main are read as top level chunks
+            b"MAIN" => continue,
+            b"SIZE" => {
+                let x = read_i32(&mut content)?;
+                let y = read_i32(&mut content)?;
+                let z = read_i32(&mut content)?;
+                if x <= 0 || y <= 0 || z <= 0 || [x, y, z].iter().any(|&v| v as u32 > MAGICA_MAX_MODEL_RES) {
+                    return Err(invalid_data("invalid model size in vox file."));
+                }
+                size = Some(UVec3::new(x as u32, y as u32, z as u32));
+            }
+            b"XYZI" => {
+                let mut model = MagicaModel::new(size.take().ok_or_else(|| invalid_data("xyzi chunk without size chunk."))?);
+                let count = read_i32(&mut content)?;
+
+                for _ in 0..count {
+                    let mut voxel = [0u8; 4];
+                    content.read_exact(&mut voxel)?;
+
+                    let pos = UVec3::new(voxel[0] as u32, voxel[1] as u32, voxel[2] as u32);
+                    if pos.cmpge(model.size).any() {
+                        return Err(invalid_data("voxel outside of model in vox file."));
+                    }
+
+                    let index = model.index(pos);
+                    model.voxels[index] = voxel[3];
+                }
+
+                scene.models.push(model);
+            }
+            b"RGBA" => {
+                // entry i holds the color of index i + 1
+                for i in 0..PALETTE_SIZE {
+                    let mut color = [0u8; 4];
+                    content.read_exact(&mut color)?;
+                    scene.palette.colors[(i + 1) % PALETTE_SIZE] = color;
+                }
+                scene.palette.colors[0] = [0; 4];
+            }
+            b"nTRN" => {
+                let node_id = read_i32(&mut content)?;
+                nodes.insert(node_id, read_transform(&mut content)?);
+            }
+            b"nGRP" => {
+                let node_id = read_i32(&mut content)?;
+                read_dict(&mut content)?;
+                let count = read_i32(&mut content)?;
+                let children = (0..count).map(|_| read_i32(&mut content)).collect::<io::Result<_>>()?;
+                nodes.insert(node_id, SceneNode::Group { children });
+            }
+            b"nSHP" => {
+                let node_id = read_i32(&mut content)?;
+                read_dict(&mut content)?;
+                let count = read_i32(&mut content)?;
+                let mut models = Vec::new();
+                for _ in 0..count {
+                    models.push(read_i32(&mut content)?);
+                    read_dict(&mut content)?;
+                }
+                nodes.insert(node_id, SceneNode::Shape { models });
+            }
+            // materials, layers, cameras, notes etc. are skipped
+            _ => {}
+        }
+
+        reader.set_position((start + content_size as usize) as u64);
+    }
+
+    if nodes.is_empty() {
+        // files without scene graph keep every model at the origin
+        for (model_id, model) in scene.models.iter().enumerate() {
+            scene.instances.push(MagicaInstance {
+                model_id,
+                rotation: IDENTITY_ROTATION,
+                translation: (model.size / 2).as_ivec3(),
+            });
+        }
+    } else {
+        let identity = decode_rotation(IDENTITY_ROTATION);
+        collect_instances(&nodes, 0, identity, IVec3::ZERO, &mut scene, 0)?;
+    }
+
+    Ok(scene)
+}
+
+fn collect_instances(
+    nodes: &HashMap<i32, SceneNode>,
+    node_id: i32,
+    rotation: [IVec3; 3],
+    translation: IVec3,
+    scene: &mut MagicaScene,
+    level: u32,
+) -> io::Result<()> {
+    // guards against cycles in corrupt files
+    if level > nodes.len() as u32 {
+        return Err(invalid_data("cyclic scene graph in vox file."));
+    }
+
+    match nodes.get(&node_id) {
+        Some(SceneNode::Transform { child, rotation: r, translation: t }) => {
+            let local = decode_rotation(*r);
+            let rotation_world = multiply(&rotation, &local);
+            // voxels of a model are placed up to a model size away from the translation
+            let limit = i32::MAX - MAGICA_MAX_MODEL_RES as i32;
+            let translation_world = checked_transform(&rotation, *t, translation)
+                .filter(|t| t.cmpge(IVec3::splat(-limit)).all() && t.cmple(IVec3::splat(limit)).all())
+                .ok_or_else(|| invalid_data("translation out of range in vox file."))?;
+            collect_instances(nodes, *child, rotation_world, translation_world, scene, level + 1)
+        }
+        Some(SceneNode::Group { children }) => {
+            for &child in children {
+                collect_instances(nodes, child, rotation, translation, scene, level + 1)?;
+            }
+            Ok(())
+        }
+        Some(SceneNode::Shape { models }) => {
+            for &model_id in models {
+                if model_id < 0 || model_id as usize >= scene.models.len() {
+                    return Err(invalid_data("shape references missing model in vox file."));
+                }
+
+                scene.instances.push(MagicaInstance {
+                    model_id: model_id as usize,
+                    rotation: encode_rotation(rotation),
+                    translation,
+                });
+            }
+            Ok(())
+        }
+        None => Err(invalid_data("scene graph references missing node in vox file.")),
+    }
+}
+
+pub fn read_magica_vox(filename: &str) -> io::Result<MagicaScene> {
+    let path = Path::new(filename);
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+
+    parse_magica_vox(&buffer)
+}
+
+fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(content.len() as i32).to_le_bytes());
+    out.extend_from_slice(&0i32.to_le_bytes());
+    out.extend_from_slice(content);
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn write_dict(out: &mut Vec<u8>, entries: &[(&str, String)]) {
+    out.extend_from_slice(&(entries.len() as i32).to_le_bytes());
+    for (key, value) in entries {
+        write_string(out, key);
+        write_string(out, value);
+    }
+}
+
+fn write_transform(out: &mut Vec<u8>, node_id: i32, child: i32, layer: i32, frame: &[(&str, String)]) {
+    let mut content = Vec::new();
+    for v in [node_id, 0, child, -1, layer, 1] {
+        content.extend_from_slice(&v.to_le_bytes());
+    }
+    // the zero above is the empty node attribute dict
+    write_dict(&mut content, frame);
+    write_chunk(out, b"nTRN", &content);
+}
+
+pub fn encode_magica_vox(scene: &MagicaScene) -> io::Result<Vec<u8>> {
+    let mut children = Vec::new();
+
+    for model in &scene.models {
+        if model.size.max_element() > MAGICA_MAX_MODEL_RES || model.size.min_element() == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "model size exceeds the vox limits."));
+        }
+
+        let mut size = Vec::new();
+        for v in model.size.to_array() {
+            size.extend_from_slice(&(v as i32).to_le_bytes());
+        }
+        write_chunk(&mut children, b"SIZE", &size);
+
+        let mut xyzi = Vec::new();
+        let count = model.voxels.iter().filter(|&&v| v > 0).count() as i32;
+        xyzi.extend_from_slice(&count.to_le_bytes());
+
+        for z in 0..model.size.z {
+            for y in 0..model.size.y {
+                for x in 0..model.size.x {
+                    let value = model.voxels[model.index(UVec3::new(x, y, z))];
+                    if value > 0 {
+                        xyzi.extend_from_slice(&[x as u8, y as u8, z as u8, value]);
+                    }
+                }
+            }
+        }
+        write_chunk(&mut children, b"XYZI", &xyzi);
+    }
+
+    // root transform -> group -> (transform -> shape) per instance
+    write_transform(&mut children, 0, 1, -1, &[]);
+
+    let mut group = Vec::new();
+    group.extend_from_slice(&1i32.to_le_bytes());
+    write_dict(&mut group, &[]);
+    group.extend_from_slice(&(scene.instances.len() as i32).to_le_bytes());
+    for i in 0..scene.instances.len() as i32 {
+        group.extend_from_slice(&(2 + i * 2).to_le_bytes());
+    }
+    write_chunk(&mut children, b"nGRP", &group);
+
+    for (i, instance) in scene.instances.iter().enumerate() {
+        if instance.model_id >= scene.models.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "instance references missing model."));
+        }
+
+        let node_id = 2 + i as i32 * 2;
+        let t = instance.translation;
+        let mut frame = vec![("_t", format!("{} {} {}", t.x, t.y, t.z))];
+        if instance.rotation != IDENTITY_ROTATION {
+            frame.push(("_r", instance.rotation.to_string()));
+        }
+        write_transform(&mut children, node_id, node_id + 1, 0, &frame);
+
+        let mut shape = Vec::new();
+        shape.extend_from_slice(&(node_id + 1).to_le_bytes());
+        write_dict(&mut shape, &[]);
+        shape.extend_from_slice(&1i32.to_le_bytes());
+        shape.extend_from_slice(&(instance.model_id as i32).to_le_bytes());
+        write_dict(&mut shape, &[]);
+        write_chunk(&mut children, b"nSHP", &shape);
+    }
+
+    let mut rgba = Vec::with_capacity(PALETTE_SIZE * 4);
+    for i in 0..PALETTE_SIZE {
+        rgba.extend_from_slice(&scene.palette.colors[(i + 1) % PALETTE_SIZE]);
+    }
+    write_chunk(&mut children, b"RGBA", &rgba);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGICA_VOX_MAGIC);
+    out.extend_from_slice(&MAGICA_VOX_VERSION.to_le_bytes());
+    out.extend_from_slice(b"MAIN");
+    out.extend_from_slice(&0i32.to_le_bytes());
+    out.extend_from_slice(&(children.len() as i32).to_le_bytes());
+    out.extend_from_slice(&children);
+
+    Ok(out)
+}
+
+pub fn write_magica_vox(filename: &str, scene: &MagicaScene) -> io::Result<()> {
+    let data = encode_magica_vox(scene)?;
+
+    write_atomic(filename, &data, false)
+}
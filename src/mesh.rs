@@ -0,0 +1,422 @@
+use crate::palette::Palette;
+use crate::svo::SVO;
+use crate::vox::{index_to_pos, pos_to_index};
+use glam::{IVec3, Mat3, UVec3, Vec3};
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+pub const DEFAULT_ISO_LEVEL: f32 = 0.5;
+
+// regularization towards the mass point when solving the dual contouring qef
+pub const DC_MASS_POINT_BIAS: f32 = 0.05;
+
+// cell corner i sits at (i & 1, (i >> 1) & 1, (i >> 2) & 1), same order as the svo children
+pub const CELL_EDGES: [(u8, u8); 12] = [
+    (0, 1), (0, 2), (0, 4),
+    (1, 3), (1, 5),
+    (2, 3), (2, 6),
+    (3, 7),
+    (4, 5), (4, 6),
+    (5, 7),
+    (6, 7),
+];
+
+const AXES: [IVec3; 3] = [IVec3::X, IVec3::Y, IVec3::Z];
+
+static MC_TABLE: OnceLock<Vec<Vec<McPolygon>>> = OnceLock::new();
+
+// closed loop of crossed cell edges, wound so its normal points out of the solid
+#[derive(Clone, Debug)]
+pub struct McPolygon {
+    pub edges: Vec<u8>,
+    // no fan from edges[0] avoids diagonals on a cell face, triangulate around the centroid instead
+    pub needs_center: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    // optional per vertex rgba, empty if the mesh has no colors
+    pub colors: Vec<[u8; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    // vertex normals from the area weighted normals of the adjacent triangles
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![Vec3::ZERO; self.positions.len()];
+
+        for tri in self.indices.chunks_exact(3) {
+            let (a, b, c) = (tri[0] as usize, tri[1] as usize, tri[2] as usize);
+            let normal = (self.positions[b] - self.positions[a]).cross(self.positions[c] - self.positions[a]);
+
+            normals[a] += normal;
+            normals[b] += normal;
+            normals[c] += normal;
+        }
+
+        self.normals = normals.into_iter().map(|n| n.normalize_or_zero()).collect();
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VoxelField {
+    // every value above zero is solid
+    Occupancy,
+    // values are densities, 0 -> 0.0 and 255 -> 1.0
+    Density,
+}
+
+impl VoxelField {
+    pub fn sample(&self, value: u8) -> f32 {
+        match self {
+            VoxelField::Occupancy => if value > 0 { 1.0 } else { 0.0 },
+            VoxelField::Density => value as f32 / u8::MAX as f32,
+        }
+    }
+}
+
+pub fn corner_offset(corner: u8) -> UVec3 {
+    UVec3::new((corner & 1) as u32, ((corner >> 1) & 1) as u32, ((corner >> 2) & 1) as u32)
+}
+
+// polygons for every cube case, built once from the face ambiguity rule instead of a hardcoded table
+pub fn mc_table() -> &'static [Vec<McPolygon>] {
+    MC_TABLE.get_or_init(|| (0..=255u8).map(triangulate_case).collect())
+}
+
+fn triangulate_case(case: u8) -> Vec<McPolygon> {
+    let inside = |corner: u8| case & (1 << corner) != 0;
+    let crossed = |edge: usize| inside(CELL_EDGES[edge].0) != inside(CELL_EDGES[edge].1);
+    let corner_pos = |corner: u8| corner_offset(corner).as_vec3();
+    let midpoint = |edge: usize| (corner_pos(CELL_EDGES[edge].0) + corner_pos(CELL_EDGES[edge].1)) * 0.5;
+
+    // every crossed edge gets one directed segment to another crossed edge on each of its two faces
+    let mut next = [usize::MAX; 12];
+
+    for (axis, bit) in [1u8, 2, 4].into_iter().enumerate() {
+        for side in [0, bit] {
+            let face_normal = if side == 0 { -AXES[axis].as_vec3() } else { AXES[axis].as_vec3() };
+            let face_corners: Vec<u8> = (0..8u8).filter(|&c| c & bit == side).collect();
+            let face_center = face_corners.iter().map(|&c| corner_pos(c)).sum::<Vec3>() * 0.25;
+            let face_edges: Vec<usize> = (0..12)
+                .filter(|&e| CELL_EDGES[e].0 & bit == side && CELL_EDGES[e].1 & bit == side && crossed(e))
+                .collect();
+
+            let mut segments = Vec::new();
+            match face_edges.len() {
+                2 => {
+                    let (inner, outer): (Vec<u8>, Vec<u8>) = face_corners.iter().partition(|&&c| inside(c));
+                    let centroid = |cs: &[u8]| cs.iter().map(|&c| corner_pos(c)).sum::<Vec3>() / cs.len() as f32;
+                    segments.push((face_edges[0], face_edges[1], centroid(&outer) - centroid(&inner)));
+                }
+                4 => {
+                    // ambiguous face, always cut off the inside corners separately
+                    // this only depends on the face itself, so neighboring cells agree
+                    for corner in face_corners.iter().copied().filter(|&c| inside(c)) {
+                        let incident: Vec<usize> = face_edges
+                            .iter()
+                            .copied()
+                            .filter(|&e| CELL_EDGES[e].0 == corner || CELL_EDGES[e].1 == corner)
+                            .collect();
+                        segments.push((incident[0], incident[1], face_center - corner_pos(corner)));
+                    }
+                }
+                _ => {}
+            }
+
+            // direct the segment so the surface normal ends up pointing towards the outside corners
+            for (a, b, to_outside) in segments {
+                if (midpoint(b) - midpoint(a)).dot(to_outside.cross(face_normal)) >= 0.0 {
+                    next[a] = b;
+                } else {
+                    next[b] = a;
+                }
+            }
+        }
+    }
+
+    // a diagonal between two edges of the same face could also be a diagonal in the neighboring cell
+    let share_face = |a: usize, b: usize| {
+        let (a, b) = (CELL_EDGES[a], CELL_EDGES[b]);
+        [1u8, 2, 4].iter().any(|&bit| {
+            let side = a.0 & bit;
+            a.1 & bit == side && b.0 & bit == side && b.1 & bit == side
+        })
+    };
+
+    let mut polygons = Vec::new();
+    let mut visited = [false; 12];
+
+    for start in 0..12 {
+        if visited[start] || next[start] == usize::MAX {
+            continue;
+        }
+
+        // walk the closed loop of crossed edges
+        let mut polygon = Vec::new();
+        let mut cur = start;
+        while !visited[cur] {
+            visited[cur] = true;
+            polygon.push(cur);
+            cur = next[cur];
+        }
+
+        let len = polygon.len();
+        let fan_start = (0..len).find(|&k| (2..len - 1).all(|i| !share_face(polygon[k], polygon[(k + i) % len])));
+
+        if let Some(k) = fan_start {
+            polygon.rotate_left(k);
+        }
+
+        polygons.push(McPolygon {
+            edges: polygon.into_iter().map(|e| e as u8).collect(),
+            needs_center: fan_start.is_none(),
+        });
+    }
+
+    polygons
+}
+
+// marching cubes over a linear (not morton encoded) grid, vertices are in voxel units
+// with the voxel centers at +0.5, the grid is padded with empty space so the surface is closed
+pub fn marching_cubes(vox_grid: &[u8], grid_res: u32, field: VoxelField, iso_level: f32) -> Mesh {
+    let table = mc_table();
+    let padded_res = grid_res + 2;
+    let cell_res = grid_res + 1;
+
+    let sample = |p: UVec3| -> f32 {
+        if p.min_element() == 0 || p.max_element() > grid_res {
+            0.0
+        } else {
+            field.sample(vox_grid[pos_to_index(p.x - 1, p.y - 1, p.z - 1, grid_res) as usize])
+        }
+    };
+
+    let mut mesh = Mesh::new();
+    let mut edge_vertices: HashMap<(u32, u32), u32> = HashMap::new();
+
+    for cell_index in 0..cell_res * cell_res * cell_res {
+        let cell = index_to_pos(cell_index, cell_res);
+
+        let mut values = [0.0; 8];
+        let mut case = 0u8;
+        for c in 0..8u8 {
+            values[c as usize] = sample(cell + corner_offset(c));
+            if values[c as usize] >= iso_level { case |= 1 << c; }
+        }
+
+        if case == 0 || case == u8::MAX {
+            continue;
+        }
+
+        for polygon in &table[case as usize] {
+            let vertices: Vec<u32> = polygon.edges.iter().map(|&edge| {
+                let (a, b) = CELL_EDGES[edge as usize];
+                let (pa, pb) = (cell + corner_offset(a), cell + corner_offset(b));
+                let key = (pos_to_index(pa.x, pa.y, pa.z, padded_res), pos_to_index(pb.x, pb.y, pb.z, padded_res));
+
+                *edge_vertices.entry(key).or_insert_with(|| {
+                    let (va, vb) = (values[a as usize], values[b as usize]);
+                    let t = ((iso_level - va) / (vb - va)).clamp(0.0, 1.0);
+                    // padded sample p is the voxel p - 1 with its center at p - 0.5
+                    mesh.positions.push(pa.as_vec3().lerp(pb.as_vec3(), t) - Vec3::splat(0.5));
+                    (mesh.positions.len() - 1) as u32
+                })
+            }).collect();
+
+            if polygon.needs_center {
+                let center = vertices.iter().map(|&v| mesh.positions[v as usize]).sum::<Vec3>() / vertices.len() as f32;
+                let center_index = mesh.positions.len() as u32;
+                mesh.positions.push(center);
+
+                for i in 0..vertices.len() {
+                    mesh.indices.extend([center_index, vertices[i], vertices[(i + 1) % vertices.len()]]);
+                }
+            } else {
+                for i in 1..vertices.len() - 1 {
+                    mesh.indices.extend([vertices[0], vertices[i], vertices[i + 1]]);
+                }
+            }
+        }
+    }
+
+    mesh.compute_normals();
+    mesh
+}
+
+// dual contouring on the leaves of an svo, a vertex is placed per mixed cell by solving the qef
+// of the crossed edges with normals estimated from the neighboring voxels
+pub fn dual_contouring(svo: &SVO) -> Mesh {
+    let mut solid = HashSet::new();
+    svo.for_each_leaf(|pos, _| { solid.insert(pos.as_ivec3()); });
+
+    let mut voxels: Vec<IVec3> = solid.iter().copied().collect();
+    voxels.sort_by_key(|p| (p.z, p.y, p.x));
+
+    let mut mesh = Mesh::new();
+    let mut cell_vertices: HashMap<IVec3, u32> = HashMap::new();
+
+    for &p in &voxels {
+        for axis in 0..3 {
+            for dir in [-1, 1] {
+                let q = p + AXES[axis] * dir;
+                if solid.contains(&q) {
+                    continue;
+                }
+
+                // the edge from a to a + axis crosses the surface, connect the four cells around it
+                let a = if dir > 0 { p } else { q };
+                let (e1, e2) = (AXES[(axis + 1) % 3], AXES[(axis + 2) % 3]);
+
+                let quad = [a, a - e1, a - e1 - e2, a - e2].map(|cell| {
+                    *cell_vertices.entry(cell).or_insert_with(|| {
+                        mesh.positions.push(dc_cell_vertex(cell, &solid));
+                        (mesh.positions.len() - 1) as u32
+                    })
+                });
+
+                // winding so the normal points away from the solid side
+                let quad = if dir > 0 { quad } else { [quad[0], quad[3], quad[2], quad[1]] };
+                mesh.indices.extend([quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+            }
+        }
+    }
+
+    mesh.compute_normals();
+    mesh
+}
+
+// one quad per voxel face that borders empty space, get returns the material at a position or 0
+fn block_mesh_with<I, F>(voxels: I, get: F, palette: &Palette, voxel_size: f32) -> Mesh
+where
+    I: Iterator<Item = (IVec3, u8)>,
+    F: Fn(IVec3) -> u8,
+{
+    let mut mesh = Mesh::new();
+
+    for (pos, mat) in voxels {
+        for axis in 0..3 {
+            for dir in [-1, 1] {
+                if get(pos + AXES[axis] * dir) != 0 {
+                    continue;
+                }
+
+                let (u, v) = (AXES[(axis + 1) % 3], AXES[(axis + 2) % 3]);
+                let base = if dir > 0 { pos + AXES[axis] } else { pos };
+                let corners = [base, base + u, base + u + v, base + v];
+                // counter clockwise seen from outside, u x v points along +axis
+                let corners = if dir > 0 { corners } else { [corners[0], corners[3], corners[2], corners[1]] };
+
+                let first = mesh.positions.len() as u32;
+                for corner in corners {
+                    mesh.positions.push(corner.as_vec3() * voxel_size);
+                    mesh.normals.push((AXES[axis] * dir).as_vec3());
+                    mesh.colors.push(palette.get(mat));
+                }
+
+                mesh.indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+            }
+        }
+    }
+
+    mesh
+}
+
+// cube faces of a linear grid, vertices are in voxel units
+pub fn block_mesh(vox_grid: &[u8], grid_res: u32, palette: &Palette) -> Mesh {
+    let res = grid_res as i32;
+    let get = |p: IVec3| {
+        if p.min_element() < 0 || p.max_element() >= res {
+            0
+        } else {
+            vox_grid[pos_to_index(p.x as u32, p.y as u32, p.z as u32, grid_res) as usize]
+        }
+    };
+
+    let voxels = (0..grid_res * grid_res * grid_res)
+        .filter(|&i| vox_grid[i as usize] > 0)
+        .map(|i| (index_to_pos(i, grid_res).as_ivec3(), vox_grid[i as usize]));
+
+    block_mesh_with(voxels, get, palette, 1.0)
+}
+
+// cube faces of the svo leaves, scaled by the leaf size
+pub fn block_mesh_svo(svo: &SVO, palette: &Palette) -> Mesh {
+    let mut voxels = HashMap::new();
+    svo.for_each_leaf(|pos, mat| { voxels.insert(pos.as_ivec3(), mat as u8); });
+
+    let mut sorted: Vec<(IVec3, u8)> = voxels.iter().map(|(&p, &m)| (p, m)).collect();
+    sorted.sort_by_key(|(p, _)| (p.z, p.y, p.x));
+
+    block_mesh_with(sorted.into_iter(), |p| voxels.get(&p).copied().unwrap_or(0), palette, svo.voxel_size())
+}
+
+fn dc_gradient(p: IVec3, solid: &HashSet<IVec3>) -> Vec3 {
+    let occ = |p: IVec3| if solid.contains(&p) { 1.0 } else { 0.0 };
+
+    // points from solid towards empty space
+    Vec3::new(
+        occ(p - IVec3::X) - occ(p + IVec3::X),
+        occ(p - IVec3::Y) - occ(p + IVec3::Y),
+        occ(p - IVec3::Z) - occ(p + IVec3::Z),
+    )
+}
+
+fn dc_cell_vertex(cell: IVec3, solid: &HashSet<IVec3>) -> Vec3 {
+    let mut ata = Mat3::ZERO;
+    let mut atb = Vec3::ZERO;
+    let mut mass_point = Vec3::ZERO;
+    let mut count = 0;
+
+    for &(a, b) in &CELL_EDGES {
+        let pa = cell + corner_offset(a).as_ivec3();
+        let pb = cell + corner_offset(b).as_ivec3();
+
+        let (inside, outside) = match (solid.contains(&pa), solid.contains(&pb)) {
+            (true, false) => (pa, pb),
+            (false, true) => (pb, pa),
+            _ => continue,
+        };
+
+        // binary data has no exact crossing, use the edge midpoint between the voxel centers
+        let point = (pa.as_vec3() + pb.as_vec3()) * 0.5 + Vec3::splat(0.5);
+        let mut normal = (dc_gradient(inside, solid) + dc_gradient(outside, solid)).normalize_or_zero();
+        if normal == Vec3::ZERO {
+            normal = (outside - inside).as_vec3();
+        }
+
+        ata += Mat3::from_cols(normal * normal.x, normal * normal.y, normal * normal.z);
+        atb += normal * normal.dot(point);
+        mass_point += point;
+        count += 1;
+    }
+
+    mass_point /= count as f32;
+
+    let ata = ata + Mat3::IDENTITY * DC_MASS_POINT_BIAS;
+    let atb = atb + mass_point * DC_MASS_POINT_BIAS;
+
+    let vertex = if ata.determinant().abs() > f32::EPSILON {
+        ata.inverse() * atb
+    } else {
+        mass_point
+    };
+
+    // keep the vertex inside its cell
+    let min = cell.as_vec3() + Vec3::splat(0.5);
+    vertex.clamp(min, min + Vec3::ONE)
+}
@@ -0,0 +1,264 @@
+use crate::deflate::{gzip_decode, is_gzip};
+use std::collections::HashMap;
+use std::io;
+
+pub const TAG_END: u8 = 0;
+pub const TAG_BYTE: u8 = 1;
+pub const TAG_SHORT: u8 = 2;
+pub const TAG_INT: u8 = 3;
+pub const TAG_LONG: u8 = 4;
+pub const TAG_FLOAT: u8 = 5;
+pub const TAG_DOUBLE: u8 = 6;
+pub const TAG_BYTE_ARRAY: u8 = 7;
+pub const TAG_STRING: u8 = 8;
+pub const TAG_LIST: u8 = 9;
+pub const TAG_COMPOUND: u8 = 10;
+pub const TAG_INT_ARRAY: u8 = 11;
+pub const TAG_LONG_ARRAY: u8 = 12;
+
+// nesting limit so corrupt files can't overflow the stack
+const MAX_NBT_DEPTH: u32 = 512;
+// gzip compressed nbt, e.g. schematics, may not expand beyond this, a few kb of deflate data can otherwise ask for gigabytes
+pub const MAX_NBT_SIZE: usize = 256 << 20;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NbtTag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    // element type and elements, the type is kept for empty lists
+    List(u8, Vec<NbtTag>),
+    Compound(HashMap<String, NbtTag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl NbtTag {
+    pub fn tag_type(&self) -> u8 {
+        match self {
+            NbtTag::Byte(_) => TAG_BYTE,
+            NbtTag::Short(_) => TAG_SHORT,
+            NbtTag::Int(_) => TAG_INT,
+            NbtTag::Long(_) => TAG_LONG,
+            NbtTag::Float(_) => TAG_FLOAT,
+            NbtTag::Double(_) => TAG_DOUBLE,
+            NbtTag::ByteArray(_) => TAG_BYTE_ARRAY,
+            NbtTag::String(_) => TAG_STRING,
+            NbtTag::List(..) => TAG_LIST,
+            NbtTag::Compound(_) => TAG_COMPOUND,
+            NbtTag::IntArray(_) => TAG_INT_ARRAY,
+            NbtTag::LongArray(_) => TAG_LONG_ARRAY,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&NbtTag> {
+        match self {
+            NbtTag::Compound(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    // any integer tag widened to i64
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            NbtTag::Byte(v) => Some(v as i64),
+            NbtTag::Short(v) => Some(v as i64),
+            NbtTag::Int(v) => Some(v as i64),
+            NbtTag::Long(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            NbtTag::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[NbtTag]> {
+        match self {
+            NbtTag::List(_, list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&HashMap<String, NbtTag>> {
+        match self {
+            NbtTag::Compound(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    // byte arrays as unsigned bytes
+    pub fn as_bytes(&self) -> Option<Vec<u8>> {
+        match self {
+            NbtTag::ByteArray(v) => Some(v.iter().map(|&b| b as u8).collect()),
+            _ => None,
+        }
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// nbt is big endian
+struct NbtReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> NbtReader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or_else(|| invalid_data("unexpected end of nbt data."))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn len(&mut self) -> io::Result<usize> {
+        let len = i32::from_be_bytes(self.array()?);
+        if len < 0 {
+            return Err(invalid_data("negative length in nbt data."));
+        }
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = u16::from_be_bytes(self.array()?) as usize;
+        // java modified utf-8, plain utf-8 for everything but nul and supplementary characters
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn payload(&mut self, tag_type: u8, level: u32) -> io::Result<NbtTag> {
+        if level > MAX_NBT_DEPTH {
+            return Err(invalid_data("nbt data nested too deep."));
+        }
+
+        Ok(match tag_type {
+            TAG_BYTE => NbtTag::Byte(self.array::<1>()?[0] as i8),
+            TAG_SHORT => NbtTag::Short(i16::from_be_bytes(self.array()?)),
+            TAG_INT => NbtTag::Int(i32::from_be_bytes(self.array()?)),
+            TAG_LONG => NbtTag::Long(i64::from_be_bytes(self.array()?)),
+            TAG_FLOAT => NbtTag::Float(f32::from_be_bytes(self.array()?)),
+            TAG_DOUBLE => NbtTag::Double(f64::from_be_bytes(self.array()?)),
+            TAG_BYTE_ARRAY => {
+                let len = self.len()?;
+                NbtTag::ByteArray(self.take(len)?.iter().map(|&b| b as i8).collect())
+            }
+            TAG_STRING => NbtTag::String(self.string()?),
+            TAG_LIST => {
+                let element_type = self.array::<1>()?[0];
+                let len = self.len()?;
+                if element_type == TAG_END && len > 0 {
+                    return Err(invalid_data("list of end tags in nbt data."));
+                }
+                let list = (0..len).map(|_| self.payload(element_type, level + 1)).collect::<io::Result<_>>()?;
+                NbtTag::List(element_type, list)
+            }
+            TAG_COMPOUND => {
+                let mut map = HashMap::new();
+                loop {
+                    let child_type = self.array::<1>()?[0];
+                    if child_type == TAG_END {
+                        break;
+                    }
+                    let name = self.string()?;
+                    map.insert(name, self.payload(child_type, level + 1)?);
+                }
+                NbtTag::Compound(map)
+            }
+            TAG_INT_ARRAY => {
+                let len = self.len()?;
+                NbtTag::IntArray((0..len).map(|_| Ok(i32::from_be_bytes(self.array()?))).collect::<io::Result<_>>()?)
+            }
+            TAG_LONG_ARRAY => {
+                let len = self.len()?;
+                NbtTag::LongArray((0..len).map(|_| Ok(i64::from_be_bytes(self.array()?))).collect::<io::Result<_>>()?)
+            }
+            _ => return Err(invalid_data("unknown tag type in nbt data.")),
+        })
+    }
+}
+
+// reads the named root tag, gzip compressed data is decompressed first
+pub fn parse_nbt(data: &[u8]) -> io::Result<(String, NbtTag)> {
+    if is_gzip(data) {
+        return parse_nbt(&gzip_decode(data, MAX_NBT_SIZE)?);
+    }
+
+    let mut reader = NbtReader { data, pos: 0 };
+    let tag_type = reader.array::<1>()?[0];
+    if tag_type == TAG_END {
+        return Err(invalid_data("nbt data without root tag."));
+    }
+
+    let name = reader.string()?;
+    let tag = reader.payload(tag_type, 0)?;
+
+    Ok((name, tag))
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn write_payload(out: &mut Vec<u8>, tag: &NbtTag) {
+    match tag {
+        NbtTag::Byte(v) => out.push(*v as u8),
+        NbtTag::Short(v) => out.extend_from_slice(&v.to_be_bytes()),
+        NbtTag::Int(v) => out.extend_from_slice(&v.to_be_bytes()),
+        NbtTag::Long(v) => out.extend_from_slice(&v.to_be_bytes()),
+        NbtTag::Float(v) => out.extend_from_slice(&v.to_be_bytes()),
+        NbtTag::Double(v) => out.extend_from_slice(&v.to_be_bytes()),
+        NbtTag::ByteArray(v) => {
+            out.extend_from_slice(&(v.len() as i32).to_be_bytes());
+            out.extend(v.iter().map(|&b| b as u8));
+        }
+        NbtTag::String(v) => write_string(out, v),
+        NbtTag::List(element_type, list) => {
+            out.push(*element_type);
+            out.extend_from_slice(&(list.len() as i32).to_be_bytes());
+            for element in list {
+                write_payload(out, element);
+            }
+        }
+        NbtTag::Compound(map) => {
+            // sorted keys keep the output deterministic
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            for key in keys {
+                out.push(map[key].tag_type());
+                write_string(out, key);
+                write_payload(out, &map[key]);
+            }
+            out.push(TAG_END);
+        }
+        NbtTag::IntArray(v) => {
+            out.extend_from_slice(&(v.len() as i32).to_be_bytes());
+            v.iter().for_each(|i| out.extend_from_slice(&i.to_be_bytes()));
+        }
+        NbtTag::LongArray(v) => {
+            out.extend_from_slice(&(v.len() as i32).to_be_bytes());
+            v.iter().for_each(|i| out.extend_from_slice(&i.to_be_bytes()));
+        }
+    }
+}
+
+// uncompressed nbt with a named root tag
+pub fn encode_nbt(name: &str, tag: &NbtTag) -> Vec<u8> {
+    let mut out = vec![tag.tag_type()];
+    write_string(&mut out, name);
+    write_payload(&mut out, tag);
+    out
+}
@@ -0,0 +1,197 @@
+use crate::vox::{index_to_pos, morton_decode_3d, morton_encode_3d, pos_to_index, DEFAULT_VOX_MAT};
+use glam::UVec3;
+use std::io;
+
+// one bit per voxel in the index order of the grid it was built from, linear or morton
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OccupancyChunk {
+    pub res: u32,
+    pub morton_encoded: bool,
+    pub bits: Vec<u64>,
+}
+
+impl OccupancyChunk {
+    // voxel indices are u32, so res^3 has to fit one
+    pub fn new(res: u32, morton_encoded: bool) -> io::Result<Self> {
+        let size = res.checked_pow(3).filter(|&size| size > 0).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "occupancy chunk resolution must be non zero and fit 32 bit voxel indices.")
+        })?;
+        Ok(Self { res, morton_encoded, bits: vec![0; (size as usize).div_ceil(64)] })
+    }
+
+    // every non zero voxel is occupied
+    pub fn from_grid(grid: &[u8], res: u32, morton_encoded: bool) -> io::Result<Self> {
+        let mut chunk = Self::new(res, morton_encoded)?;
+
+        if grid.len() != chunk.size() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "grid length does not match the occupancy chunk size."));
+        }
+
+        for (word, values) in chunk.bits.iter_mut().zip(grid.chunks(64)) {
+            *word = values.iter().enumerate().fold(0, |w, (i, &v)| w | ((v != 0) as u64) << i);
+        }
+
+        Ok(chunk)
+    }
+
+    // occupied voxels get mat, the result has the same index order as the chunk
+    pub fn to_grid(&self, mat: u8) -> Vec<u8> {
+        (0..self.size()).map(|i| if self.get(i) { mat } else { 0 }).collect()
+    }
+
+    pub fn size(&self) -> usize {
+        (self.res as usize).pow(3)
+    }
+
+    pub fn get(&self, index: usize) -> bool {
+        self.bits[index >> 6] & (1 << (index & 63)) != 0
+    }
+
+    pub fn set(&mut self, index: usize, occupied: bool) {
+        if occupied {
+            self.bits[index >> 6] |= 1 << (index & 63);
+        } else {
+            self.bits[index >> 6] &= !(1 << (index & 63));
+        }
+    }
+
+    // index of a voxel position in the order of this chunk, morton indices only cover 8 bits per axis
+    pub fn index(&self, pos: UVec3) -> usize {
+        if self.morton_encoded {
+            morton_encode_3d(pos.x as u8, pos.y as u8, pos.z as u8) as usize
+        } else {
+            pos_to_index(pos.x, pos.y, pos.z, self.res) as usize
+        }
+    }
+
+    pub fn position(&self, index: usize) -> UVec3 {
+        if self.morton_encoded {
+            let (x, y, z) = morton_decode_3d(index as u32);
+            UVec3::new(x as u32, y as u32, z as u32)
+        } else {
+            index_to_pos(index as u32, self.res)
+        }
+    }
+
+    pub fn get_voxel(&self, pos: UVec3) -> bool {
+        self.get(self.index(pos))
+    }
+
+    pub fn set_voxel(&mut self, pos: UVec3, occupied: bool) {
+        let index = self.index(pos);
+        self.set(index, occupied);
+    }
+
+    // same voxels in the other index order
+    pub fn to_morton(&self) -> Self {
+        self.reorder(true)
+    }
+
+    pub fn to_linear(&self) -> Self {
+        self.reorder(false)
+    }
+
+    fn reorder(&self, morton_encoded: bool) -> Self {
+        let mut chunk = Self { res: self.res, morton_encoded, bits: vec![0; self.bits.len()] };
+        self.for_each_occupied(|index| {
+            let pos = self.position(index);
+            chunk.set_voxel(pos, true);
+        });
+        chunk
+    }
+
+    // visits the occupied indices in order, skipping empty words
+    pub fn for_each_occupied<F: FnMut(usize)>(&self, mut f: F) {
+        for (w, &word) in self.bits.iter().enumerate() {
+            let mut word = word;
+            while word != 0 {
+                f(w * 64 + word.trailing_zeros() as usize);
+                word &= word - 1;
+            }
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.bits.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    // occupied voxels in start..end
+    pub fn count_range(&self, start: usize, end: usize) -> usize {
+        if start >= end {
+            return 0;
+        }
+
+        let range_mask = |from: usize, to: usize| -> u64 {
+            let high = if to == 64 { u64::MAX } else { (1 << to) - 1 };
+            high & !((1u64 << from) - 1)
+        };
+
+        let (first, last) = (start >> 6, (end - 1) >> 6);
+        if first == last {
+            return (self.bits[first] & range_mask(start & 63, ((end - 1) & 63) + 1)).count_ones() as usize;
+        }
+
+        let mut count = (self.bits[first] & range_mask(start & 63, 64)).count_ones() as usize;
+        count += self.bits[first + 1..last].iter().map(|w| w.count_ones() as usize).sum::<usize>();
+        count + (self.bits[last] & range_mask(0, ((end - 1) & 63) + 1)).count_ones() as usize
+    }
+
+    // occupied voxels per node at the given subdivision level (0 is the whole chunk), morton chunks keep every node contiguous
+    pub fn node_counts(&self, level: u32) -> io::Result<Vec<usize>> {
+        if !self.morton_encoded || level > self.res.trailing_zeros() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "node counts need a morton chunk and a level within its depth."));
+        }
+
+        let nodes = 1usize << (3 * level);
+        let node_size = self.size() / nodes;
+        Ok((0..nodes).map(|n| self.count_range(n * node_size, (n + 1) * node_size)).collect())
+    }
+
+    pub fn fill_ratio(&self) -> f32 {
+        self.count() as f32 / self.size() as f32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&w| w == 0)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.count() == self.size()
+    }
+
+    // bits as little endian bytes, bit i is bit i % 8 of byte i / 8
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = self.bits.iter().flat_map(|w| w.to_le_bytes()).collect();
+        bytes.truncate(self.size().div_ceil(8));
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8], res: u32, morton_encoded: bool) -> io::Result<Self> {
+        let mut chunk = Self::new(res, morton_encoded)?;
+
+        if bytes.len() != chunk.size().div_ceil(8) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "occupancy data does not match the chunk size."));
+        }
+
+        for (word, b) in chunk.bits.iter_mut().zip(bytes.chunks(8)) {
+            let mut le = [0u8; 8];
+            le[..b.len()].copy_from_slice(b);
+            *word = u64::from_le_bytes(le);
+        }
+
+        Ok(chunk)
+    }
+}
+
+// the single non zero value of a chunk, none if it holds several materials
+pub fn occupancy_mat(grid: &[u8]) -> Option<u8> {
+    let mut mat = None;
+
+    for &v in grid {
+        if v != 0 && mat.get_or_insert(v) != &v {
+            return None;
+        }
+    }
+
+    Some(mat.unwrap_or(DEFAULT_VOX_MAT))
+}
@@ -0,0 +1,40 @@
+pub const PALETTE_SIZE: usize = 256;
+
+// rgba colors indexed by the voxel value, index 0 is empty space
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [[u8; 4]; PALETTE_SIZE],
+}
+
+impl Palette {
+    pub fn new() -> Self {
+        Self { colors: [[0; 4]; PALETTE_SIZE] }
+    }
+
+    pub fn get(&self, index: u8) -> [u8; 4] {
+        self.colors[index as usize]
+    }
+
+    pub fn set(&mut self, index: u8, color: [u8; 4]) {
+        self.colors[index as usize] = color;
+    }
+
+    // closest solid entry (1..=255) by squared rgb distance
+    pub fn nearest(&self, color: [u8; 3]) -> u8 {
+        let distance = |c: &[u8; 4]| (0..3).map(|i| (c[i] as i32 - color[i] as i32).pow(2)).sum::<i32>();
+
+        (1..PALETTE_SIZE).min_by_key(|&i| distance(&self.colors[i])).unwrap() as u8
+    }
+}
+
+impl Default for Palette {
+    // opaque gray ramp so every material is visible
+    fn default() -> Self {
+        let mut palette = Self::new();
+        for i in 1..PALETTE_SIZE {
+            let v = (PALETTE_SIZE - i) as u8;
+            palette.colors[i] = [v, v, v, u8::MAX];
+        }
+        palette
+    }
+}
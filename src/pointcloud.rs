@@ -0,0 +1,416 @@
+use crate::palette::Palette;
+use crate::svo::SVO;
+use crate::vox::{pos_to_index, DEFAULT_VOX_MAT};
+use crate::voxelize::GridTransform;
+use glam::{UVec3, Vec3};
+use std::{collections::HashMap, fs::File, io, io::{BufReader, Read}, path::Path};
+
+// colors are either empty or one per position
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PointCloud {
+    pub positions: Vec<Vec3>,
+    pub colors: Vec<[u8; 3]>,
+}
+
+impl PointCloud {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn has_colors(&self) -> bool {
+        !self.colors.is_empty()
+    }
+
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        self.positions.iter().fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), &p| (min.min(p), max.max(p)))
+    }
+
+    // fits the bounding box of the points into a grid of the given resolution, keeping the aspect ratio
+    pub fn fit(&self, grid_res: u32) -> GridTransform {
+        let (min, max) = self.bounds();
+        GridTransform::fit_bounds(min, max, grid_res)
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum PlyType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> io::Result<Self> {
+        Ok(match name {
+            "char" | "int8" => PlyType::I8,
+            "uchar" | "uint8" => PlyType::U8,
+            "short" | "int16" => PlyType::I16,
+            "ushort" | "uint16" => PlyType::U16,
+            "int" | "int32" => PlyType::I32,
+            "uint" | "uint32" => PlyType::U32,
+            "float" | "float32" => PlyType::F32,
+            "double" | "float64" => PlyType::F64,
+            _ => return Err(invalid_data("unknown ply property type.")),
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            PlyType::I8 | PlyType::U8 => 1,
+            PlyType::I16 | PlyType::U16 => 2,
+            PlyType::I32 | PlyType::U32 | PlyType::F32 => 4,
+            PlyType::F64 => 8,
+        }
+    }
+}
+
+struct PlyProperty {
+    name: String,
+    data_type: PlyType,
+    // count type of list properties
+    list: Option<PlyType>,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+// reads scalar values from the body of a ply file
+struct PlyReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    format: PlyFormat,
+}
+
+impl PlyReader<'_> {
+    fn next_token(&mut self) -> io::Result<&str> {
+        while self.pos < self.data.len() && self.data[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+
+        let start = self.pos;
+        while self.pos < self.data.len() && !self.data[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+
+        if start == self.pos {
+            return Err(invalid_data("unexpected end of ply data."));
+        }
+
+        std::str::from_utf8(&self.data[start..self.pos]).map_err(|_| invalid_data("invalid ply data."))
+    }
+
+    fn read(&mut self, data_type: PlyType) -> io::Result<f64> {
+        if self.format == PlyFormat::Ascii {
+            return self.next_token()?.parse().map_err(|_| invalid_data("invalid ply value."));
+        }
+
+        let size = data_type.size();
+        let bytes = self.data.get(self.pos..self.pos + size).ok_or_else(|| invalid_data("unexpected end of ply data."))?;
+        self.pos += size;
+
+        let mut b = [0u8; 8];
+        b[..size].copy_from_slice(bytes);
+        if self.format == PlyFormat::BinaryBigEndian {
+            b[..size].reverse();
+        }
+
+        Ok(match data_type {
+            PlyType::I8 => b[0] as i8 as f64,
+            PlyType::U8 => b[0] as f64,
+            PlyType::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            PlyType::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            PlyType::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            PlyType::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            PlyType::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            PlyType::F64 => f64::from_le_bytes(b),
+        })
+    }
+}
+
+// color properties are either 0..=255 integers or 0.0..=1.0 floats
+fn ply_color(value: f64, data_type: PlyType) -> u8 {
+    match data_type {
+        PlyType::F32 | PlyType::F64 => (value * 255.0).round().clamp(0.0, 255.0) as u8,
+        _ => value.clamp(0.0, 255.0) as u8,
+    }
+}
+
+// reads the vertex positions and colors of an ascii or binary ply file, other elements are skipped
+pub fn parse_ply(data: &[u8]) -> io::Result<PointCloud> {
+    if !data.starts_with(b"ply\n") && !data.starts_with(b"ply\r\n") {
+        return Err(invalid_data("data is not a ply file."));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    let mut offset = data.iter().position(|&b| b == b'\n').ok_or_else(|| invalid_data("unexpected end of ply header."))? + 1;
+
+    loop {
+        let end = data[offset..].iter().position(|&b| b == b'\n').ok_or_else(|| invalid_data("unexpected end of ply header."))?;
+        let line = std::str::from_utf8(&data[offset..offset + end]).map_err(|_| invalid_data("invalid ply header."))?;
+        offset += end + 1;
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            ["format", name, _] => {
+                format = Some(match *name {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    _ => return Err(invalid_data("unknown ply format.")),
+                });
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| invalid_data("invalid ply element count."))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_type, data_type, name] => {
+                let element = elements.last_mut().ok_or_else(|| invalid_data("ply property without element."))?;
+                element.properties.push(PlyProperty { name: name.to_string(), data_type: PlyType::parse(data_type)?, list: Some(PlyType::parse(count_type)?) });
+            }
+            ["property", data_type, name] => {
+                let element = elements.last_mut().ok_or_else(|| invalid_data("ply property without element."))?;
+                element.properties.push(PlyProperty { name: name.to_string(), data_type: PlyType::parse(data_type)?, list: None });
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            ["end_header"] => break,
+            _ => return Err(invalid_data("unknown ply header line.")),
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid_data("ply file without format."))?;
+    let mut reader = PlyReader { data, pos: offset, format };
+    let mut cloud = PointCloud::new();
+
+    for element in &elements {
+        let is_vertex = element.name == "vertex";
+        let find = |names: &[&str]| element.properties.iter().position(|p| p.list.is_none() && names.contains(&p.name.as_str()));
+
+        let xyz = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let rgb = [find(&["red", "r", "diffuse_red"]), find(&["green", "g", "diffuse_green"]), find(&["blue", "b", "diffuse_blue"])];
+
+        if is_vertex && xyz.iter().any(Option::is_none) {
+            return Err(invalid_data("ply vertices without positions."));
+        }
+        let has_colors = is_vertex && rgb.iter().all(Option::is_some);
+
+        let mut values = vec![0.0; element.properties.len()];
+
+        for _ in 0..element.count {
+            for (i, property) in element.properties.iter().enumerate() {
+                match property.list {
+                    Some(count_type) => {
+                        let count = reader.read(count_type)? as usize;
+                        for _ in 0..count {
+                            reader.read(property.data_type)?;
+                        }
+                    }
+                    None => values[i] = reader.read(property.data_type)?,
+                }
+            }
+
+            if is_vertex {
+                cloud.positions.push(Vec3::from_array(xyz.map(|i| values[i.unwrap()] as f32)));
+                if has_colors {
+                    cloud.colors.push(rgb.map(|i| ply_color(values[i.unwrap()], element.properties[i.unwrap()].data_type)));
+                }
+            }
+        }
+
+        // nothing after the vertices is needed
+        if is_vertex {
+            break;
+        }
+    }
+
+    Ok(cloud)
+}
+
+// one point per line as "x y z" with optional trailing "r g b", whitespace or comma separated
+// an intensity column before the colors is skipped, colors that are all within 0.0..=1.0 are scaled up
+pub fn parse_xyz(data: &[u8]) -> io::Result<PointCloud> {
+    let text = std::str::from_utf8(data).map_err(|_| invalid_data("invalid xyz data."))?;
+    let mut cloud = PointCloud::new();
+    let mut colors: Vec<[f32; 3]> = Vec::new();
+    let mut float_colors = true;
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+
+        let values = line
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|v| !v.is_empty())
+            .map(|v| v.parse::<f32>().map_err(|_| invalid_data("invalid xyz value.")))
+            .collect::<io::Result<Vec<f32>>>()?;
+
+        if values.len() < 3 {
+            return Err(invalid_data("xyz line with less than 3 values."));
+        }
+
+        cloud.positions.push(Vec3::new(values[0], values[1], values[2]));
+
+        if values.len() >= 6 {
+            let c = &values[values.len() - 3..];
+            float_colors &= c.iter().all(|&v| v <= 1.0);
+            colors.push([c[0], c[1], c[2]]);
+        }
+    }
+
+    // either every point has a color or none
+    if colors.len() == cloud.positions.len() {
+        let scale = if float_colors { 255.0 } else { 1.0 };
+        cloud.colors = colors.iter().map(|c| c.map(|v| (v * scale).round().clamp(0.0, 255.0) as u8)).collect();
+    }
+
+    Ok(cloud)
+}
+
+pub fn read_point_cloud(filename: &str) -> io::Result<PointCloud> {
+    let path = Path::new(filename);
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+
+    if buffer.starts_with(b"ply") {
+        parse_ply(&buffer)
+    } else {
+        parse_xyz(&buffer)
+    }
+}
+
+// sum of the colors and the number of points falling into a voxel
+type Bin = ([u32; 3], u32);
+
+// bins the points in grid space, points outside of the grid are dropped
+fn bin_points(cloud: &PointCloud, grid_res: u32, transform: GridTransform) -> HashMap<UVec3, Bin> {
+    let mut bins: HashMap<UVec3, Bin> = HashMap::new();
+    let res = grid_res as f32;
+
+    for (i, &p) in cloud.positions.iter().enumerate() {
+        let p = transform.to_grid(p);
+        // points on the max face of a fitted grid belong to the last voxel
+        if !(p.cmpge(Vec3::ZERO).all() && p.cmple(Vec3::splat(res)).all()) {
+            continue;
+        }
+
+        let voxel = p.floor().as_uvec3().min(UVec3::splat(grid_res - 1));
+        let bin = bins.entry(voxel).or_insert(([0; 3], 0));
+        if let Some(c) = cloud.colors.get(i) {
+            (0..3).for_each(|j| bin.0[j] += c[j] as u32);
+        }
+        bin.1 += 1;
+    }
+
+    bins
+}
+
+fn average(bin: &Bin) -> [u8; 4] {
+    let c = bin.0.map(|v| ((v + bin.1 / 2) / bin.1) as u8);
+    [c[0], c[1], c[2], u8::MAX]
+}
+
+fn bin_mat(cloud: &PointCloud, bin: &Bin, palette: Option<&Palette>) -> u8 {
+    match palette {
+        Some(palette) if cloud.has_colors() => {
+            let c = average(bin);
+            palette.nearest([c[0], c[1], c[2]])
+        }
+        _ => DEFAULT_VOX_MAT,
+    }
+}
+
+// grid holds the voxel values, colors is the averaged point color per voxel (empty if the cloud has no colors)
+#[derive(Clone, Debug, PartialEq)]
+pub struct PointGrid {
+    pub grid_res: u32,
+    pub grid: Vec<u8>,
+    pub colors: Vec<[u8; 4]>,
+}
+
+// bins the points into a linear grid, with a palette the voxels get the entry nearest to their average color
+pub fn voxelize_points(cloud: &PointCloud, grid_res: u32, palette: Option<&Palette>) -> Result<PointGrid, String> {
+    voxelize_points_with(cloud, grid_res, cloud.fit(grid_res), palette)
+}
+
+pub fn voxelize_points_with(cloud: &PointCloud, grid_res: u32, transform: GridTransform, palette: Option<&Palette>) -> Result<PointGrid, String> {
+    if grid_res == 0 {
+        return Err("grid resolution has to be at least 1.".to_string());
+    }
+    let size = (grid_res as usize).checked_pow(3).ok_or_else(|| "grid resolution is too large.".to_string())?;
+
+    let mut grid = vec![0u8; size];
+    let mut colors = if cloud.has_colors() { vec![[0u8; 4]; size] } else { Vec::new() };
+
+    for (voxel, bin) in bin_points(cloud, grid_res, transform) {
+        let index = pos_to_index(voxel.x, voxel.y, voxel.z, grid_res) as usize;
+        grid[index] = bin_mat(cloud, &bin, palette);
+        if cloud.has_colors() {
+            colors[index] = average(&bin);
+        }
+    }
+
+    Ok(PointGrid { grid_res, grid, colors })
+}
+
+// inserts the binned points straight into an svo, returns the averaged colors by leaf position
+pub fn voxelize_points_svo(cloud: &PointCloud, grid_res: u32, palette: Option<&Palette>) -> Result<(SVO, HashMap<UVec3, [u8; 4]>), String> {
+    voxelize_points_svo_with(cloud, grid_res, cloud.fit(grid_res), palette)
+}
+
+pub fn voxelize_points_svo_with(
+    cloud: &PointCloud,
+    grid_res: u32,
+    transform: GridTransform,
+    palette: Option<&Palette>,
+) -> Result<(SVO, HashMap<UVec3, [u8; 4]>), String> {
+    if !grid_res.is_power_of_two() {
+        return Err("grid resolution has to be a power of two.".to_string());
+    }
+
+    let mut svo = SVO::new(grid_res.trailing_zeros() as u8);
+    let mut colors = HashMap::new();
+
+    for (voxel, bin) in bin_points(cloud, grid_res, transform) {
+        // the root span equals the grid resolution so grid space is svo space
+        let node_idx = svo.insert_node_at_depth(voxel.as_vec3() + Vec3::splat(0.5), svo.depth);
+        svo.nodes[node_idx] = bin_mat(cloud, &bin, palette) as u32;
+
+        if cloud.has_colors() {
+            colors.insert(voxel, average(&bin));
+        }
+    }
+
+    Ok((svo, colors))
+}
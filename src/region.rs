@@ -0,0 +1,469 @@
+use crate::atomic::{replace_atomic, sync_parent, write_atomic};
+use crate::bsvo::{decode_bsvo, encode_bsvo, BsvoHeader};
+use crate::bvox::{decode_bvox, encode_bvox, BvoxHeader};
+use crate::codec::Codec;
+use crate::vox::pos_to_index;
+use crate::world::{ChunkStore, WorldChunk};
+use glam::{IVec3, UVec3};
+use std::collections::HashMap;
+use std::{fs::{self, File, OpenOptions}, io::{self, Read, Seek, SeekFrom, Write}, path::{Path, PathBuf}};
+
+pub const REGION_MAGIC: [u8; 4] = *b"BREG";
+pub const REGION_VERSION: u8 = 1;
+pub const REGION_HEADER_SIZE: usize = 16;
+pub const REGION_SECTOR_SIZE: usize = 4096;
+// chunks per axis of a region
+pub const DEFAULT_REGION_SIZE: u32 = 32;
+pub const MAX_REGION_SIZE: u32 = 256;
+// first sector and byte length of every chunk in the allocation table
+pub const REGION_ENTRY_SIZE: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RegionHeader {
+    version: u8,
+    pub region_size: u32,
+}
+
+impl RegionHeader {
+    pub fn new(region_size: u32) -> io::Result<Self> {
+        if region_size == 0 || region_size > MAX_REGION_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "region size is out of range."));
+        }
+        Ok(Self { version: REGION_VERSION, region_size })
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        (self.region_size * self.region_size * self.region_size) as usize
+    }
+
+    // the header and the allocation table come first and are padded to whole sectors
+    pub fn table_sectors(&self) -> u32 {
+        (REGION_HEADER_SIZE + self.chunk_count() * REGION_ENTRY_SIZE).div_ceil(REGION_SECTOR_SIZE) as u32
+    }
+
+    pub fn to_bytes(&self) -> [u8; REGION_HEADER_SIZE] {
+        let mut bytes = [0u8; REGION_HEADER_SIZE];
+        bytes[0x00..0x04].copy_from_slice(&REGION_MAGIC);
+        bytes[0x04] = REGION_VERSION;
+        bytes[0x08..0x0C].copy_from_slice(&self.region_size.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; REGION_HEADER_SIZE]) -> io::Result<Self> {
+        if bytes[0x00..0x04] != REGION_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "file is not a region file."));
+        }
+
+        let version = bytes[0x04];
+
+        if version > REGION_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "newer region reader version required for file."));
+        }
+
+        if version < REGION_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "file version is outdated, use older region reader."));
+        }
+
+        let region_size = u32::from_le_bytes(bytes[0x08..0x0C].try_into().unwrap());
+        if region_size == 0 || region_size > MAX_REGION_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "region size is out of range."));
+        }
+
+        Ok(Self { version, region_size })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct RegionEntry {
+    sector: u32,
+    len: u32,
+}
+
+impl RegionEntry {
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn sectors(&self) -> u32 {
+        (self.len as usize).div_ceil(REGION_SECTOR_SIZE) as u32
+    }
+
+    fn range(&self) -> std::ops::Range<usize> {
+        self.sector as usize..self.sector as usize + self.sectors() as usize
+    }
+
+    fn to_bytes(self) -> [u8; REGION_ENTRY_SIZE] {
+        let mut bytes = [0u8; REGION_ENTRY_SIZE];
+        bytes[0..4].copy_from_slice(&self.sector.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.len.to_le_bytes());
+        bytes
+    }
+}
+
+// many chunks in one file split into fixed size sectors, every chunk occupies a run of sectors
+// listed in the allocation table so it can be rewritten, grown or removed without touching the others.
+// chunks are written copy on write, the new sectors are synced before the table entry points at them,
+// so a crash leaves either the old or the new chunk
+pub struct RegionFile {
+    pub header: RegionHeader,
+    path: String,
+    file: File,
+    table: Vec<RegionEntry>,
+    // one flag per sector of the file, the header and table sectors are always used
+    used: Vec<bool>,
+}
+
+impl RegionFile {
+    pub fn create(filename: &str, region_size: u32) -> io::Result<Self> {
+        let header = RegionHeader::new(region_size)?;
+
+        // a crash never leaves a region file with a partial table behind
+        let mut bytes = vec![0u8; header.table_sectors() as usize * REGION_SECTOR_SIZE];
+        bytes[..REGION_HEADER_SIZE].copy_from_slice(&header.to_bytes());
+        write_atomic(filename, &bytes, false)?;
+
+        Self::open(filename)
+    }
+
+    pub fn open(filename: &str) -> io::Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).open(filename)?;
+
+        let mut buffer = [0u8; REGION_HEADER_SIZE];
+        file.read_exact(&mut buffer)?;
+        let header = RegionHeader::from_bytes(&buffer)?;
+
+        let mut bytes = vec![0u8; header.chunk_count() * REGION_ENTRY_SIZE];
+        file.read_exact(&mut bytes)?;
+
+        let sectors = (file.metadata()?.len() as usize).div_ceil(REGION_SECTOR_SIZE);
+        let mut used = vec![false; sectors.max(header.table_sectors() as usize)];
+        used[..header.table_sectors() as usize].fill(true);
+
+        let mut table = Vec::with_capacity(header.chunk_count());
+        for entry in bytes.chunks_exact(REGION_ENTRY_SIZE) {
+            let entry = RegionEntry {
+                sector: u32::from_le_bytes(entry[0..4].try_into().unwrap()),
+                len: u32::from_le_bytes(entry[4..8].try_into().unwrap()),
+            };
+
+            if !entry.is_empty() {
+                let end = entry.sector.checked_add(entry.sectors()).map_or(usize::MAX, |end| end as usize);
+                if end > used.len() || used[entry.range()].iter().any(|&u| u) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "region allocation table points outside the file or to overlapping sectors."));
+                }
+                used[entry.range()].fill(true);
+            }
+            table.push(entry);
+        }
+
+        Ok(Self { header, path: filename.to_string(), file, table, used })
+    }
+
+    pub fn open_or_create(filename: &str, region_size: u32) -> io::Result<Self> {
+        if fs::exists(filename)? {
+            let region = Self::open(filename)?;
+            if region.header.region_size != region_size {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "region file has a different region size."));
+            }
+            return Ok(region);
+        }
+        Self::create(filename, region_size)
+    }
+
+    fn index(&self, local: UVec3) -> io::Result<usize> {
+        if local.cmpge(UVec3::splat(self.header.region_size)).any() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk position is outside the region."));
+        }
+        Ok(pos_to_index(local.x, local.y, local.z, self.header.region_size) as usize)
+    }
+
+    pub fn contains(&self, local: UVec3) -> io::Result<bool> {
+        Ok(!self.table[self.index(local)?].is_empty())
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.iter().filter(|entry| !entry.is_empty()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // positions of all stored chunks inside the region
+    pub fn chunks(&self) -> Vec<UVec3> {
+        let size = self.header.region_size;
+        (0..self.table.len())
+            .filter(|&i| !self.table[i].is_empty())
+            .map(|i| UVec3::new(i as u32 % size, i as u32 / size % size, i as u32 / (size * size)))
+            .collect()
+    }
+
+    pub fn sector_count(&self) -> usize {
+        self.used.len()
+    }
+
+    // sectors inside the file that no chunk uses, reclaimed by compact
+    pub fn free_sectors(&self) -> usize {
+        self.used.iter().filter(|&&u| !u).count()
+    }
+
+    pub fn read_chunk(&mut self, local: UVec3) -> io::Result<Option<Vec<u8>>> {
+        let entry = self.table[self.index(local)?];
+        if entry.is_empty() {
+            return Ok(None);
+        }
+
+        let mut data = vec![0u8; entry.len as usize];
+        self.file.seek(SeekFrom::Start(entry.sector as u64 * REGION_SECTOR_SIZE as u64))?;
+        self.file.read_exact(&mut data)?;
+        Ok(Some(data))
+    }
+
+    // the chunk goes to the first free run of sectors that is long enough, its old sectors are freed afterwards
+    pub fn write_chunk(&mut self, local: UVec3, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "region chunk data is empty."));
+        }
+        let len = u32::try_from(data.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "region chunk data is too large."))?;
+
+        let index = self.index(local)?;
+        let old = self.table[index];
+
+        // the old sectors are still marked as used, so the new copy never overwrites them
+        let sectors = RegionEntry { sector: 0, len }.sectors();
+        let entry = RegionEntry { sector: self.allocate(sectors), len };
+
+        let mut bytes = data.to_vec();
+        bytes.resize(sectors as usize * REGION_SECTOR_SIZE, 0);
+        self.file.seek(SeekFrom::Start(entry.sector as u64 * REGION_SECTOR_SIZE as u64))?;
+        self.file.write_all(&bytes)?;
+        self.file.sync_data()?;
+        self.write_entry(index, entry)?;
+
+        if !old.is_empty() {
+            self.free(old);
+        }
+        self.mark(entry);
+        self.trim()
+    }
+
+    pub fn remove_chunk(&mut self, local: UVec3) -> io::Result<bool> {
+        let index = self.index(local)?;
+        let old = self.table[index];
+        if old.is_empty() {
+            return Ok(false);
+        }
+
+        self.write_entry(index, RegionEntry::default())?;
+        self.free(old);
+        self.trim()?;
+        Ok(true)
+    }
+
+    // rewrites the region into a temp file with all chunks packed behind the table and renames it
+    // over the original, returns the bytes reclaimed
+    pub fn compact(&mut self) -> io::Result<u64> {
+        let before = self.used.len();
+        let mut table = vec![RegionEntry::default(); self.table.len()];
+        let mut next = self.header.table_sectors();
+
+        let (source, old_table) = (&mut self.file, &self.table);
+        replace_atomic(&self.path, false, |file| {
+            let mut bytes = vec![0u8; next as usize * REGION_SECTOR_SIZE];
+            file.write_all(&bytes)?;
+
+            for (index, entry) in old_table.iter().enumerate().filter(|(_, entry)| !entry.is_empty()) {
+                bytes.resize(entry.sectors() as usize * REGION_SECTOR_SIZE, 0);
+                bytes.fill(0);
+                source.seek(SeekFrom::Start(entry.sector as u64 * REGION_SECTOR_SIZE as u64))?;
+                source.read_exact(&mut bytes[..entry.len as usize])?;
+                file.write_all(&bytes)?;
+
+                table[index] = RegionEntry { sector: next, ..*entry };
+                next += entry.sectors();
+            }
+
+            let mut head = self.header.to_bytes().to_vec();
+            for entry in &table {
+                head.extend_from_slice(&entry.to_bytes());
+            }
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&head)
+        })?;
+
+        self.file = OpenOptions::new().read(true).write(true).open(&self.path)?;
+        self.table = table;
+        self.used = vec![true; next as usize];
+        Ok(((before - self.used.len()) * REGION_SECTOR_SIZE) as u64)
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+
+    // the entry is synced before the sectors it replaces can be reused
+    fn write_entry(&mut self, index: usize, entry: RegionEntry) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start((REGION_HEADER_SIZE + index * REGION_ENTRY_SIZE) as u64))?;
+        self.file.write_all(&entry.to_bytes())?;
+        self.file.sync_data()?;
+        self.table[index] = entry;
+        Ok(())
+    }
+
+    // first run of free sectors that is long enough, or the end of the file
+    fn allocate(&mut self, sectors: u32) -> u32 {
+        let sectors = sectors as usize;
+        let mut run = 0;
+        for (i, &used) in self.used.iter().enumerate() {
+            run = if used { 0 } else { run + 1 };
+            if run == sectors {
+                return (i + 1 - sectors) as u32;
+            }
+        }
+
+        // a free run at the end of the file is extended
+        let start = self.used.len() - run;
+        self.used.resize(start + sectors, false);
+        start as u32
+    }
+
+    fn mark(&mut self, entry: RegionEntry) {
+        self.used[entry.range()].fill(true);
+    }
+
+    fn free(&mut self, entry: RegionEntry) {
+        self.used[entry.range()].fill(false);
+    }
+
+    // free sectors at the end of the file are given back right away
+    fn trim(&mut self) -> io::Result<()> {
+        let len = self.used.iter().rposition(|&u| u).map_or(0, |i| i + 1);
+        if len < self.used.len() {
+            self.used.truncate(len);
+        }
+        if self.file.metadata()?.len() != (len * REGION_SECTOR_SIZE) as u64 {
+            self.file.set_len((len * REGION_SECTOR_SIZE) as u64)?;
+        }
+        Ok(())
+    }
+}
+
+// kind of world chunk in front of its data inside a region
+const REGION_CHUNK_DENSE: u8 = 0;
+const REGION_CHUNK_SVO: u8 = 1;
+
+// chunk store that bundles region_size^3 chunks into each region file
+pub struct RegionStore {
+    pub dir: PathBuf,
+    pub chunk_res: u32,
+    pub region_size: u32,
+    regions: HashMap<IVec3, RegionFile>,
+}
+
+impl RegionStore {
+    pub fn new(dir: &str, chunk_res: u32, region_size: u32) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self { dir: PathBuf::from(dir), chunk_res, region_size, regions: HashMap::new() })
+    }
+
+    // region coordinate and position inside the region of a chunk
+    pub fn region_coord(&self, coord: IVec3) -> (IVec3, UVec3) {
+        let size = IVec3::splat(self.region_size as i32);
+        (coord.div_euclid(size), coord.rem_euclid(size).as_uvec3())
+    }
+
+    fn path(&self, region: IVec3) -> String {
+        self.dir.join(format!("{}_{}_{}.region", region.x, region.y, region.z)).to_string_lossy().into_owned()
+    }
+
+    // opens a region that is already on disk, new region files are only created when saving
+    fn region(&mut self, region: IVec3, create: bool) -> io::Result<Option<&mut RegionFile>> {
+        if !self.regions.contains_key(&region) {
+            let path = self.path(region);
+            let file = if fs::exists(&path)? {
+                RegionFile::open_or_create(&path, self.region_size)?
+            } else if create {
+                RegionFile::create(&path, self.region_size)?
+            } else {
+                return Ok(None);
+            };
+            self.regions.insert(region, file);
+        }
+        Ok(self.regions.get_mut(&region))
+    }
+
+    // compacts every region file in the directory, returns the bytes reclaimed
+    pub fn compact(&mut self) -> io::Result<u64> {
+        self.regions.clear();
+
+        let mut reclaimed = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some("region") {
+                reclaimed += RegionFile::open(&path.to_string_lossy())?.compact()?;
+            }
+        }
+        Ok(reclaimed)
+    }
+}
+
+impl ChunkStore for RegionStore {
+    fn load(&mut self, coord: IVec3) -> io::Result<Option<WorldChunk>> {
+        let (region, local) = self.region_coord(coord);
+        let chunk_res = self.chunk_res;
+
+        let data = match self.region(region, false)? {
+            Some(file) => file.read_chunk(local)?,
+            None => None,
+        };
+        let Some(data) = data else {
+            return Ok(None);
+        };
+
+        match data[0] {
+            REGION_CHUNK_DENSE => {
+                let (header, chunks) = decode_bvox(&data[1..])?;
+                if header.chunk_res != chunk_res || header.morton_encoded || chunks.len() != 1 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "region chunk does not hold one linear chunk of the world resolution."));
+                }
+                Ok(chunks.into_iter().next().map(WorldChunk::Dense))
+            }
+            REGION_CHUNK_SVO => {
+                let chunk = WorldChunk::Svo(decode_bsvo(&data[1..])?.1.to_svo());
+                chunk.check_res(chunk_res)?;
+                Ok(Some(chunk))
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "unknown region chunk kind.")),
+        }
+    }
+
+    fn save(&mut self, coord: IVec3, chunk: &WorldChunk) -> io::Result<()> {
+        let data = match chunk {
+            WorldChunk::Dense(grid) => {
+                let size = self.chunk_res * self.chunk_res * self.chunk_res;
+                let header = BvoxHeader::new(self.chunk_res, size, false, false).with_codec(Codec::Auto);
+                [vec![REGION_CHUNK_DENSE], encode_bvox(std::slice::from_ref(grid), header)?].concat()
+            }
+            WorldChunk::Svo(svo) => [vec![REGION_CHUNK_SVO], encode_bsvo(svo, BsvoHeader::new(svo.depth, svo.root_span, true))].concat(),
+        };
+
+        let (region, local) = self.region_coord(coord);
+        self.region(region, true)?.unwrap().write_chunk(local, &data)
+    }
+
+    fn remove(&mut self, coord: IVec3) -> io::Result<()> {
+        let (region, local) = self.region_coord(coord);
+        let Some(file) = self.region(region, false)? else {
+            return Ok(());
+        };
+
+        // a region without chunks is deleted rather than kept as an empty table
+        if file.remove_chunk(local)? && file.is_empty() {
+            self.regions.remove(&region);
+            let path = self.path(region);
+            fs::remove_file(&path)?;
+            sync_parent(Path::new(&path))?;
+        }
+        Ok(())
+    }
+}
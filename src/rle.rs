@@ -4,6 +4,10 @@ pub const RLE_MAX: u8 = u8::MAX - 1;
 
 pub fn run_length_encode(data: &[u8]) -> Vec<u8> {
     let mut encoded = Vec::new();
+    if data.is_empty() {
+        return encoded;
+    }
+
     let mut current = data[0];
     let mut count = 1;
 
@@ -29,7 +33,7 @@ pub fn run_length_encode(data: &[u8]) -> Vec<u8> {
 }
 
 pub fn run_length_decode(data: &[u8]) -> io::Result<Vec<u8>> {
-    if data.len() % 2 != 0 {
+    if !data.len().is_multiple_of(2) {
         return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid encoded vector size."));
     }
 
@@ -42,4 +46,86 @@ pub fn run_length_decode(data: &[u8]) -> io::Result<Vec<u8>> {
     }
 
     Ok(decoded)
-}
\ No newline at end of file
+}
+
+// (value, count) pairs with the count as a little endian base 128 varint, long runs take a few bytes instead of one pair per 254 voxels
+pub fn run_length_encode_varint(data: &[u8]) -> Vec<u8> {
+    encode_runs(data, |encoded, value| encoded.push(value))
+}
+
+// max_len guards against corrupt counts allocating huge buffers
+pub fn run_length_decode_varint(data: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+    decode_runs(data, max_len, |data, pos| {
+        let value = data[*pos];
+        *pos += 1;
+        Ok(value)
+    })
+}
+
+// same with the values as varints too, used for octree node streams
+pub fn run_length_encode_varint_u32(data: &[u32]) -> Vec<u8> {
+    encode_runs(data, |encoded, value| write_varint(encoded, value as u64))
+}
+
+pub fn run_length_decode_varint_u32(data: &[u8], max_len: usize) -> io::Result<Vec<u32>> {
+    decode_runs(data, max_len, |data, pos| {
+        u32::try_from(read_varint(data, pos)?).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "rle value out of range."))
+    })
+}
+
+fn encode_runs<T: Copy + PartialEq>(data: &[T], write_value: impl Fn(&mut Vec<u8>, T)) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    let mut iter = data.iter().peekable();
+
+    while let Some(&value) = iter.next() {
+        let mut count = 1u64;
+        while iter.next_if_eq(&&value).is_some() {
+            count += 1;
+        }
+
+        write_value(&mut encoded, value);
+        write_varint(&mut encoded, count);
+    }
+
+    encoded
+}
+
+fn decode_runs<T: Copy>(data: &[u8], max_len: usize, read_value: impl Fn(&[u8], &mut usize) -> io::Result<T>) -> io::Result<Vec<T>> {
+    let mut decoded = Vec::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let value = read_value(data, &mut pos)?;
+        let count = read_varint(data, &mut pos)?;
+
+        if count > (max_len - decoded.len()) as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "decoded data exceeds the expected size."));
+        }
+        decoded.resize(decoded.len() + count as usize, value);
+    }
+
+    Ok(decoded)
+}
+
+pub fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+pub fn read_varint(data: &[u8], pos: &mut usize) -> io::Result<u64> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*pos).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unexpected end of varint."))?;
+        *pos += 1;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::InvalidData, "varint too long."))
+}
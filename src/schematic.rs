@@ -0,0 +1,344 @@
+use crate::nbt::{parse_nbt, NbtTag};
+use crate::vox::{morton_encode_3d_grid, pos_to_index};
+use glam::{IVec3, UVec3};
+use std::{collections::HashMap, fs::File, io, io::{BufReader, Read}, path::Path};
+
+pub const AIR_BLOCKS: [&str; 3] = ["minecraft:air", "minecraft:cave_air", "minecraft:void_air"];
+
+// block state names like "minecraft:oak_log[axis=y]" or legacy "17:4" ids to palette indices
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockMapping {
+    pub states: HashMap<String, u8>,
+    // used for blocks that aren't in the table, 0 skips them
+    pub fallback: u8,
+}
+
+impl BlockMapping {
+    pub fn new(fallback: u8) -> Self {
+        Self { states: HashMap::new(), fallback }
+    }
+
+    pub fn insert(&mut self, state: &str, index: u8) {
+        self.states.insert(state.to_string(), index);
+    }
+
+    // tries the full state first, then the block name without its properties
+    pub fn lookup(&self, state: &str) -> u8 {
+        if let Some(&index) = self.states.get(state) {
+            return index;
+        }
+
+        let name = state.split('[').next().unwrap_or(state);
+        if let Some(&index) = self.states.get(name) {
+            return index;
+        }
+
+        if AIR_BLOCKS.contains(&name) {
+            0
+        } else {
+            self.fallback
+        }
+    }
+
+    fn lookup_legacy(&self, id: u16, data: u8) -> u8 {
+        if id == 0 {
+            return 0;
+        }
+
+        self.states.get(&format!("{}:{}", id, data)).or_else(|| self.states.get(&id.to_string())).copied().unwrap_or(self.fallback)
+    }
+}
+
+// voxels are stored x fastest, then y, then z
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schematic {
+    pub size: UVec3,
+    pub offset: IVec3,
+    pub voxels: Vec<u8>,
+}
+
+impl Schematic {
+    // fails for sizes whose voxel count overflows or can't be allocated
+    pub fn new(size: UVec3, offset: IVec3) -> io::Result<Self> {
+        let count = voxel_count(size)?;
+
+        let mut voxels = Vec::new();
+        voxels.try_reserve_exact(count).map_err(|_| invalid_data("schematic is too large."))?;
+        voxels.resize(count, 0);
+
+        Ok(Self { size, offset, voxels })
+    }
+
+    pub fn index(&self, pos: UVec3) -> usize {
+        let (sx, sy) = (self.size.x as usize, self.size.y as usize);
+        pos.x as usize + pos.y as usize * sx + pos.z as usize * sx * sy
+    }
+
+    pub fn get(&self, pos: UVec3) -> u8 {
+        self.voxels[self.index(pos)]
+    }
+
+    pub fn set(&mut self, pos: UVec3, value: u8) {
+        let index = self.index(pos);
+        self.voxels[index] = value;
+    }
+
+    // splits the schematic placed at world_pos into chunks, returns (chunk coordinate, chunk) sorted by z, y, x
+    // morton encoded chunks can be passed straight to SVO::from_grid
+    pub fn to_chunks(&self, world_pos: IVec3, chunk_res: u32, morton_encoded: bool) -> io::Result<Vec<(IVec3, Vec<u8>)>> {
+        if chunk_res == 0 || (morton_encoded && (!chunk_res.is_power_of_two() || chunk_res > 256)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid chunk resolution."));
+        }
+
+        let chunk_size = chunk_res.checked_pow(3).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid chunk resolution."))?;
+        let res = IVec3::splat(chunk_res as i32);
+        let mut chunks: HashMap<IVec3, Vec<u8>> = HashMap::new();
+
+        for z in 0..self.size.z {
+            for y in 0..self.size.y {
+                for x in 0..self.size.x {
+                    let pos = UVec3::new(x, y, z);
+                    let value = self.get(pos);
+                    if value == 0 {
+                        continue;
+                    }
+
+                    let world = world_pos + pos.as_ivec3();
+                    let local = world.rem_euclid(res).as_uvec3();
+
+                    let chunk = chunks.entry(world.div_euclid(res)).or_insert_with(|| vec![0; chunk_size as usize]);
+                    chunk[pos_to_index(local.x, local.y, local.z, chunk_res) as usize] = value;
+                }
+            }
+        }
+
+        let mut chunks: Vec<(IVec3, Vec<u8>)> = chunks.into_iter().collect();
+        chunks.sort_by_key(|(coord, _)| (coord.z, coord.y, coord.x));
+
+        if morton_encoded {
+            for (_, chunk) in chunks.iter_mut() {
+                let mut morton_chunk = vec![0u8; chunk_size as usize];
+                morton_encode_3d_grid(chunk, chunk_res, chunk_size, &mut morton_chunk);
+                *chunk = morton_chunk;
+            }
+        }
+
+        Ok(chunks)
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn voxel_count(size: UVec3) -> io::Result<usize> {
+    (size.x as usize)
+        .checked_mul(size.y as usize)
+        .and_then(|n| n.checked_mul(size.z as usize))
+        .ok_or_else(|| invalid_data("schematic is too large."))
+}
+
+fn get_int(tag: &NbtTag, key: &str) -> io::Result<i64> {
+    tag.get(key).and_then(NbtTag::as_i64).ok_or_else(|| invalid_data(&format!("missing {} in schematic.", key.to_lowercase())))
+}
+
+// widths are stored as signed shorts but are unsigned
+fn get_size(tag: &NbtTag) -> io::Result<UVec3> {
+    let dim = |key| get_int(tag, key).map(|v| v as u16 as u32);
+    Ok(UVec3::new(dim("Width")?, dim("Height")?, dim("Length")?))
+}
+
+fn get_offset(tag: &NbtTag, key: &str) -> IVec3 {
+    match tag.get(key) {
+        Some(NbtTag::IntArray(v)) if v.len() == 3 => IVec3::new(v[0], v[1], v[2]),
+        _ => IVec3::ZERO,
+    }
+}
+
+// sponge block data is a sequence of varints indexing the palette, y slowest, then z, then x
+fn decode_varints(data: &[u8], count: usize) -> io::Result<Vec<u32>> {
+    // every value takes at least one byte, so a bogus count can't reserve more than the data
+    let mut values = Vec::with_capacity(count.min(data.len()));
+    let (mut value, mut shift) = (0u32, 0);
+
+    for &byte in data {
+        value |= ((byte & 0x7F) as u32) << shift;
+        if byte & 0x80 == 0 {
+            values.push(value);
+            value = 0;
+            shift = 0;
+        } else {
+            shift += 7;
+            if shift > 28 {
+                return Err(invalid_data("varint too long in schematic block data."));
+            }
+        }
+    }
+
+    if values.len() != count || shift != 0 {
+        return Err(invalid_data("schematic block data does not match the dimensions."));
+    }
+
+    Ok(values)
+}
+
+fn parse_sponge(root: &NbtTag, blocks: &NbtTag, palette_key: &str, data_key: &str, offset: IVec3, mapping: &BlockMapping) -> io::Result<Schematic> {
+    let size = get_size(root)?;
+    let palette = blocks.get(palette_key).and_then(NbtTag::as_compound).ok_or_else(|| invalid_data("missing palette in schematic."))?;
+
+    let mut lookup = HashMap::new();
+    for (state, id) in palette {
+        let id = id.as_i64().ok_or_else(|| invalid_data("invalid palette entry in schematic."))?;
+        lookup.insert(id as u32, mapping.lookup(state));
+    }
+
+    let data = blocks.get(data_key).and_then(NbtTag::as_bytes).ok_or_else(|| invalid_data("missing block data in schematic."))?;
+    let ids = decode_varints(&data, voxel_count(size)?)?;
+
+    let mut schematic = Schematic::new(size, offset)?;
+    let mut i = 0;
+
+    for y in 0..size.y {
+        for z in 0..size.z {
+            for x in 0..size.x {
+                let value = *lookup.get(&ids[i]).ok_or_else(|| invalid_data("block data references a missing palette entry."))?;
+                schematic.set(UVec3::new(x, y, z), value);
+                i += 1;
+            }
+        }
+    }
+
+    Ok(schematic)
+}
+
+// mcedit schematics store numeric block ids and 4 bit data values
+fn parse_legacy(root: &NbtTag, mapping: &BlockMapping) -> io::Result<Schematic> {
+    let size = get_size(root)?;
+    let count = voxel_count(size)?;
+
+    let blocks = root.get("Blocks").and_then(NbtTag::as_bytes).ok_or_else(|| invalid_data("missing blocks in schematic."))?;
+    if blocks.len() != count {
+        return Err(invalid_data("schematic block data does not match the dimensions."));
+    }
+
+    let data = root.get("Data").and_then(NbtTag::as_bytes).unwrap_or_else(|| vec![0; count]);
+    // optional high 4 bits of the block ids, two blocks per byte, even indices in the low nibble
+    let add_blocks = root.get("AddBlocks").and_then(NbtTag::as_bytes);
+
+    if data.len() != count {
+        return Err(invalid_data("schematic block data does not match the dimensions."));
+    }
+
+    let offset = IVec3::new(
+        get_int(root, "WEOffsetX").unwrap_or(0) as i32,
+        get_int(root, "WEOffsetY").unwrap_or(0) as i32,
+        get_int(root, "WEOffsetZ").unwrap_or(0) as i32,
+    );
+
+    let mut schematic = Schematic::new(size, offset)?;
+    let mut i = 0;
+
+    for y in 0..size.y {
+        for z in 0..size.z {
+            for x in 0..size.x {
+                let mut id = blocks[i] as u16;
+                if let Some(add) = add_blocks.as_ref().and_then(|add| add.get(i / 2)) {
+                    let high = if i % 2 == 0 { add & 0x0F } else { add >> 4 };
+                    id |= (high as u16) << 8;
+                }
+
+                schematic.set(UVec3::new(x, y, z), mapping.lookup_legacy(id, data[i] & 0x0F));
+                i += 1;
+            }
+        }
+    }
+
+    Ok(schematic)
+}
+
+// "minecraft:oak_log" with properties {axis: "y"} becomes "minecraft:oak_log[axis=y]"
+fn block_state_name(entry: &NbtTag) -> io::Result<String> {
+    let name = entry.get("Name").and_then(NbtTag::as_str).ok_or_else(|| invalid_data("missing block name in structure palette."))?;
+
+    let Some(properties) = entry.get("Properties").and_then(NbtTag::as_compound) else {
+        return Ok(name.to_string());
+    };
+
+    let mut properties: Vec<String> = properties.iter().map(|(k, v)| format!("{}={}", k, v.as_str().unwrap_or_default())).collect();
+    properties.sort();
+
+    Ok(format!("{}[{}]", name, properties.join(",")))
+}
+
+// vanilla structure block files, blocks are a sparse list of positions and palette states
+fn parse_structure(root: &NbtTag, mapping: &BlockMapping) -> io::Result<Schematic> {
+    let ints = |tag: Option<&NbtTag>| -> io::Result<IVec3> {
+        let list = tag.and_then(NbtTag::as_list).filter(|l| l.len() == 3).ok_or_else(|| invalid_data("invalid position in structure."))?;
+        let v: Vec<i32> = list.iter().map(|t| t.as_i64().unwrap_or(-1) as i32).collect();
+        Ok(IVec3::new(v[0], v[1], v[2]))
+    };
+
+    let size = ints(root.get("size"))?;
+    if size.min_element() < 0 {
+        return Err(invalid_data("invalid structure size."));
+    }
+
+    // structures with random variants store several palettes, the first one is used
+    let palette = match root.get("palette") {
+        Some(palette) => palette,
+        None => root.get("palettes").and_then(NbtTag::as_list).and_then(|p| p.first()).ok_or_else(|| invalid_data("missing palette in structure."))?,
+    };
+    let palette: Vec<u8> = palette.as_list().unwrap_or_default().iter().map(|e| block_state_name(e).map(|s| mapping.lookup(&s))).collect::<io::Result<_>>()?;
+
+    let mut schematic = Schematic::new(size.as_uvec3(), IVec3::ZERO)?;
+    let blocks = root.get("blocks").and_then(NbtTag::as_list).ok_or_else(|| invalid_data("missing blocks in structure."))?;
+
+    for block in blocks {
+        let pos = ints(block.get("pos"))?;
+        let state = get_int(block, "state")?;
+
+        if pos.cmplt(IVec3::ZERO).any() || pos.cmpge(size).any() {
+            return Err(invalid_data("structure block outside of the structure size."));
+        }
+        let value = *palette.get(state as usize).ok_or_else(|| invalid_data("structure block references a missing palette entry."))?;
+
+        schematic.set(pos.as_uvec3(), value);
+    }
+
+    Ok(schematic)
+}
+
+// detects sponge .schem (v1 to v3), mcedit .schematic and structure nbt files, gzip compressed or not
+pub fn parse_schematic(data: &[u8], mapping: &BlockMapping) -> io::Result<Schematic> {
+    let (_, root) = parse_nbt(data)?;
+
+    // sponge v3 nests everything in a "Schematic" compound
+    if let Some(inner) = root.get("Schematic") {
+        let blocks = inner.get("Blocks").ok_or_else(|| invalid_data("missing blocks in schematic."))?;
+        return parse_sponge(inner, blocks, "Palette", "Data", get_offset(inner, "Offset"), mapping);
+    }
+
+    if root.get("BlockData").is_some() {
+        return parse_sponge(&root, &root, "Palette", "BlockData", get_offset(&root, "Offset"), mapping);
+    }
+
+    if root.get("Blocks").is_some() {
+        return parse_legacy(&root, mapping);
+    }
+
+    if root.get("size").is_some() {
+        return parse_structure(&root, mapping);
+    }
+
+    Err(invalid_data("unknown schematic format."))
+}
+
+pub fn read_schematic(filename: &str, mapping: &BlockMapping) -> io::Result<Schematic> {
+    let path = Path::new(filename);
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+
+    parse_schematic(&buffer, mapping)
+}
@@ -1,6 +1,10 @@
-use glam::Vec3;
+use crate::occupancy::OccupancyChunk;
+use crate::vox::{index_to_pos, morton_decode_3d, morton_encode_3d, pos_to_index};
+use glam::{I64Vec3, IVec3, UVec3, Vec3};
 use rand::prelude::StdRng;
 use rand::{Rng, SeedableRng};
+use std::borrow::Cow;
+use std::collections::HashMap;
 
 pub const CHILD_OFFSET: u32 = 24;
 pub const DEFAULT_SVO_MAX_DEPTH: u8 = 8;
@@ -70,6 +74,15 @@ pub fn encode_node(child_mask: u8, first_child_index: u32) -> u32 {
         | (first_child_index & 0b00000000_11111111_11111111_11111111)
 }
 
+// voxels in a chunk, checked since chunk_res^3 overflows u32 from 2^11 on
+fn chunk_voxel_count(chunk_res: u32) -> Result<usize, String> {
+    (chunk_res as u64)
+        .checked_pow(3)
+        .and_then(|count| usize::try_from(count).ok())
+        .ok_or_else(|| "chunk resolution is too large.".to_string())
+}
+
+#[derive(Clone, Debug)]
 pub struct SVO {
     pub nodes: Vec<u32>,
     pub depth: u8,
@@ -100,6 +113,104 @@ impl SVO {
         svo
     }
 
+    // same as from_grid for an occupancy chunk, only occupied voxels are visited
+    pub fn from_occupancy(chunk: &OccupancyChunk, depth: u8, mat: u32) -> SVO {
+        let mut svo = Self {
+            nodes: Vec::from([0]),
+            root_span: chunk.res as f32,
+            depth,
+        };
+
+        chunk.for_each_occupied(|i| {
+            if chunk.morton_encoded {
+                svo.insert_node_morton(i as u32, mat).unwrap();
+            } else {
+                svo.insert_voxel(chunk.position(i), mat).unwrap();
+            }
+        });
+
+        svo
+    }
+
+    // one svo over chunks keyed by chunk coordinate, the upper levels index the chunks and empty chunks stay empty nodes.
+    // returns the svo and the voxel position of its origin, chunk_res has to be a power of two
+    pub fn from_chunks(chunks: &[(IVec3, Vec<u8>)], chunk_res: u32, morton_encoded: bool) -> Result<(SVO, IVec3), String> {
+        if !chunk_res.is_power_of_two() || (morton_encoded && chunk_res > 256) {
+            return Err("chunk resolution must be a power of two, at most 256 for morton chunks.".to_string());
+        }
+
+        let size = chunk_voxel_count(chunk_res)?;
+        if chunks.iter().any(|(_, chunk)| chunk.len() != size) {
+            return Err("chunk is not the given size.".to_string());
+        }
+
+        let min = chunks.iter().fold(IVec3::MAX, |m, (coord, _)| m.min(*coord));
+        let max = chunks.iter().fold(IVec3::MIN, |m, (coord, _)| m.max(*coord));
+
+        // in 64 bit, chunks at opposite ends of the i32 range are more than u32::MAX apart
+        let span = if chunks.is_empty() { 0 } else { (max.as_i64vec3() - min.as_i64vec3()).max_element() as u64 };
+        let voxels = (span + 1).next_power_of_two().checked_mul(chunk_res as u64);
+        let depth = match voxels {
+            Some(voxels) if voxels <= 1 << 24 => voxels.trailing_zeros(),
+            _ => return Err("chunks span more than 2^24 voxels.".to_string()),
+        };
+
+        let mut svo = SVO::new(depth as u8);
+        for (coord, chunk) in chunks {
+            let chunk_origin = (*coord - min).as_uvec3() * chunk_res;
+
+            for (i, &mat) in chunk.iter().enumerate() {
+                if mat == 0 {
+                    continue;
+                }
+
+                let local = if morton_encoded {
+                    let (x, y, z) = morton_decode_3d(i as u32);
+                    UVec3::new(x as u32, y as u32, z as u32)
+                } else {
+                    index_to_pos(i as u32, chunk_res)
+                };
+                svo.insert_voxel(chunk_origin + local, mat as u32)?;
+            }
+        }
+
+        let origin = if chunks.is_empty() { I64Vec3::ZERO } else { min.as_i64vec3() * chunk_res as i64 };
+        if origin.cmplt(I64Vec3::splat(i32::MIN as i64)).any() || origin.cmpgt(I64Vec3::splat(i32::MAX as i64)).any() {
+            return Err("chunk origin is out of the voxel coordinate range.".to_string());
+        }
+        Ok((svo, origin.as_ivec3()))
+    }
+
+    // splits the leaves into chunks of chunk_res voxels, origin is the voxel position of the svo as returned by from_chunks.
+    // only chunks holding voxels are returned, sorted by z, y, x
+    pub fn to_chunks(&self, chunk_res: u32, origin: IVec3, morton_encoded: bool) -> Result<Vec<(IVec3, Vec<u8>)>, String> {
+        if !chunk_res.is_power_of_two() || (morton_encoded && chunk_res > 256) {
+            return Err("chunk resolution must be a power of two, at most 256 for morton chunks.".to_string());
+        }
+
+        let size = chunk_voxel_count(chunk_res)?;
+        let res = i32::try_from(chunk_res).map_err(|_| "chunk resolution is too large.".to_string())?;
+        let mut chunks: HashMap<IVec3, Vec<u8>> = HashMap::new();
+
+        self.for_each_leaf(|pos, mat| {
+            let world = origin + pos.as_ivec3();
+            let coord = world.div_euclid(IVec3::splat(res));
+            let local = world.rem_euclid(IVec3::splat(res)).as_uvec3();
+
+            let index = if morton_encoded {
+                morton_encode_3d(local.x as u8, local.y as u8, local.z as u8)
+            } else {
+                pos_to_index(local.x, local.y, local.z, chunk_res)
+            };
+
+            chunks.entry(coord).or_insert_with(|| vec![0; size])[index as usize] = mat.min(u8::MAX as u32) as u8;
+        });
+
+        let mut chunks: Vec<(IVec3, Vec<u8>)> = chunks.into_iter().collect();
+        chunks.sort_by_key(|(coord, _)| (coord.z, coord.y, coord.x));
+        Ok(chunks)
+    }
+
     pub fn insert_node_morton(&mut self, morton_index: u32, mat: u32) -> Result<(), String> {
         let mut local_idx = morton_index;
         let mut cs = self.root_span;
@@ -140,6 +251,32 @@ impl SVO {
         Ok(())
     }
 
+    // inserts a leaf at max depth by its voxel position, unlike morton indices this is not limited to 8 bits per axis
+    pub fn insert_voxel(&mut self, pos: UVec3, mat: u32) -> Result<(), String> {
+        if pos.max_element() >= 1 << self.depth {
+            return Err("voxel position out of bounds.".to_string());
+        }
+
+        let mut node_idx = 0;
+
+        for cd in 0..self.depth {
+            let shift = (self.depth - 1 - cd) as u32;
+            let child_idx = ((pos.x >> shift) & 1) | (((pos.y >> shift) & 1) << 1) | (((pos.z >> shift) & 1) << 2);
+
+            if !self.nodes[node_idx].has_children() {
+                self.nodes[node_idx] = self.nodes.len() as u32;
+                for _ in 0..8 { self.nodes.push(0); }
+            }
+
+            self.nodes[node_idx] = self.nodes[node_idx].set_child(child_idx);
+            node_idx = (self.nodes[node_idx].first_child_index() + child_idx) as usize;
+        }
+
+        self.nodes[node_idx] = self.nodes[node_idx].set_first_child_index(mat);
+
+        Ok(())
+    }
+
     pub fn gen_random_svo(&mut self, seed: u64) {
         let mut rng = StdRng::seed_from_u64(seed);
 
@@ -167,18 +304,19 @@ impl SVO {
         }
     }
 
-    #[allow(dead_code)]
+    // pos is in root span units, returns the index of the inserted node
     pub fn insert_node_at_depth(&mut self, pos: Vec3, depth: u8) -> usize {
         let mut cs = self.root_span; // span
         let mut cd = 0; // depth
         let mut node_idx = 0;
+        let mut origin = Vec3::ZERO; // min corner of the current node
 
         while cd < depth {
             cs *= 0.5;
             let mut child_idx = 0;
-            if cs < pos.x { child_idx += 1; }
-            if cs < pos.y { child_idx += 2; }
-            if cs < pos.z { child_idx += 4; }
+            if pos.x >= origin.x + cs { child_idx += 1; origin.x += cs; }
+            if pos.y >= origin.y + cs { child_idx += 2; origin.y += cs; }
+            if pos.z >= origin.z + cs { child_idx += 4; origin.z += cs; }
 
             if !self.nodes[node_idx].has_children() {
                 self.nodes[node_idx] = self.nodes.len() as u32;
@@ -195,15 +333,297 @@ impl SVO {
         node_idx
     }
 
-    // Todo: implement tests
-    #[allow(dead_code)]
     pub fn insert_node(&mut self, pos: Vec3) -> usize {
         self.insert_node_at_depth(pos, self.depth)
     }
 
+    pub fn count_leaf_nodes(&self) -> u32 {
+        self.view().count_leaf_nodes()
+    }
+
+    // returns the material of the leaf at the given voxel position or 0 if empty
+    pub fn get_voxel(&self, pos: UVec3) -> u32 {
+        self.view().get_voxel(pos)
+    }
+
+    // visits every leaf at max depth with its voxel position and material
+    pub fn for_each_leaf<F: FnMut(UVec3, u32)>(&self, f: F) {
+        self.view().for_each_leaf(f)
+    }
+
+    // visits every reachable node depth first, pos is in units of the node size at cur_depth
+    pub fn for_each_node<F: FnMut(UVec3, u8, u32)>(&self, f: F) {
+        self.view().for_each_node(f)
+    }
+
+    // edge length of a leaf voxel in root span units
+    pub fn voxel_size(&self) -> f32 {
+        self.view().voxel_size()
+    }
+
+    pub fn validate(&self) -> SvoReport {
+        self.view().validate()
+    }
+
+    // rebuilds the tree without invalid subtrees and unreachable nodes, returns the report of the issues that were removed
+    pub fn repair(&mut self) -> SvoReport {
+        let report = self.validate();
+        if report.is_valid() && report.unreachable_nodes() == 0 {
+            return report;
+        }
+
+        if self.nodes.is_empty() {
+            self.nodes.push(0);
+            return report;
+        }
+
+        let mut nodes = vec![0];
+        let mut visited = vec![false; self.nodes.len()];
+        visited[0] = true;
+        nodes[0] = self.repair_node(0, 0, &mut nodes, &mut visited);
+        self.nodes = nodes;
+
+        report
+    }
+
+    // returns the repaired node or 0 if the subtree is dropped, children are appended to nodes in blocks of 8
+    fn repair_node(&self, node_idx: usize, cur_depth: u8, nodes: &mut Vec<u32>, visited: &mut [bool]) -> u32 {
+        let node = self.nodes[node_idx];
+
+        if !node.has_children() {
+            return if cur_depth == self.depth { node } else { 0 };
+        }
+
+        let first_child_index = node.first_child_index() as usize;
+        if cur_depth == self.depth || first_child_index + 8 > self.nodes.len() {
+            return 0;
+        }
+
+        let new_first_child_index = nodes.len();
+        nodes.resize(new_first_child_index + 8, 0);
+
+        let mut mask = 0u8;
+        for i in 0..8 {
+            let child_idx = first_child_index + i;
+            if !node.check_child(i as u32) || visited[child_idx] {
+                continue;
+            }
+            visited[child_idx] = true;
+
+            let child = self.repair_node(child_idx, cur_depth + 1, nodes, visited);
+            if child != 0 {
+                nodes[new_first_child_index + i] = child;
+                mask |= 1 << i;
+            }
+        }
+
+        if mask == 0 {
+            nodes.truncate(new_first_child_index);
+            return 0;
+        }
+
+        encode_node(mask, new_first_child_index as u32)
+    }
+
+    pub fn view(&self) -> SvoView<'_> {
+        SvoView {
+            nodes: Cow::Borrowed(&self.nodes),
+            depth: self.depth,
+            root_span: self.root_span,
+        }
+    }
+}
+
+// read only svo over nodes that are either borrowed, e.g. from a mapped file, or decoded into an owned buffer
+#[derive(Clone, Debug)]
+pub struct SvoView<'a> {
+    pub nodes: Cow<'a, [u32]>,
+    pub depth: u8,
+    pub root_span: f32,
+}
+
+impl SvoView<'_> {
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self.nodes, Cow::Borrowed(_))
+    }
+
+    pub fn to_svo(&self) -> SVO {
+        SVO {
+            nodes: self.nodes.to_vec(),
+            depth: self.depth,
+            root_span: self.root_span,
+        }
+    }
+
     pub fn count_leaf_nodes(&self) -> u32 {
         self.nodes.iter().filter(|&n| n.leaf()).count() as u32
     }
+
+    // nodes past the end read as empty, so corrupt or mapped trees can be queried without panicking
+    fn node(&self, node_idx: usize) -> u32 {
+        self.nodes.get(node_idx).copied().unwrap_or(0)
+    }
+
+    pub fn get_voxel(&self, pos: UVec3) -> u32 {
+        let mut node_idx = 0;
+
+        for cd in 0..self.depth {
+            let shift = (self.depth - 1 - cd) as u32;
+            let bit = |v: u32| v.checked_shr(shift).unwrap_or(0) & 1;
+            let child_idx = bit(pos.x) | (bit(pos.y) << 1) | (bit(pos.z) << 2);
+
+            let node = self.node(node_idx);
+            if !node.check_child(child_idx) {
+                return 0;
+            }
+
+            node_idx = node.first_child_index() as usize + child_idx as usize;
+        }
+
+        self.node(node_idx).first_child_index()
+    }
+
+    pub fn for_each_leaf<F: FnMut(UVec3, u32)>(&self, mut f: F) {
+        let depth = self.depth;
+        self.for_each_node(|pos, cur_depth, node| {
+            if cur_depth == depth && node.leaf() { f(pos, node.first_child_index()); }
+        });
+    }
+
+    pub fn for_each_node<F: FnMut(UVec3, u8, u32)>(&self, mut f: F) {
+        self.visit_nodes(0, UVec3::ZERO, 0, &mut f);
+    }
+
+    fn visit_nodes<F: FnMut(UVec3, u8, u32)>(&self, node_idx: usize, pos: UVec3, cur_depth: u8, f: &mut F) {
+        let Some(&node) = self.nodes.get(node_idx) else {
+            return;
+        };
+        f(pos, cur_depth, node);
+
+        if cur_depth == self.depth {
+            return;
+        }
+
+        for i in 0..8 {
+            if node.check_child(i) {
+                let child_pos = pos * 2 + UVec3::new(i & 1, (i >> 1) & 1, (i >> 2) & 1);
+                self.visit_nodes(node.first_child_index() as usize + i as usize, child_pos, cur_depth + 1, f);
+            }
+        }
+    }
+
+    pub fn voxel_size(&self) -> f32 {
+        self.root_span / (1u32 << self.depth) as f32
+    }
+
+    // checks every pointer before following it, so this is safe to run on untrusted nodes
+    pub fn validate(&self) -> SvoReport {
+        let mut report = SvoReport::default();
+
+        if self.nodes.is_empty() {
+            report.issues.push(SvoIssue::MissingRoot);
+            return report;
+        }
+
+        let mut visited = vec![false; self.nodes.len()];
+        // root and every child block of a reachable node, unused slots in a block are padding and not unreachable
+        let mut covered = vec![false; self.nodes.len()];
+        visited[0] = true;
+        covered[0] = true;
+
+        let mut stack = vec![(0usize, 0u8)];
+        while let Some((node_idx, cur_depth)) = stack.pop() {
+            let node = self.nodes[node_idx];
+            report.reachable_nodes += 1;
+
+            if !node.has_children() {
+                if cur_depth == self.depth && node != 0 {
+                    report.leaves += 1;
+                } else if node != 0 {
+                    report.issues.push(SvoIssue::LeafAboveDepth { node: node_idx, depth: cur_depth });
+                }
+                continue;
+            }
+
+            if cur_depth == self.depth {
+                report.issues.push(SvoIssue::ChildrenAtMaxDepth { node: node_idx });
+                continue;
+            }
+
+            let first_child_index = node.first_child_index() as usize;
+            if first_child_index + 8 > self.nodes.len() {
+                report.issues.push(SvoIssue::PointerOutOfRange { node: node_idx, first_child_index });
+                continue;
+            }
+            covered[first_child_index..first_child_index + 8].iter_mut().for_each(|c| *c = true);
+
+            for i in 0..8 {
+                let child_idx = first_child_index + i as usize;
+                if !node.check_child(i) {
+                    continue;
+                }
+
+                if visited[child_idx] {
+                    report.issues.push(SvoIssue::SharedChild { node: node_idx, child: child_idx });
+                    continue;
+                }
+                visited[child_idx] = true;
+
+                if self.nodes[child_idx] == 0 {
+                    report.issues.push(SvoIssue::EmptyChild { node: node_idx, child: child_idx });
+                }
+                stack.push((child_idx, cur_depth + 1));
+            }
+        }
+
+        // consecutive uncovered nodes are reported as one range
+        let mut start = None;
+        for (i, &c) in covered.iter().chain([true].iter()).enumerate() {
+            match (c, start) {
+                (false, None) => start = Some(i),
+                (true, Some(s)) => {
+                    report.issues.push(SvoIssue::Unreachable { start: s, len: i - s });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        report
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SvoIssue {
+    MissingRoot,
+    // first_child_index + 7 is past the end of the nodes
+    PointerOutOfRange { node: usize, first_child_index: usize },
+    // a leaf or node without children above max depth
+    LeafAboveDepth { node: usize, depth: u8 },
+    ChildrenAtMaxDepth { node: usize },
+    // the child is already reachable through another node, either a cycle or a shared subtree
+    SharedChild { node: usize, child: usize },
+    // the child mask bit is set but the child node is 0
+    EmptyChild { node: usize, child: usize },
+    Unreachable { start: usize, len: usize },
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SvoReport {
+    pub issues: Vec<SvoIssue>,
+    pub reachable_nodes: usize,
+    pub leaves: usize,
+}
+
+impl SvoReport {
+    // unreachable nodes waste space but can't be followed, everything else can break traversal
+    pub fn is_valid(&self) -> bool {
+        self.issues.iter().all(|issue| matches!(issue, SvoIssue::Unreachable { .. }))
+    }
+
+    pub fn unreachable_nodes(&self) -> usize {
+        self.issues.iter().map(|issue| if let SvoIssue::Unreachable { len, .. } = issue { *len } else { 0 }).sum()
+    }
 }
 
 impl Default for SVO {
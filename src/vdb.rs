@@ -0,0 +1,656 @@
+use crate::atomic::write_atomic;
+use crate::deflate::inflate;
+use crate::svo::SVO;
+use crate::vox::{pos_to_index, DEFAULT_VOX_MAT};
+use crate::voxelize::GridTransform;
+use glam::{IVec3, Vec3};
+use rand::Rng;
+use std::{collections::{BTreeMap, HashMap}, fs::File, io, io::{BufReader, Read}, path::Path};
+
+pub const VDB_MAGIC: i64 = 0x56444220;
+// multipass io, the current openvdb file version
+pub const VDB_FILE_VERSION: u32 = 224;
+const VDB_LIBRARY_VERSION: (u32, u32) = (10, 0);
+// oldest version with per grid compression and node mask compression
+const VDB_MIN_FILE_VERSION: u32 = 222;
+
+const COMPRESS_ZIP: u32 = 1;
+const COMPRESS_ACTIVE_MASK: u32 = 2;
+const COMPRESS_BLOSC: u32 = 4;
+
+// per value array metadata of the active mask compression
+const NO_MASK_OR_INACTIVE_VALS: u8 = 0;
+const NO_MASK_AND_ONE_INACTIVE_VAL: u8 = 2;
+const MASK_AND_NO_INACTIVE_VALS: u8 = 3;
+const MASK_AND_ONE_INACTIVE_VAL: u8 = 4;
+const MASK_AND_TWO_INACTIVE_VALS: u8 = 5;
+const NO_MASK_AND_ALL_VALS: u8 = 6;
+
+// log2 dimensions of the standard 5_4_3 tree, leaf first
+const LEAF_LOG2: u32 = 3;
+const LOWER_LOG2: u32 = 4;
+const UPPER_LOG2: u32 = 5;
+
+// active tiles are expanded into single voxels, a few bytes of tile masks could otherwise ask for billions of them
+pub const MAX_VDB_VOXELS: usize = 1 << 24;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VdbValueType {
+    Float,
+    // occupancy only, values are 0.0 or 1.0
+    Bool,
+}
+
+impl VdbValueType {
+    fn type_name(&self) -> &'static str {
+        match self {
+            VdbValueType::Float => "Tree_float_5_4_3",
+            VdbValueType::Bool => "Tree_bool_5_4_3",
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            VdbValueType::Float => 4,
+            VdbValueType::Bool => 1,
+        }
+    }
+
+    fn write_value(&self, out: &mut Vec<u8>, value: f32) {
+        match self {
+            VdbValueType::Float => out.extend_from_slice(&value.to_le_bytes()),
+            VdbValueType::Bool => out.push((value != 0.0) as u8),
+        }
+    }
+
+    fn read_value(&self, bytes: &[u8]) -> f32 {
+        match self {
+            VdbValueType::Float => f32::from_le_bytes(bytes[..4].try_into().unwrap()),
+            VdbValueType::Bool => (bytes[0] != 0) as u8 as f32,
+        }
+    }
+}
+
+// a sparse grid of active voxels in index space, the transform maps index space to world space
+#[derive(Clone, Debug, PartialEq)]
+pub struct VdbGrid {
+    pub name: String,
+    pub value_type: VdbValueType,
+    pub transform: GridTransform,
+    pub background: f32,
+    pub voxels: HashMap<IVec3, f32>,
+}
+
+impl VdbGrid {
+    pub fn new(name: &str, value_type: VdbValueType, transform: GridTransform) -> Self {
+        Self { name: name.to_string(), value_type, transform, background: 0.0, voxels: HashMap::new() }
+    }
+
+    // float grids store the voxel materials as values
+    pub fn from_chunk(name: &str, chunk: &[u8], chunk_res: u32, offset: IVec3, value_type: VdbValueType) -> Self {
+        let mut grid = Self::new(name, value_type, GridTransform::default());
+
+        for z in 0..chunk_res {
+            for y in 0..chunk_res {
+                for x in 0..chunk_res {
+                    let mat = chunk[pos_to_index(x, y, z, chunk_res) as usize];
+                    if mat > 0 {
+                        grid.set(offset + IVec3::new(x as i32, y as i32, z as i32), mat as f32);
+                    }
+                }
+            }
+        }
+
+        grid
+    }
+
+    pub fn from_svo(name: &str, svo: &SVO, value_type: VdbValueType) -> Self {
+        let mut grid = Self::new(name, value_type, GridTransform::new(Vec3::ZERO, svo.voxel_size()));
+        svo.for_each_leaf(|pos, mat| grid.set(pos.as_ivec3(), mat as f32));
+        grid
+    }
+
+    pub fn set(&mut self, pos: IVec3, value: f32) {
+        let value = match self.value_type {
+            VdbValueType::Float => value,
+            VdbValueType::Bool => (value != 0.0) as u8 as f32,
+        };
+        self.voxels.insert(pos, value);
+    }
+
+    // linear chunk of the voxels inside offset..offset + chunk_res, values are clamped to materials
+    pub fn to_chunk(&self, chunk_res: u32, offset: IVec3) -> Vec<u8> {
+        let mut chunk = vec![0u8; (chunk_res * chunk_res * chunk_res) as usize];
+
+        for (&pos, &value) in &self.voxels {
+            let local = pos - offset;
+            if local.cmplt(IVec3::ZERO).any() || local.cmpge(IVec3::splat(chunk_res as i32)).any() || value <= 0.0 {
+                continue;
+            }
+
+            let mat = match self.value_type {
+                VdbValueType::Float => value.round().clamp(1.0, 255.0) as u8,
+                VdbValueType::Bool => DEFAULT_VOX_MAT,
+            };
+            let local = local.as_uvec3();
+            chunk[pos_to_index(local.x, local.y, local.z, chunk_res) as usize] = mat;
+        }
+
+        chunk
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+type Coord = (i32, i32, i32);
+
+// origin of the node with the given total log2 dimension containing pos
+fn node_origin(pos: IVec3, total_log2: u32) -> Coord {
+    let mask = !((1i32 << total_log2) - 1);
+    (pos.x & mask, pos.y & mask, pos.z & mask)
+}
+
+// linear offset inside a node, x major and z fastest like openvdb
+fn node_offset(pos: IVec3, log2: u32, child_log2: u32) -> usize {
+    let dim_mask = (1i32 << (log2 + child_log2)) - 1;
+    let local = (pos & IVec3::splat(dim_mask)) >> IVec3::splat(child_log2 as i32);
+    ((local.x << (2 * log2)) + (local.y << log2) + local.z) as usize
+}
+
+fn mask_words(log2: u32) -> usize {
+    (1usize << (3 * log2)) / 64
+}
+
+fn is_on(mask: &[u64], i: usize) -> bool {
+    mask[i >> 6] & (1 << (i & 63)) != 0
+}
+
+fn set_on(mask: &mut [u64], i: usize) {
+    mask[i >> 6] |= 1 << (i & 63);
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn write_mask(out: &mut Vec<u8>, mask: &[u64]) {
+    mask.iter().for_each(|w| out.extend_from_slice(&w.to_le_bytes()));
+}
+
+fn write_coord(out: &mut Vec<u8>, (x, y, z): Coord) {
+    [x, y, z].iter().for_each(|v| out.extend_from_slice(&v.to_le_bytes()));
+}
+
+// all inactive values are the background so only the active values are written
+fn write_active_values(out: &mut Vec<u8>, value_type: VdbValueType, values: &[f32], value_mask: &[u64]) {
+    out.push(NO_MASK_OR_INACTIVE_VALS);
+    for (i, &value) in values.iter().enumerate() {
+        if is_on(value_mask, i) {
+            value_type.write_value(out, value);
+        }
+    }
+}
+
+// internal node without tiles, only the child mask is set
+fn write_internal_topology(out: &mut Vec<u8>, value_type: VdbValueType, log2: u32, children: impl Iterator<Item = usize>) {
+    let mut child_mask = vec![0u64; mask_words(log2)];
+    children.for_each(|i| set_on(&mut child_mask, i));
+
+    write_mask(out, &child_mask);
+    write_mask(out, &vec![0u64; mask_words(log2)]);
+    write_active_values(out, value_type, &[], &[]);
+}
+
+type Leaf = ([u64; 8], [f32; 512]);
+// lower internal nodes by origin, each with its leaves by origin
+type UpperNode = BTreeMap<Coord, BTreeMap<Coord, Leaf>>;
+
+fn encode_grid(out: &mut Vec<u8>, grid: &VdbGrid) -> io::Result<()> {
+    let voxel_size = grid.transform.voxel_size as f64;
+    if voxel_size <= 0.0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "vdb voxel size has to be positive."));
+    }
+
+    // root children, lower internal nodes and leaves sorted like the openvdb tree iterators
+    let mut tree: BTreeMap<Coord, UpperNode> = BTreeMap::new();
+    for (&pos, &value) in &grid.voxels {
+        let upper = tree.entry(node_origin(pos, UPPER_LOG2 + LOWER_LOG2 + LEAF_LOG2)).or_default();
+        let lower = upper.entry(node_origin(pos, LOWER_LOG2 + LEAF_LOG2)).or_default();
+        let leaf = lower.entry(node_origin(pos, LEAF_LOG2)).or_insert(([0; 8], [grid.background; 512]));
+
+        let i = node_offset(pos, LEAF_LOG2, 0);
+        set_on(&mut leaf.0, i);
+        leaf.1[i] = value;
+    }
+
+    let offset = |origin: &Coord, log2, child_log2| node_offset(IVec3::new(origin.0, origin.1, origin.2), log2, child_log2);
+
+    // grid descriptor, the stream positions are patched once known
+    write_string(out, &grid.name);
+    write_string(out, grid.value_type.type_name());
+    write_string(out, "");
+    let pos_offset = out.len();
+    out.extend_from_slice(&[0; 24]);
+    let grid_pos = out.len();
+
+    out.extend_from_slice(&COMPRESS_ACTIVE_MASK.to_le_bytes());
+
+    // grid metadata
+    out.extend_from_slice(&1u32.to_le_bytes());
+    write_string(out, "name");
+    write_string(out, "string");
+    write_string(out, &grid.name);
+
+    // index space voxel centers sit on integer coordinates
+    let translation = grid.transform.origin.as_dvec3() + 0.5 * voxel_size;
+    write_string(out, "UniformScaleTranslateMap");
+    for v in [translation.to_array(), [voxel_size; 3], [voxel_size; 3], [1.0 / voxel_size; 3], [1.0 / (voxel_size * voxel_size); 3], [0.5 / voxel_size; 3]] {
+        v.iter().for_each(|d| out.extend_from_slice(&d.to_le_bytes()));
+    }
+
+    // topology, one buffer per leaf
+    out.extend_from_slice(&1i32.to_le_bytes());
+    grid.value_type.write_value(out, grid.background);
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&(tree.len() as u32).to_le_bytes());
+
+    for (upper_origin, upper) in &tree {
+        write_coord(out, *upper_origin);
+        write_internal_topology(out, grid.value_type, UPPER_LOG2, upper.keys().map(|o| offset(o, UPPER_LOG2, LOWER_LOG2 + LEAF_LOG2)));
+
+        for lower in upper.values() {
+            write_internal_topology(out, grid.value_type, LOWER_LOG2, lower.keys().map(|o| offset(o, LOWER_LOG2, LEAF_LOG2)));
+
+            for (mask, _) in lower.values() {
+                write_mask(out, mask);
+            }
+        }
+    }
+
+    let block_pos = out.len();
+
+    for lower in tree.values().flat_map(|upper| upper.values()) {
+        for (origin, (mask, values)) in lower {
+            write_mask(out, mask);
+            match grid.value_type {
+                VdbValueType::Float => write_active_values(out, grid.value_type, values, mask),
+                VdbValueType::Bool => {
+                    write_coord(out, *origin);
+                    let mut bits = [0u64; 8];
+                    values.iter().enumerate().filter(|(_, &v)| v != 0.0).for_each(|(i, _)| set_on(&mut bits, i));
+                    write_mask(out, &bits);
+                }
+            }
+        }
+    }
+
+    let end_pos = out.len();
+    for (i, pos) in [grid_pos, block_pos, end_pos].iter().enumerate() {
+        out[pos_offset + i * 8..pos_offset + i * 8 + 8].copy_from_slice(&(*pos as i64).to_le_bytes());
+    }
+
+    Ok(())
+}
+
+// openvdb file with one tree per grid, values are stored uncompressed apart from the active mask compression
+pub fn encode_vdb(grids: &[VdbGrid]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&VDB_MAGIC.to_le_bytes());
+    for v in [VDB_FILE_VERSION, VDB_LIBRARY_VERSION.0, VDB_LIBRARY_VERSION.1] {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    // has grid offsets
+    out.push(1);
+
+    let uuid: [u8; 16] = rand::thread_rng().gen();
+    let hex: String = uuid.iter().map(|b| format!("{:02x}", b)).collect();
+    out.extend_from_slice(format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32]).as_bytes());
+
+    // file metadata
+    out.extend_from_slice(&0u32.to_le_bytes());
+
+    out.extend_from_slice(&(grids.len() as i32).to_le_bytes());
+    for grid in grids {
+        encode_grid(&mut out, grid)?;
+    }
+
+    Ok(out)
+}
+
+pub fn write_vdb(filename: &str, grids: &[VdbGrid]) -> io::Result<()> {
+    let data = encode_vdb(grids)?;
+
+    write_atomic(filename, &data, false)
+}
+
+struct VdbReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    compression: u32,
+}
+
+impl<'a> VdbReader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.data.len());
+        let end = end.ok_or_else(|| invalid_data("unexpected end of vdb data."))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> io::Result<i64> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    fn f64(&mut self) -> io::Result<f64> {
+        Ok(f64::from_le_bytes(self.array()?))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn coord(&mut self) -> io::Result<IVec3> {
+        let mut v = [0i32; 3];
+        for c in v.iter_mut() {
+            *c = i32::from_le_bytes(self.array()?);
+        }
+        Ok(IVec3::from_array(v))
+    }
+
+    fn mask(&mut self, log2: u32) -> io::Result<Vec<u64>> {
+        (0..mask_words(log2)).map(|_| Ok(u64::from_le_bytes(self.array()?))).collect()
+    }
+
+    // name to string value, other metadata types are skipped
+    fn metadata(&mut self) -> io::Result<HashMap<String, String>> {
+        let mut meta = HashMap::new();
+
+        for _ in 0..self.u32()? {
+            let name = self.string()?;
+            let type_name = self.string()?;
+            let len = self.u32()? as usize;
+            let value = self.take(len)?;
+            if type_name == "string" {
+                meta.insert(name, String::from_utf8_lossy(value).into_owned());
+            }
+        }
+
+        Ok(meta)
+    }
+
+    // raw or zlib compressed value bytes
+    fn data(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        if self.compression & COMPRESS_BLOSC != 0 {
+            return Err(invalid_data("blosc compressed vdb files are not supported."));
+        }
+
+        if self.compression & COMPRESS_ZIP == 0 {
+            return Ok(self.take(len)?.to_vec());
+        }
+
+        // negative sizes mark data that didn't compress
+        let zipped = self.i64()?;
+        let data = if zipped <= 0 {
+            self.take(zipped.unsigned_abs() as usize)?.to_vec()
+        } else {
+            // skip the two byte zlib header, the adler checksum is not verified
+            let stream = self.take(zipped as usize)?;
+            inflate(stream.get(2..).ok_or_else(|| invalid_data("invalid zlib stream in vdb data."))?, len)?
+        };
+
+        if data.len() != len {
+            return Err(invalid_data("vdb value data does not match the node size."));
+        }
+        Ok(data)
+    }
+
+    fn values(&mut self, value_type: VdbValueType, background: f32, count: usize, value_mask: &[u64], log2: u32) -> io::Result<Vec<f32>> {
+        let size = value_type.size();
+        let metadata = self.array::<1>()?[0];
+
+        let mut inactive = [-background, background];
+        if metadata == NO_MASK_OR_INACTIVE_VALS {
+            inactive[0] = background;
+        }
+        if matches!(metadata, NO_MASK_AND_ONE_INACTIVE_VAL | MASK_AND_ONE_INACTIVE_VAL | MASK_AND_TWO_INACTIVE_VALS) {
+            inactive[0] = value_type.read_value(self.take(size)?);
+            if metadata == MASK_AND_TWO_INACTIVE_VALS {
+                inactive[1] = value_type.read_value(self.take(size)?);
+            }
+        }
+        if metadata > NO_MASK_AND_ALL_VALS {
+            return Err(invalid_data("invalid value metadata in vdb data."));
+        }
+
+        let selection = if matches!(metadata, MASK_AND_NO_INACTIVE_VALS | MASK_AND_ONE_INACTIVE_VAL | MASK_AND_TWO_INACTIVE_VALS) {
+            self.mask(log2)?
+        } else {
+            vec![0; mask_words(log2)]
+        };
+
+        let mask_compressed = self.compression & COMPRESS_ACTIVE_MASK != 0 && metadata != NO_MASK_AND_ALL_VALS;
+        let active_count = if mask_compressed { (0..count).filter(|&i| is_on(value_mask, i)).count() } else { count };
+
+        let data = self.data(active_count * size)?;
+        let mut read = data.chunks_exact(size).map(|b| value_type.read_value(b));
+
+        Ok((0..count)
+            .map(|i| {
+                if !mask_compressed || is_on(value_mask, i) {
+                    read.next().unwrap()
+                } else {
+                    inactive[is_on(&selection, i) as usize]
+                }
+            })
+            .collect())
+    }
+}
+
+// the node has to fit the i32 coordinate range so the child and voxel origins inside it can't overflow
+fn check_node_origin(origin: IVec3, total_log2: u32) -> io::Result<()> {
+    if origin.max_element() > i32::MAX - ((1 << total_log2) - 1) {
+        return Err(invalid_data("vdb node origin is out of the coordinate range."));
+    }
+    Ok(())
+}
+
+fn fill_tile(grid: &mut VdbGrid, origin: IVec3, total_log2: u32, value: f32) {
+    let dim = 1 << total_log2;
+    for x in 0..dim {
+        for y in 0..dim {
+            for z in 0..dim {
+                grid.voxels.insert(origin + IVec3::new(x, y, z), value);
+            }
+        }
+    }
+}
+
+fn node_child_origin(origin: IVec3, offset: usize, log2: u32, child_log2: u32) -> IVec3 {
+    let dim_mask = (1 << log2) - 1;
+    let local = IVec3::new((offset >> (2 * log2)) as i32, ((offset >> log2) & dim_mask) as i32, (offset & dim_mask) as i32);
+    origin + (local << IVec3::splat(child_log2 as i32))
+}
+
+// reads the internal node topology, active tiles are expanded and the leaf origins are collected in file order
+fn read_internal(reader: &mut VdbReader, grid: &mut VdbGrid, origin: IVec3, log2: u32, leaves: &mut Vec<IVec3>) -> io::Result<()> {
+    let child_log2 = if log2 == UPPER_LOG2 { LOWER_LOG2 + LEAF_LOG2 } else { LEAF_LOG2 };
+    let count = 1usize << (3 * log2);
+    check_node_origin(origin, log2 + child_log2)?;
+
+    let child_mask = reader.mask(log2)?;
+    let value_mask = reader.mask(log2)?;
+    let values = reader.values(grid.value_type, grid.background, count, &value_mask, log2)?;
+
+    let tiles = (0..count).filter(|&i| !is_on(&child_mask, i) && is_on(&value_mask, i)).count();
+    if grid.voxels.len() + tiles * (1 << (3 * child_log2)) > MAX_VDB_VOXELS {
+        return Err(invalid_data("vdb tiles expand to more voxels than supported."));
+    }
+
+    for (i, &value) in values.iter().enumerate() {
+        if !is_on(&child_mask, i) && is_on(&value_mask, i) {
+            fill_tile(grid, node_child_origin(origin, i, log2, child_log2), child_log2, value);
+        }
+    }
+
+    for i in (0..count).filter(|&i| is_on(&child_mask, i)) {
+        let child_origin = node_child_origin(origin, i, log2, child_log2);
+        if log2 == UPPER_LOG2 {
+            read_internal(reader, grid, child_origin, LOWER_LOG2, leaves)?;
+        } else {
+            // the leaf topology is its value mask, which is repeated with the buffers
+            reader.mask(LEAF_LOG2)?;
+            leaves.push(child_origin);
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_grid(reader: &mut VdbReader, has_offsets: bool) -> io::Result<VdbGrid> {
+    let name = reader.string()?;
+    let type_name = reader.string()?;
+    let parent = reader.string()?;
+
+    let value_type = match type_name.as_str() {
+        "Tree_float_5_4_3" => VdbValueType::Float,
+        "Tree_bool_5_4_3" => VdbValueType::Bool,
+        _ => return Err(invalid_data("unsupported vdb grid type.")),
+    };
+    if !parent.is_empty() {
+        return Err(invalid_data("instanced vdb grids are not supported."));
+    }
+
+    let end_pos = if has_offsets {
+        reader.i64()?;
+        reader.i64()?;
+        let end_pos = usize::try_from(reader.i64()?).ok().filter(|&end| end <= reader.data.len());
+        Some(end_pos.ok_or_else(|| invalid_data("vdb grid end offset is out of range."))?)
+    } else {
+        None
+    };
+
+    reader.compression = reader.u32()?;
+    let meta = reader.metadata()?;
+
+    let map = reader.string()?;
+    let mut doubles = |count: usize| (0..count).map(|_| reader.f64()).collect::<io::Result<Vec<f64>>>();
+    let (translation, scale) = match map.as_str() {
+        "UniformScaleTranslateMap" | "ScaleTranslateMap" => {
+            let v = doubles(18)?;
+            ([v[0], v[1], v[2]], [v[3], v[4], v[5]])
+        }
+        "UniformScaleMap" | "ScaleMap" => {
+            let v = doubles(15)?;
+            ([0.0; 3], [v[0], v[1], v[2]])
+        }
+        "TranslationMap" => {
+            let v = doubles(3)?;
+            ([v[0], v[1], v[2]], [1.0; 3])
+        }
+        _ => return Err(invalid_data("unsupported vdb transform.")),
+    };
+    if scale[0] != scale[1] || scale[0] != scale[2] {
+        return Err(invalid_data("non uniform vdb transforms are not supported."));
+    }
+
+    let voxel_size = scale[0] as f32;
+    let origin = Vec3::from_array(translation.map(|v| v as f32)) - Vec3::splat(0.5 * voxel_size);
+    let mut grid = VdbGrid::new(meta.get("name").unwrap_or(&name), value_type, GridTransform::new(origin, voxel_size));
+
+    if reader.u32()? != 1 {
+        return Err(invalid_data("vdb grids with multiple buffers are not supported."));
+    }
+
+    grid.background = value_type.read_value(reader.take(value_type.size())?);
+    let tile_count = reader.u32()?;
+    let child_count = reader.u32()?;
+
+    for _ in 0..tile_count {
+        reader.coord()?;
+        let value = value_type.read_value(reader.take(value_type.size())?);
+        if reader.array::<1>()?[0] != 0 && value != grid.background {
+            return Err(invalid_data("active root tiles are not supported."));
+        }
+    }
+
+    let mut leaves = Vec::new();
+    for _ in 0..child_count {
+        let origin = reader.coord()?;
+        read_internal(reader, &mut grid, origin, UPPER_LOG2, &mut leaves)?;
+    }
+
+    for origin in leaves {
+        let value_mask = reader.mask(LEAF_LOG2)?;
+
+        let values = match value_type {
+            VdbValueType::Float => reader.values(value_type, grid.background, 512, &value_mask, LEAF_LOG2)?,
+            VdbValueType::Bool => {
+                // bool leaves repeat their origin before the value bits
+                reader.coord()?;
+                let bits = reader.mask(LEAF_LOG2)?;
+                (0..512).map(|i| is_on(&bits, i) as u8 as f32).collect()
+            }
+        };
+
+        for (i, value) in values.into_iter().enumerate() {
+            if is_on(&value_mask, i) {
+                grid.voxels.insert(node_child_origin(origin, i, LEAF_LOG2, 0), value);
+            }
+        }
+    }
+
+    if let Some(end_pos) = end_pos {
+        if end_pos < reader.pos {
+            return Err(invalid_data("vdb grid end offset is inside the grid."));
+        }
+        reader.pos = end_pos;
+    }
+
+    Ok(grid)
+}
+
+// reads the float and bool grids of a vdb file as their active voxels
+pub fn parse_vdb(data: &[u8]) -> io::Result<Vec<VdbGrid>> {
+    let mut reader = VdbReader { data, pos: 0, compression: 0 };
+
+    if reader.i64()? != VDB_MAGIC {
+        return Err(invalid_data("data is not a vdb file."));
+    }
+
+    let version = reader.u32()?;
+    if version < VDB_MIN_FILE_VERSION {
+        return Err(invalid_data("unsupported vdb file version."));
+    }
+
+    // library version, grid offsets flag and uuid
+    reader.take(8)?;
+    let has_offsets = reader.array::<1>()?[0] != 0;
+    reader.take(36)?;
+    reader.metadata()?;
+
+    let grid_count = i32::from_le_bytes(reader.array()?);
+    (0..grid_count).map(|_| parse_grid(&mut reader, has_offsets)).collect()
+}
+
+pub fn read_vdb(filename: &str) -> io::Result<Vec<VdbGrid>> {
+    let path = Path::new(filename);
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+
+    parse_vdb(&buffer)
+}
@@ -0,0 +1,12 @@
+use crate::bsvo::verify_bsvo;
+use crate::bvox::verify_bvox;
+use std::{io, path::Path};
+
+// checks a bvox or bsvo file by its extension, stored checksums are compared but nothing is decoded
+pub fn verify_file(filename: &str) -> io::Result<()> {
+    match Path::new(filename).extension().and_then(|ext| ext.to_str()) {
+        Some("bvox") => verify_bvox(filename).map(|_| ()),
+        Some("bsvo") => verify_bsvo(filename).map(|_| ()),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "unknown file extension, expected bvox or bsvo.")),
+    }
+}
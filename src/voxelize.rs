@@ -0,0 +1,427 @@
+use crate::svo::SVO;
+use crate::vox::{index_to_pos, pos_to_index, DEFAULT_VOX_MAT};
+use glam::{UVec3, Vec2, Vec3};
+use std::collections::{HashMap, VecDeque};
+
+// offsets the parity rays from the voxel centers so they don't run exactly through mesh edges
+const RAY_JITTER: Vec2 = Vec2::new(1.234e-4, 2.345e-4);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FillMode {
+    // counts ray crossings along z through every voxel column
+    Parity,
+    // flood fills the empty space from the grid border, everything not reached is interior
+    FloodFill,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VoxelizeMode {
+    // every voxel the triangle touches, triangle box overlap test
+    Conservative,
+    // voxels whose center projects into the triangle along its dominant axis, gives a thin surface
+    Center,
+}
+
+// maps mesh space into grid space, voxel (x, y, z) covers origin + [x, x + 1) * voxel_size
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GridTransform {
+    pub origin: Vec3,
+    pub voxel_size: f32,
+}
+
+impl GridTransform {
+    pub fn new(origin: Vec3, voxel_size: f32) -> Self {
+        Self { origin, voxel_size }
+    }
+
+    // fits the bounding box of the triangles into a grid of the given resolution, keeping the aspect ratio
+    pub fn fit(triangles: &[[Vec3; 3]], grid_res: u32) -> Self {
+        let (min, max) = triangles_bounds(triangles);
+        Self::fit_bounds(min, max, grid_res)
+    }
+
+    pub fn fit_bounds(min: Vec3, max: Vec3, grid_res: u32) -> Self {
+        let extent = (max - min).max_element();
+
+        Self {
+            origin: min,
+            voxel_size: if extent > 0.0 { extent / grid_res as f32 } else { 1.0 },
+        }
+    }
+
+    pub fn to_grid(&self, pos: Vec3) -> Vec3 {
+        (pos - self.origin) / self.voxel_size
+    }
+
+    pub fn to_world(&self, pos: Vec3) -> Vec3 {
+        pos * self.voxel_size + self.origin
+    }
+}
+
+impl Default for GridTransform {
+    fn default() -> Self {
+        Self::new(Vec3::ZERO, 1.0)
+    }
+}
+
+pub fn triangles_bounds(triangles: &[[Vec3; 3]]) -> (Vec3, Vec3) {
+    triangles.iter().flatten().fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), &p| (min.min(p), max.max(p)))
+}
+
+// separating axis test of a triangle against an axis aligned box (akenine-moeller)
+pub fn triangle_box_overlap(box_center: Vec3, box_half: Vec3, tri: &[Vec3; 3]) -> bool {
+    let v = tri.map(|p| p - box_center);
+    let edges = [v[1] - v[0], v[2] - v[1], v[0] - v[2]];
+
+    let separated = |axis: Vec3| {
+        let p = v.map(|p| p.dot(axis));
+        let r = box_half.dot(axis.abs());
+        p[0].min(p[1]).min(p[2]) > r || p[0].max(p[1]).max(p[2]) < -r
+    };
+
+    // cross products of the triangle edges with the box normals
+    for edge in edges {
+        for box_normal in [Vec3::X, Vec3::Y, Vec3::Z] {
+            let axis = box_normal.cross(edge);
+            if axis.length_squared() > f32::EPSILON && separated(axis) {
+                return false;
+            }
+        }
+    }
+
+    // box normals, equal to comparing the bounds
+    if [Vec3::X, Vec3::Y, Vec3::Z].into_iter().any(separated) {
+        return false;
+    }
+
+    // triangle normal
+    let normal = edges[0].cross(edges[1]);
+    normal.length_squared() <= f32::EPSILON || !separated(normal)
+}
+
+// calls f with every voxel of the grid that the triangle (in grid space) covers
+pub fn rasterize_triangle<F: FnMut(UVec3)>(tri: &[Vec3; 3], grid_res: u32, mode: VoxelizeMode, mut f: F) {
+    if grid_res == 0 {
+        return;
+    }
+
+    let limit = Vec3::splat(grid_res as f32 - 1.0);
+    let min = tri[0].min(tri[1]).min(tri[2]).floor().clamp(Vec3::ZERO, limit).as_uvec3();
+    let max = tri[0].max(tri[1]).max(tri[2]).floor().clamp(Vec3::ZERO, limit).as_uvec3();
+
+    let normal = (tri[1] - tri[0]).cross(tri[2] - tri[0]);
+    let abs_normal = normal.abs();
+    let dominant = if abs_normal.x >= abs_normal.y && abs_normal.x >= abs_normal.z {
+        0
+    } else if abs_normal.y >= abs_normal.z {
+        1
+    } else {
+        2
+    };
+    let (u, v) = ((dominant + 1) % 3, (dominant + 2) % 3);
+
+    for z in min.z..=max.z {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let voxel = UVec3::new(x, y, z);
+                let center = voxel.as_vec3() + Vec3::splat(0.5);
+
+                let covered = match mode {
+                    VoxelizeMode::Conservative => triangle_box_overlap(center, Vec3::splat(0.5), tri),
+                    VoxelizeMode::Center => {
+                        if normal[dominant] == 0.0 {
+                            false
+                        } else {
+                            // project the center onto the plane along the dominant axis
+                            let edge = |a: Vec3, b: Vec3| (b[u] - a[u]) * (center[v] - a[v]) - (b[v] - a[v]) * (center[u] - a[u]);
+                            let w = [edge(tri[0], tri[1]), edge(tri[1], tri[2]), edge(tri[2], tri[0])];
+                            let inside = w.iter().all(|&w| w >= 0.0) || w.iter().all(|&w| w <= 0.0);
+
+                            let depth = tri[0][dominant]
+                                - (normal[u] * (center[u] - tri[0][u]) + normal[v] * (center[v] - tri[0][v])) / normal[dominant];
+
+                            inside && depth >= voxel[dominant] as f32 && depth < voxel[dominant] as f32 + 1.0
+                        }
+                    }
+                };
+
+                if covered { f(voxel); }
+            }
+        }
+    }
+}
+
+// voxels of a dense grid, the resolution has to have voxels and a size that fits a usize
+fn grid_size(grid_res: u32) -> Result<usize, String> {
+    if grid_res == 0 {
+        return Err("grid resolution has to be at least 1.".to_string());
+    }
+    (grid_res as usize).checked_pow(3).ok_or_else(|| "grid resolution is too large.".to_string())
+}
+
+// materials are per triangle, checked once so triangle_mat can index them directly
+fn check_materials(triangles: &[[Vec3; 3]], materials: Option<&[u8]>) -> Result<(), String> {
+    match materials {
+        Some(m) if m.len() != triangles.len() => Err(format!("{} materials given for {} triangles.", m.len(), triangles.len())),
+        _ => Ok(()),
+    }
+}
+
+fn triangle_mat(materials: Option<&[u8]>, index: usize) -> u8 {
+    materials.map_or(DEFAULT_VOX_MAT, |m| m[index])
+}
+
+// surface voxelization into a linear grid, the triangles are fitted into the grid
+pub fn voxelize_triangles(triangles: &[[Vec3; 3]], materials: Option<&[u8]>, grid_res: u32, mode: VoxelizeMode) -> Result<Vec<u8>, String> {
+    voxelize_triangles_with(triangles, materials, grid_res, GridTransform::fit(triangles, grid_res), mode)
+}
+
+pub fn voxelize_triangles_with(
+    triangles: &[[Vec3; 3]],
+    materials: Option<&[u8]>,
+    grid_res: u32,
+    transform: GridTransform,
+    mode: VoxelizeMode,
+) -> Result<Vec<u8>, String> {
+    check_materials(triangles, materials)?;
+
+    let mut grid = vec![0u8; grid_size(grid_res)?];
+
+    for (i, tri) in triangles.iter().enumerate() {
+        let mat = triangle_mat(materials, i);
+        let tri = tri.map(|p| transform.to_grid(p));
+
+        rasterize_triangle(&tri, grid_res, mode, |pos| {
+            grid[pos_to_index(pos.x, pos.y, pos.z, grid_res) as usize] = mat;
+        });
+    }
+
+    Ok(grid)
+}
+
+// surface voxelization straight into an svo without a dense grid, grid_res has to be a power of two
+pub fn voxelize_triangles_svo(triangles: &[[Vec3; 3]], materials: Option<&[u8]>, grid_res: u32, mode: VoxelizeMode) -> Result<SVO, String> {
+    voxelize_triangles_svo_with(triangles, materials, grid_res, GridTransform::fit(triangles, grid_res), mode)
+}
+
+pub fn voxelize_triangles_svo_with(
+    triangles: &[[Vec3; 3]],
+    materials: Option<&[u8]>,
+    grid_res: u32,
+    transform: GridTransform,
+    mode: VoxelizeMode,
+) -> Result<SVO, String> {
+    if !grid_res.is_power_of_two() {
+        return Err("grid resolution has to be a power of two.".to_string());
+    }
+    check_materials(triangles, materials)?;
+
+    let mut svo = SVO::new(grid_res.trailing_zeros() as u8);
+    let mut result = Ok(());
+
+    for (i, tri) in triangles.iter().enumerate() {
+        let mat = triangle_mat(materials, i) as u32;
+        let tri = tri.map(|p| transform.to_grid(p));
+
+        rasterize_triangle(&tri, grid_res, mode, |pos| {
+            if result.is_ok() { result = svo.insert_voxel(pos, mat); }
+        });
+    }
+
+    result.map(|_| svo)
+}
+
+// every edge has to be shared by exactly two triangles, vertices are matched by exact position
+pub fn check_watertight(triangles: &[[Vec3; 3]]) -> Result<(), String> {
+    let key = |p: Vec3| (p + Vec3::ZERO).to_array().map(f32::to_bits);
+    let mut edges: HashMap<_, u32> = HashMap::new();
+
+    for tri in triangles {
+        for (a, b) in [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])] {
+            let (a, b) = (key(a), key(b));
+            *edges.entry(if a < b { (a, b) } else { (b, a) }).or_insert(0) += 1;
+        }
+    }
+
+    let open_edges = edges.values().filter(|&&count| count != 2).count();
+    if open_edges > 0 {
+        return Err(format!("mesh is not watertight, {} edges are not shared by exactly two triangles.", open_edges));
+    }
+
+    Ok(())
+}
+
+// z values where the ray through every voxel column crosses the mesh, triangles in grid space
+fn column_crossings(triangles: &[[Vec3; 3]], grid_res: u32) -> Vec<Vec<f32>> {
+    let mut columns = vec![Vec::new(); (grid_res * grid_res) as usize];
+    let limit = Vec2::splat(grid_res as f32 - 1.0);
+
+    for tri in triangles {
+        let [a, b, c] = tri.map(|p| p.truncate());
+        let area = (b - a).perp_dot(c - a);
+        if area == 0.0 {
+            continue;
+        }
+
+        let min = a.min(b).min(c).floor().clamp(Vec2::ZERO, limit).as_uvec2();
+        let max = a.max(b).max(c).floor().clamp(Vec2::ZERO, limit).as_uvec2();
+
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let p = Vec2::new(x as f32, y as f32) + Vec2::splat(0.5) + RAY_JITTER;
+
+                let w0 = (c - b).perp_dot(p - b) / area;
+                let w1 = (a - c).perp_dot(p - c) / area;
+                let w2 = 1.0 - w0 - w1;
+
+                if w0 >= 0.0 && w1 >= 0.0 && w2 >= 0.0 {
+                    columns[(x + y * grid_res) as usize].push(w0 * tri[0].z + w1 * tri[1].z + w2 * tri[2].z);
+                }
+            }
+        }
+    }
+
+    columns
+}
+
+// calls f with every voxel whose center lies inside the mesh by the even odd rule
+fn parity_interior<F: FnMut(UVec3)>(triangles: &[[Vec3; 3]], grid_res: u32, mut f: F) -> Result<(), String> {
+    let mut columns = column_crossings(triangles, grid_res);
+
+    for (i, crossings) in columns.iter_mut().enumerate() {
+        if !crossings.len().is_multiple_of(2) {
+            return Err("mesh is not watertight, odd number of ray crossings.".to_string());
+        }
+
+        crossings.sort_by(f32::total_cmp);
+
+        let (x, y) = (i as u32 % grid_res, i as u32 / grid_res);
+        for span in crossings.chunks_exact(2) {
+            let first = (span[0] - 0.5).ceil().max(0.0) as u32;
+            let last = ((span[1] - 0.5).floor() as i64).min(grid_res as i64 - 1);
+
+            for z in first as i64..=last {
+                f(UVec3::new(x, y, z as u32));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// marks every empty voxel that can't be reached from the grid border through empty voxels
+pub fn flood_fill_interior(grid: &mut [u8], grid_res: u32, fill_mat: u8) {
+    let mut outside = vec![false; grid.len()];
+    let mut queue = VecDeque::new();
+
+    for i in 0..grid.len() as u32 {
+        let pos = index_to_pos(i, grid_res);
+        let border = pos.min_element() == 0 || pos.max_element() == grid_res - 1;
+
+        if border && grid[i as usize] == 0 {
+            outside[i as usize] = true;
+            queue.push_back(pos);
+        }
+    }
+
+    while let Some(pos) = queue.pop_front() {
+        for (axis, dir) in [(0, -1), (0, 1), (1, -1), (1, 1), (2, -1), (2, 1)] {
+            let mut next = pos.as_ivec3();
+            next[axis] += dir;
+
+            if next.min_element() < 0 || next.max_element() >= grid_res as i32 {
+                continue;
+            }
+
+            let next = next.as_uvec3();
+            let index = pos_to_index(next.x, next.y, next.z, grid_res) as usize;
+
+            if !outside[index] && grid[index] == 0 {
+                outside[index] = true;
+                queue.push_back(next);
+            }
+        }
+    }
+
+    for (i, voxel) in grid.iter_mut().enumerate() {
+        if *voxel == 0 && !outside[i] {
+            *voxel = fill_mat;
+        }
+    }
+}
+
+// marks every empty voxel whose center is inside the watertight mesh
+pub fn parity_fill_interior(
+    grid: &mut [u8],
+    grid_res: u32,
+    triangles: &[[Vec3; 3]],
+    transform: GridTransform,
+    fill_mat: u8,
+) -> Result<(), String> {
+    if grid_size(grid_res)? != grid.len() {
+        return Err("grid is not the given size.".to_string());
+    }
+    check_watertight(triangles)?;
+
+    let triangles: Vec<[Vec3; 3]> = triangles.iter().map(|tri| tri.map(|p| transform.to_grid(p))).collect();
+
+    parity_interior(&triangles, grid_res, |pos| {
+        let index = pos_to_index(pos.x, pos.y, pos.z, grid_res) as usize;
+        if grid[index] == 0 { grid[index] = fill_mat; }
+    })
+}
+
+// surface voxelization followed by filling the interior, fails if the mesh is not watertight
+pub fn voxelize_solid_with(
+    triangles: &[[Vec3; 3]],
+    materials: Option<&[u8]>,
+    grid_res: u32,
+    transform: GridTransform,
+    fill_mode: FillMode,
+    fill_mat: u8,
+) -> Result<Vec<u8>, String> {
+    check_watertight(triangles)?;
+
+    let mut grid = voxelize_triangles_with(triangles, materials, grid_res, transform, VoxelizeMode::Conservative)?;
+
+    match fill_mode {
+        FillMode::Parity => parity_fill_interior(&mut grid, grid_res, triangles, transform, fill_mat)?,
+        FillMode::FloodFill => flood_fill_interior(&mut grid, grid_res, fill_mat),
+    }
+
+    Ok(grid)
+}
+
+// fills the interior of the mesh into an svo, only voxels that are still empty are inserted
+pub fn fill_interior_svo(svo: &mut SVO, triangles: &[[Vec3; 3]], transform: GridTransform, fill_mat: u32) -> Result<(), String> {
+    check_watertight(triangles)?;
+
+    let grid_res = 1u32 << svo.depth;
+    let triangles: Vec<[Vec3; 3]> = triangles.iter().map(|tri| tri.map(|p| transform.to_grid(p))).collect();
+
+    let mut interior = Vec::new();
+    parity_interior(&triangles, grid_res, |pos| interior.push(pos))?;
+
+    for pos in interior {
+        if svo.get_voxel(pos) == 0 {
+            svo.insert_voxel(pos, fill_mat)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn voxelize_solid_svo_with(
+    triangles: &[[Vec3; 3]],
+    materials: Option<&[u8]>,
+    grid_res: u32,
+    transform: GridTransform,
+    fill_mat: u32,
+) -> Result<SVO, String> {
+    check_watertight(triangles)?;
+
+    let mut svo = voxelize_triangles_svo_with(triangles, materials, grid_res, transform, VoxelizeMode::Conservative)?;
+    fill_interior_svo(&mut svo, triangles, transform, fill_mat)?;
+
+    Ok(svo)
+}
@@ -0,0 +1,394 @@
+use crate::atomic::sync_parent;
+use crate::bsvo::{read_bsvo, write_bsvo, BsvoHeader};
+use crate::bvox::{read_bvox, write_bvox, BvoxHeader};
+use crate::codec::Codec;
+use crate::svo::SVO;
+use crate::vox::pos_to_index;
+use glam::{IVec3, UVec3, Vec3};
+use std::collections::{HashMap, HashSet};
+use std::{fs, io, path::{Path, PathBuf}};
+
+// a chunk is either a linear grid or an svo with a depth of log2(chunk_res)
+#[derive(Clone, Debug)]
+pub enum WorldChunk {
+    Dense(Vec<u8>),
+    Svo(SVO),
+}
+
+impl WorldChunk {
+    pub fn get(&self, local: UVec3, chunk_res: u32) -> u8 {
+        match self {
+            WorldChunk::Dense(grid) => grid[pos_to_index(local.x, local.y, local.z, chunk_res) as usize],
+            WorldChunk::Svo(svo) => svo.get_voxel(local).min(u8::MAX as u32) as u8,
+        }
+    }
+
+    // stores hand out chunks read from files, which have to cover exactly one chunk of the world resolution
+    pub fn check_res(&self, chunk_res: u32) -> io::Result<()> {
+        let valid = match self {
+            WorldChunk::Dense(grid) => grid.len() as u64 == (chunk_res as u64).pow(3),
+            WorldChunk::Svo(svo) => chunk_res.is_power_of_two() && svo.depth as u32 == chunk_res.trailing_zeros(),
+        };
+
+        if !valid {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "chunk does not match the world chunk resolution."));
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            WorldChunk::Dense(grid) => grid.iter().all(|&v| v == 0),
+            WorldChunk::Svo(svo) => svo.count_leaf_nodes() == 0,
+        }
+    }
+
+    pub fn to_dense(&self, chunk_res: u32) -> Vec<u8> {
+        match self {
+            WorldChunk::Dense(grid) => grid.clone(),
+            WorldChunk::Svo(svo) => {
+                let mut grid = vec![0u8; (chunk_res * chunk_res * chunk_res) as usize];
+                svo.for_each_leaf(|pos, mat| grid[pos_to_index(pos.x, pos.y, pos.z, chunk_res) as usize] = mat.min(u8::MAX as u32) as u8);
+                grid
+            }
+        }
+    }
+
+    pub fn to_svo(&self, chunk_res: u32) -> io::Result<SVO> {
+        match self {
+            WorldChunk::Dense(grid) => SVO::from_chunks(&[(IVec3::ZERO, grid.clone())], chunk_res, false)
+                .map(|(svo, _)| svo)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e)),
+            WorldChunk::Svo(svo) => Ok(svo.clone()),
+        }
+    }
+}
+
+// where a world pages its chunks in and out, chunks that were never saved load as none
+pub trait ChunkStore {
+    fn load(&mut self, coord: IVec3) -> io::Result<Option<WorldChunk>>;
+    fn save(&mut self, coord: IVec3, chunk: &WorldChunk) -> io::Result<()>;
+    fn remove(&mut self, coord: IVec3) -> io::Result<()>;
+}
+
+// one file per chunk named by its coordinate, dense chunks as bvox and svo chunks as bsvo
+pub struct DirectoryStore {
+    pub dir: PathBuf,
+    pub chunk_res: u32,
+}
+
+impl DirectoryStore {
+    pub fn new(dir: &str, chunk_res: u32) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self { dir: PathBuf::from(dir), chunk_res })
+    }
+
+    fn path(&self, coord: IVec3, extension: &str) -> String {
+        self.dir.join(format!("{}_{}_{}.{}", coord.x, coord.y, coord.z, extension)).to_string_lossy().into_owned()
+    }
+
+    fn remove_file(&self, coord: IVec3, extension: &str) -> io::Result<()> {
+        let path = self.path(coord, extension);
+        if fs::exists(&path)? {
+            fs::remove_file(&path)?;
+            sync_parent(Path::new(&path))?;
+        }
+        Ok(())
+    }
+}
+
+impl ChunkStore for DirectoryStore {
+    fn load(&mut self, coord: IVec3) -> io::Result<Option<WorldChunk>> {
+        let bsvo = self.path(coord, "bsvo");
+        if fs::exists(&bsvo)? {
+            let chunk = WorldChunk::Svo(read_bsvo(&bsvo)?.1);
+            chunk.check_res(self.chunk_res)?;
+            return Ok(Some(chunk));
+        }
+
+        let bvox = self.path(coord, "bvox");
+        if fs::exists(&bvox)? {
+            let (header, chunks) = read_bvox(&bvox)?;
+            if header.chunk_res != self.chunk_res || header.morton_encoded || chunks.len() != 1 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "world chunk file does not hold one linear chunk of the world resolution."));
+            }
+            return Ok(chunks.into_iter().next().map(WorldChunk::Dense));
+        }
+
+        Ok(None)
+    }
+
+    fn save(&mut self, coord: IVec3, chunk: &WorldChunk) -> io::Result<()> {
+        let stale = match chunk {
+            WorldChunk::Dense(grid) => {
+                let size = self.chunk_res * self.chunk_res * self.chunk_res;
+                let header = BvoxHeader::new(self.chunk_res, size, false, false).with_codec(Codec::Auto);
+                write_bvox(&self.path(coord, "bvox"), std::slice::from_ref(grid), header)?;
+                "bsvo"
+            }
+            WorldChunk::Svo(svo) => {
+                write_bsvo(&self.path(coord, "bsvo"), svo, BsvoHeader::new(svo.depth, svo.root_span, true))?;
+                "bvox"
+            }
+        };
+
+        // the other representation may still be on disk from an earlier save, it is only
+        // removed once the new file is in place so a crash never loses the chunk
+        self.remove_file(coord, stale)
+    }
+
+    fn remove(&mut self, coord: IVec3) -> io::Result<()> {
+        self.remove_file(coord, "bsvo")?;
+        self.remove_file(coord, "bvox")
+    }
+}
+
+// longest ray raycast traces, in voxels
+pub const MAX_RAY_DISTANCE: f32 = 65536.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RayHit {
+    pub pos: IVec3,
+    pub mat: u8,
+    pub distance: f32,
+    // face of the voxel the ray entered through, zero if the ray started inside it
+    pub normal: IVec3,
+}
+
+// chunks are saved as bvox, whose u32 chunk size holds at most 1024^3 voxels
+pub const MAX_WORLD_CHUNK_RES: u32 = 1024;
+
+// unbounded world of chunks keyed by chunk coordinate, positions are in world voxels.
+// with a store chunks are loaded on first access and dirty chunks are written back on save or unload
+pub struct VoxelWorld {
+    pub chunk_res: u32,
+    chunks: HashMap<IVec3, WorldChunk>,
+    dirty: HashSet<IVec3>,
+    // chunks the store doesn't have, so they aren't looked up again
+    absent: HashSet<IVec3>,
+    store: Option<Box<dyn ChunkStore>>,
+}
+
+impl VoxelWorld {
+    pub fn new(chunk_res: u32) -> io::Result<Self> {
+        if !chunk_res.is_power_of_two() || chunk_res > MAX_WORLD_CHUNK_RES {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk resolution must be a power of two up to 1024."));
+        }
+
+        Ok(Self {
+            chunk_res,
+            chunks: HashMap::new(),
+            dirty: HashSet::new(),
+            absent: HashSet::new(),
+            store: None,
+        })
+    }
+
+    pub fn with_store(chunk_res: u32, store: Box<dyn ChunkStore>) -> io::Result<Self> {
+        let mut world = Self::new(chunk_res)?;
+        world.store = Some(store);
+        Ok(world)
+    }
+
+    // chunk coordinate and position inside the chunk of a world voxel
+    pub fn chunk_coord(&self, pos: IVec3) -> (IVec3, UVec3) {
+        let res = IVec3::splat(self.chunk_res as i32);
+        (pos.div_euclid(res), pos.rem_euclid(res).as_uvec3())
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        (self.chunk_res * self.chunk_res * self.chunk_res) as usize
+    }
+
+    pub fn is_loaded(&self, coord: IVec3) -> bool {
+        self.chunks.contains_key(&coord)
+    }
+
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = &IVec3> {
+        self.chunks.keys()
+    }
+
+    pub fn dirty_chunks(&self) -> impl Iterator<Item = &IVec3> {
+        self.dirty.iter()
+    }
+
+    pub fn is_dirty(&self, coord: IVec3) -> bool {
+        self.dirty.contains(&coord)
+    }
+
+    // loads the chunk from the store if it isn't loaded yet, none if it doesn't exist anywhere
+    pub fn chunk(&mut self, coord: IVec3) -> io::Result<Option<&WorldChunk>> {
+        self.load_chunk(coord)?;
+        Ok(self.chunks.get(&coord))
+    }
+
+    fn load_chunk(&mut self, coord: IVec3) -> io::Result<()> {
+        if self.chunks.contains_key(&coord) || self.absent.contains(&coord) {
+            return Ok(());
+        }
+
+        let chunk = match self.store.as_mut() {
+            Some(store) => store.load(coord)?,
+            None => None,
+        };
+
+        if let Some(chunk) = chunk {
+            self.chunks.insert(coord, chunk);
+        } else {
+            self.absent.insert(coord);
+        }
+
+        Ok(())
+    }
+
+    pub fn insert_chunk(&mut self, coord: IVec3, chunk: WorldChunk) -> io::Result<()> {
+        chunk.check_res(self.chunk_res)?;
+
+        self.absent.remove(&coord);
+        self.chunks.insert(coord, chunk);
+        self.dirty.insert(coord);
+        Ok(())
+    }
+
+    pub fn get(&mut self, pos: IVec3) -> io::Result<u8> {
+        let (coord, local) = self.chunk_coord(pos);
+        let chunk_res = self.chunk_res;
+        Ok(self.chunk(coord)?.map_or(0, |chunk| chunk.get(local, chunk_res)))
+    }
+
+    // svo chunks turn dense when a voxel is cleared, svos can't remove leaves
+    pub fn set(&mut self, pos: IVec3, mat: u8) -> io::Result<()> {
+        let (coord, local) = self.chunk_coord(pos);
+        self.load_chunk(coord)?;
+
+        if mat == 0 && !self.chunks.contains_key(&coord) {
+            return Ok(());
+        }
+
+        let chunk_res = self.chunk_res;
+        let size = self.chunk_size();
+        let chunk = self.chunks.entry(coord).or_insert_with(|| WorldChunk::Dense(vec![0; size]));
+
+        match chunk {
+            WorldChunk::Svo(svo) if mat != 0 => {
+                svo.insert_voxel(local, mat as u32).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            }
+            WorldChunk::Svo(_) => {
+                let mut grid = chunk.to_dense(chunk_res);
+                grid[pos_to_index(local.x, local.y, local.z, chunk_res) as usize] = 0;
+                *chunk = WorldChunk::Dense(grid);
+            }
+            WorldChunk::Dense(grid) => grid[pos_to_index(local.x, local.y, local.z, chunk_res) as usize] = mat,
+        }
+
+        self.absent.remove(&coord);
+        self.dirty.insert(coord);
+        Ok(())
+    }
+
+    // turns loaded dense chunks into svos where that takes less memory, the stored chunks stay untouched
+    pub fn compact(&mut self) -> io::Result<()> {
+        let chunk_res = self.chunk_res;
+        let size = self.chunk_size();
+
+        for chunk in self.chunks.values_mut() {
+            if let WorldChunk::Dense(_) = chunk {
+                let svo = chunk.to_svo(chunk_res)?;
+                if svo.nodes.len() * size_of::<u32>() < size {
+                    *chunk = WorldChunk::Svo(svo);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // writes every dirty chunk to the store, empty chunks are removed from it. returns the number of chunks written
+    pub fn save(&mut self) -> io::Result<usize> {
+        let mut dirty: Vec<IVec3> = self.dirty.iter().copied().collect();
+        dirty.sort_by_key(|coord| (coord.z, coord.y, coord.x));
+
+        for &coord in &dirty {
+            self.save_chunk(coord)?;
+        }
+
+        Ok(dirty.len())
+    }
+
+    fn save_chunk(&mut self, coord: IVec3) -> io::Result<()> {
+        let Some(store) = self.store.as_mut() else {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "world has no chunk store."));
+        };
+
+        match self.chunks.get(&coord) {
+            Some(chunk) if !chunk.is_empty() => store.save(coord, chunk)?,
+            _ => store.remove(coord)?,
+        }
+
+        self.dirty.remove(&coord);
+        Ok(())
+    }
+
+    // drops a chunk from memory, dirty chunks are saved first when there is a store
+    pub fn unload(&mut self, coord: IVec3) -> io::Result<()> {
+        if self.store.is_some() && self.dirty.contains(&coord) {
+            self.save_chunk(coord)?;
+        }
+
+        self.dirty.remove(&coord);
+        self.chunks.remove(&coord);
+        Ok(())
+    }
+
+    // unloads every chunk further than radius chunks from center on any axis
+    pub fn unload_outside(&mut self, center: IVec3, radius: i32) -> io::Result<()> {
+        let far: Vec<IVec3> = self.chunks.keys().filter(|&&coord| (coord - center).abs().max_element() > radius).copied().collect();
+
+        for coord in far {
+            self.unload(coord)?;
+        }
+
+        self.absent.retain(|&coord| (coord - center).abs().max_element() <= radius);
+        Ok(())
+    }
+
+    // steps through the voxels along the ray (amanatides and woo), origin and max_distance are in world voxels
+    pub fn raycast(&mut self, origin: Vec3, dir: Vec3, max_distance: f32) -> io::Result<Option<RayHit>> {
+        // every voxel along the ray is visited, so the distance has to be bounded
+        if !max_distance.is_finite() || max_distance > MAX_RAY_DISTANCE || !origin.is_finite() || !dir.is_finite() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "ray has to be finite and no longer than the max ray distance."));
+        }
+
+        if dir == Vec3::ZERO {
+            return Ok(None);
+        }
+
+        let dir = dir.normalize();
+        let mut pos = origin.floor().as_ivec3();
+        let step = dir.signum().as_ivec3();
+
+        // distance along the ray to the next voxel boundary per axis and between boundaries
+        let next_boundary = |o: f32, d: f32, p: i32| if d > 0.0 { (p as f32 + 1.0 - o) / d } else if d < 0.0 { (p as f32 - o) / d } else { f32::INFINITY };
+        let mut t_max = Vec3::new(next_boundary(origin.x, dir.x, pos.x), next_boundary(origin.y, dir.y, pos.y), next_boundary(origin.z, dir.z, pos.z));
+        let t_delta = Vec3::new(1.0 / dir.x.abs(), 1.0 / dir.y.abs(), 1.0 / dir.z.abs());
+
+        let mut distance = 0.0;
+        let mut normal = IVec3::ZERO;
+
+        while distance <= max_distance {
+            let mat = self.get(pos)?;
+            if mat != 0 {
+                return Ok(Some(RayHit { pos, mat, distance, normal }));
+            }
+
+            let axis = if t_max.x < t_max.y && t_max.x < t_max.z { 0 } else if t_max.y < t_max.z { 1 } else { 2 };
+            distance = t_max[axis];
+            t_max[axis] += t_delta[axis];
+            pos[axis] += step[axis];
+            normal = IVec3::ZERO;
+            normal[axis] = -step[axis];
+        }
+
+        Ok(None)
+    }
+}